@@ -0,0 +1,202 @@
+//! A JSON representation of a [`NodeTree`] for tooling.
+//!
+//! The output is a single object:
+//!
+//! ```text
+//! { "schema_version": 1, "root": <node> }
+//! ```
+//!
+//! Every node has a `"kind"` and a `"span"`, plus fields depending on the kind:
+//!
+//! | kind                       | fields                                                    |
+//! |----------------------------|-----------------------------------------------------------|
+//! | `"document"`               | `"encoding"`, `"quirks_mode"`, `"children"`               |
+//! | `"document_fragment"`      | `"children"`                                              |
+//! | `"element"`                | `"name"`, `"namespace"`, `"prefix"`, `"attributes"`, `"children"`, and `"template_contents"` for `<template>` |
+//! | `"text"`, `"comment"`      | `"text"`                                                  |
+//! | `"doctype"`                | `"name"`, `"public_id"`, `"system_id"`                    |
+//! | `"processing_instruction"` | `"target"`, `"data"`                                      |
+//!
+//! Attributes are an array of `{ "name", "namespace", "prefix", "value" }` objects in the
+//! order they are written in the source. Those that are not in it, such as ones added after
+//! parsing, come last, in the tree's order (by namespace, then local name), so the same tree
+//! always gives the same JSON. `"prefix"` is `null` when there is none.
+//!
+//! `"span"` is `null` for nodes that did not come from the source (like an implied `<body>`)
+//! or when the tree was parsed without keeping its source. Otherwise it is
+//! `{ "start", "end", "start_line", "start_column", "end_line", "end_column" }`, with byte
+//! offsets into the decoded source and 1-based lines and columns (in chars). An element's
+//! span covers it up to and including its end tag, and it also has a `"start_tag_span"`.
+//!
+//! Fields are only ever added to a schema version; anything else bumps [`SCHEMA_VERSION`].
+
+use html5ever::tree_builder::QuirksMode;
+use std::fmt::Write;
+
+use crate::span::{self, Span};
+use crate::tree::*;
+
+/// The version of the JSON AST format written by [`NodeTree::to_json_ast`].
+pub const SCHEMA_VERSION: u32 = 1;
+
+impl NodeTree {
+    /// Dump the tree as JSON, following the format described in the [`ast`](crate::ast)
+    /// module.
+    pub fn to_json_ast(&self) -> String {
+        let mut json = String::new();
+        json.push_str("{\"schema_version\":");
+        json.push_str(&SCHEMA_VERSION.to_string());
+        json.push_str(",\"root\":");
+        self.write_json_node(&mut json, self.root());
+        json.push('}');
+        json
+    }
+
+    fn write_json_node(&self, json: &mut String, node_ref: NodeRef) {
+        let node = self.get_node(node_ref);
+        json.push_str("{\"kind\":");
+        let kind = match node.data() {
+            NodeData::Document(_) => "document",
+            NodeData::DocumentFragment => "document_fragment",
+            NodeData::Element(_) => "element",
+            NodeData::Text(_) => "text",
+            NodeData::Comment(_) => "comment",
+            NodeData::Doctype(_) => "doctype",
+            NodeData::ProcessingInstruction(_) => "processing_instruction",
+        };
+        write_json_string(json, kind);
+        json.push_str(",\"span\":");
+        self.write_json_span(json, node, node.span());
+
+        match node.data() {
+            NodeData::Document(document) => {
+                json.push_str(",\"encoding\":");
+                write_json_string(json, document.encoding.name());
+                json.push_str(",\"quirks_mode\":");
+                let quirks_mode = match document.quirks_mode() {
+                    QuirksMode::Quirks => "quirks",
+                    QuirksMode::LimitedQuirks => "limited_quirks",
+                    QuirksMode::NoQuirks => "no_quirks",
+                };
+                write_json_string(json, quirks_mode);
+                self.write_json_children(json, "children", node_ref);
+            }
+            NodeData::DocumentFragment => self.write_json_children(json, "children", node_ref),
+            NodeData::Element(element) => {
+                json.push_str(",\"start_tag_span\":");
+                self.write_json_span(json, node, node.start_tag_span());
+                json.push_str(",\"name\":");
+                write_json_string(json, &element.name.local);
+                json.push_str(",\"namespace\":");
+                write_json_string(json, &element.name.ns);
+                json.push_str(",\"prefix\":");
+                write_json_option(json, element.name.prefix.as_deref());
+                json.push_str(",\"attributes\":[");
+                let mut attributes: Vec<_> = element.attributes.map.iter().collect();
+                if let (Some(source), Some(tag_span)) = (node.source(), node.start_tag_span()) {
+                    let written = span::attribute_spans(&source.text[tag_span.range()], 0);
+                    attributes.sort_by_key(|(name, attribute)| {
+                        let qualified = match &attribute.prefix {
+                            Some(prefix) => format!("{}:{}", prefix, name.local),
+                            None => name.local.to_string(),
+                        };
+                        written
+                            .iter()
+                            .position(|(written, _)| written.eq_ignore_ascii_case(&qualified))
+                            .unwrap_or(written.len())
+                    });
+                }
+                for (i, (name, attribute)) in attributes.into_iter().enumerate() {
+                    if i > 0 {
+                        json.push(',');
+                    }
+                    json.push_str("{\"name\":");
+                    write_json_string(json, &name.local);
+                    json.push_str(",\"namespace\":");
+                    write_json_string(json, &name.ns);
+                    json.push_str(",\"prefix\":");
+                    write_json_option(json, attribute.prefix.as_deref());
+                    json.push_str(",\"value\":");
+                    write_json_string(json, &attribute.value);
+                    json.push('}');
+                }
+                json.push(']');
+                self.write_json_children(json, "children", node_ref);
+                if let Some(template_contents) = element.template_contents {
+                    self.write_json_children(json, "template_contents", template_contents);
+                }
+            }
+            NodeData::Text(text) | NodeData::Comment(text) => {
+                json.push_str(",\"text\":");
+                write_json_string(json, text);
+            }
+            NodeData::Doctype(doctype) => {
+                json.push_str(",\"name\":");
+                write_json_string(json, &doctype.name);
+                json.push_str(",\"public_id\":");
+                write_json_string(json, &doctype.public_id);
+                json.push_str(",\"system_id\":");
+                write_json_string(json, &doctype.system_id);
+            }
+            NodeData::ProcessingInstruction((target, data)) => {
+                json.push_str(",\"target\":");
+                write_json_string(json, target);
+                json.push_str(",\"data\":");
+                write_json_string(json, data);
+            }
+        }
+        json.push('}');
+    }
+
+    fn write_json_children(&self, json: &mut String, key: &str, parent_ref: NodeRef) {
+        json.push(',');
+        write_json_string(json, key);
+        json.push_str(":[");
+        for (i, child_ref) in self.children(parent_ref).enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            self.write_json_node(json, child_ref);
+        }
+        json.push(']');
+    }
+
+    fn write_json_span(&self, json: &mut String, node: &Node, span: Option<Span>) {
+        match (span, node.source()) {
+            (Some(span), Some(source)) => {
+                let start = source.location(span.start);
+                let end = source.location(span.end);
+                write!(
+                    json,
+                    "{{\"start\":{},\"end\":{},\"start_line\":{},\"start_column\":{},\"end_line\":{},\"end_column\":{}}}",
+                    span.start, span.end, start.line, start.column, end.line, end.column
+                )
+                .unwrap();
+            }
+            _ => json.push_str("null"),
+        }
+    }
+}
+
+fn write_json_option(json: &mut String, value: Option<&str>) {
+    match value {
+        Some(value) => write_json_string(json, value),
+        None => json.push_str("null"),
+    }
+}
+
+pub(crate) fn write_json_string(json: &mut String, value: &str) {
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
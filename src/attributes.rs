@@ -2,7 +2,7 @@ use html5ever::{namespace_url, ns, LocalName, Namespace, Prefix};
 use std::collections::btree_map::{BTreeMap, Entry};
 
 /// Convenience wrapper around a btreemap that adds method for attributes in the null namespace.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct Attributes {
     /// A map of attributes whose name can have namespaces.
     pub map: BTreeMap<ExpandedName, Attribute>,
@@ -57,7 +57,10 @@ impl Attributes {
     }
 
     /// Like BTreeMap::entry
-    pub fn entry<A: Into<LocalName>>(&mut self, local_name: A) -> Entry<ExpandedName, Attribute> {
+    pub fn entry<A: Into<LocalName>>(
+        &mut self,
+        local_name: A,
+    ) -> Entry<'_, ExpandedName, Attribute> {
         self.map.entry(ExpandedName::new(ns!(), local_name))
     }
 
@@ -80,4 +83,46 @@ impl Attributes {
     pub fn remove<A: Into<LocalName>>(&mut self, local_name: A) -> Option<Attribute> {
         self.map.remove(&ExpandedName::new(ns!(), local_name))
     }
+
+    /// Like BTreeMap::get, for an attribute written as `qualified_name`, see
+    /// [`Attributes::expanded_name`].
+    pub fn get_qualified(&self, qualified_name: &str) -> Option<&str> {
+        let (name, _) = Attributes::expanded_name(qualified_name);
+        self.map.get(&name).map(|attr| &*attr.value)
+    }
+
+    /// Like BTreeMap::insert, for an attribute written as `qualified_name`, see
+    /// [`Attributes::expanded_name`].
+    pub fn insert_qualified(&mut self, qualified_name: &str, value: String) -> Option<Attribute> {
+        let (name, prefix) = Attributes::expanded_name(qualified_name);
+        self.map.insert(name, Attribute { prefix, value })
+    }
+
+    /// Like BTreeMap::remove, for an attribute written as `qualified_name`, see
+    /// [`Attributes::expanded_name`].
+    pub fn remove_qualified(&mut self, qualified_name: &str) -> Option<Attribute> {
+        let (name, _) = Attributes::expanded_name(qualified_name);
+        self.map.remove(&name)
+    }
+
+    /// The expanded name and prefix of an attribute written as `qualified_name`.
+    ///
+    /// The `xlink:`, `xml:` and `xmlns:` prefixes (and `xmlns` itself) are in their namespaces,
+    /// as the HTML parser puts them on SVG and MathML elements; any other name is a local name
+    /// in the null namespace.
+    pub fn expanded_name(qualified_name: &str) -> (ExpandedName, Option<Prefix>) {
+        if qualified_name == "xmlns" {
+            return (ExpandedName::new(ns!(xmlns), "xmlns"), None);
+        }
+        let namespace = match qualified_name.split_once(':') {
+            Some(("xlink", local)) => Some((ns!(xlink), "xlink", local)),
+            Some(("xml", local)) => Some((ns!(xml), "xml", local)),
+            Some(("xmlns", local)) => Some((ns!(xmlns), "xmlns", local)),
+            _ => None,
+        };
+        match namespace {
+            Some((ns, prefix, local)) => (ExpandedName::new(ns, local), Some(Prefix::from(prefix))),
+            None => (ExpandedName::new(ns!(), qualified_name), None),
+        }
+    }
 }
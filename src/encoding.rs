@@ -0,0 +1,290 @@
+//! Character encoding detection and re-encoding for byte-stream input and output.
+//!
+//! Detection follows the HTML "encoding sniffing algorithm":
+//! <https://html.spec.whatwg.org/multipage/parsing.html#encoding-sniffing-algorithm>
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252, X_USER_DEFINED};
+
+/// How many bytes of the input the `<meta>` prescan is allowed to look at.
+const PRESCAN_LIMIT: usize = 1024;
+
+/// How sure the sniffer is about an encoding.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Confidence {
+    /// The encoding came from a byte order mark and can not be overridden.
+    Certain,
+    /// The encoding was found in a `<meta>` element, guessed, or is the fallback.
+    Tentative,
+}
+
+/// Determines the encoding of an HTML byte stream.
+///
+/// Checks for a BOM, then prescans the first 1024 bytes for a `<meta charset>` or
+/// `<meta http-equiv="content-type">` declaration. If neither is found, input that is valid
+/// UTF-8 is assumed to be UTF-8, and everything else falls back to `fallback`.
+///
+/// Returns the encoding and how sure we are about it.
+pub fn sniff(bytes: &[u8], fallback: &'static Encoding) -> (&'static Encoding, Confidence) {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return (encoding, Confidence::Certain);
+    }
+    if let Some(encoding) = prescan(&bytes[..bytes.len().min(PRESCAN_LIMIT)]) {
+        return (encoding, Confidence::Tentative);
+    }
+    if std::str::from_utf8(bytes).is_ok() {
+        return (UTF_8, Confidence::Tentative);
+    }
+    (fallback, Confidence::Tentative)
+}
+
+/// Decodes `bytes` with the sniffed encoding, stripping any BOM.
+///
+/// Malformed sequences are replaced with U+FFFD.
+pub fn decode(bytes: &[u8], fallback: &'static Encoding) -> (String, &'static Encoding) {
+    let (encoding, _) = sniff(bytes, fallback);
+    // `Encoding::decode` sniffs the BOM again and lets it win, which is what we want anyway.
+    let (text, encoding, _) = encoding.decode(bytes);
+    (text.into_owned(), encoding)
+}
+
+/// Determines the encoding of an XML byte stream.
+///
+/// Checks for a BOM, then for an `encoding` pseudo-attribute in the XML declaration, and
+/// otherwise assumes UTF-8 as XML requires. A declaration that could be read without a BOM is
+/// not in UTF-16, so a UTF-16 `encoding` is taken as UTF-8, like in [`sniff`].
+pub fn sniff_xml(bytes: &[u8]) -> (&'static Encoding, Confidence) {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return (encoding, Confidence::Certain);
+    }
+    if bytes.starts_with(b"<?xml") {
+        let end = find(bytes, b"?>").unwrap_or(bytes.len());
+        let mut position = b"<?xml".len();
+        while let Some((name, value)) = get_attribute(&bytes[..end], &mut position) {
+            if name == b"encoding" {
+                match Encoding::for_label(&value) {
+                    Some(encoding) if encoding == UTF_16BE || encoding == UTF_16LE => {
+                        return (UTF_8, Confidence::Tentative)
+                    }
+                    Some(encoding) => return (encoding, Confidence::Tentative),
+                    None => {}
+                }
+            }
+        }
+    }
+    (UTF_8, Confidence::Tentative)
+}
+
+/// Decodes XML `bytes` with the sniffed encoding, stripping any BOM.
+pub fn decode_xml(bytes: &[u8]) -> (String, &'static Encoding) {
+    let (encoding, _) = sniff_xml(bytes);
+    let (text, encoding, _) = encoding.decode(bytes);
+    (text.into_owned(), encoding)
+}
+
+/// Encodes serialized HTML in `encoding`.
+///
+/// Characters that can not be represented in `encoding` are written as numeric character
+/// references, which is always valid in HTML text and attribute values. UTF-16 can not be
+/// produced by `encoding_rs`, so it is written as UTF-8 instead.
+pub fn encode(html: &str, encoding: &'static Encoding) -> Vec<u8> {
+    let (bytes, _, _) = encoding.output_encoding().encode(html);
+    bytes.into_owned()
+}
+
+/// The "prescan a byte stream to determine its encoding" algorithm.
+///
+/// <https://html.spec.whatwg.org/multipage/parsing.html#prescan-a-byte-stream-to-determine-its-encoding>
+fn prescan(bytes: &[u8]) -> Option<&'static Encoding> {
+    let mut position = 0;
+    while position < bytes.len() {
+        let rest = &bytes[position..];
+        if rest.starts_with(b"<!--") {
+            position += match find(&rest[2..], b"-->") {
+                Some(end) => 2 + end + 3,
+                None => return None,
+            };
+        } else if starts_with_ignore_case(rest, b"<meta")
+            && rest.get(5).is_some_and(|&b| is_space(b) || b == b'/')
+        {
+            position += 5;
+            if let Some(encoding) = prescan_meta(bytes, &mut position) {
+                return Some(encoding);
+            }
+        } else if rest.len() > 1
+            && (rest[1].is_ascii_alphabetic()
+                || (rest[1] == b'/' && rest.get(2).is_some_and(u8::is_ascii_alphabetic)))
+            && rest[0] == b'<'
+        {
+            position += 1;
+            while position < bytes.len() && !is_space(bytes[position]) && bytes[position] != b'>' {
+                position += 1;
+            }
+            while get_attribute(bytes, &mut position).is_some() {}
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            position += match rest.iter().position(|&b| b == b'>') {
+                Some(end) => end + 1,
+                None => return None,
+            };
+        } else {
+            position += 1;
+        }
+    }
+    None
+}
+
+/// Handles the attributes of a `<meta` tag, with `position` right after the tag name.
+fn prescan_meta(bytes: &[u8], position: &mut usize) -> Option<&'static Encoding> {
+    let mut attribute_names: Vec<Vec<u8>> = Vec::new();
+    let mut got_pragma = false;
+    let mut need_pragma = None;
+    let mut charset = None;
+
+    while let Some((name, value)) = get_attribute(bytes, position) {
+        if attribute_names.contains(&name) {
+            continue;
+        }
+        match &name[..] {
+            b"http-equiv" if value.eq_ignore_ascii_case(b"content-type") => got_pragma = true,
+            b"content" if charset.is_none() => {
+                if let Some(encoding) = charset_from_content(&value) {
+                    charset = Some(encoding);
+                    need_pragma = Some(true);
+                }
+            }
+            b"charset" if charset.is_none() => {
+                charset = Encoding::for_label(&value);
+                need_pragma = Some(false);
+            }
+            _ => {}
+        }
+        attribute_names.push(name);
+    }
+
+    match need_pragma {
+        None => return None,
+        Some(true) if !got_pragma => return None,
+        _ => {}
+    }
+    let charset = charset?;
+    if charset == UTF_16BE || charset == UTF_16LE {
+        return Some(UTF_8);
+    }
+    if charset == X_USER_DEFINED {
+        return Some(WINDOWS_1252);
+    }
+    Some(charset)
+}
+
+/// The "get an attribute" algorithm. Names are lowercased; values are lowercased too,
+/// since they are only ever compared against encoding labels.
+fn get_attribute(bytes: &[u8], position: &mut usize) -> Option<(Vec<u8>, Vec<u8>)> {
+    while *position < bytes.len() && (is_space(bytes[*position]) || bytes[*position] == b'/') {
+        *position += 1;
+    }
+    if *position >= bytes.len() || bytes[*position] == b'>' {
+        return None;
+    }
+
+    let mut name = Vec::new();
+    let mut value = Vec::new();
+    loop {
+        let byte = *bytes.get(*position)?;
+        if byte == b'=' && !name.is_empty() {
+            *position += 1;
+            break;
+        } else if is_space(byte) {
+            while bytes.get(*position).is_some_and(|&b| is_space(b)) {
+                *position += 1;
+            }
+            if bytes.get(*position) != Some(&b'=') {
+                return Some((name, value));
+            }
+            *position += 1;
+            break;
+        } else if byte == b'/' || byte == b'>' {
+            return Some((name, value));
+        } else {
+            name.push(byte.to_ascii_lowercase());
+            *position += 1;
+        }
+    }
+
+    while bytes.get(*position).is_some_and(|&b| is_space(b)) {
+        *position += 1;
+    }
+    match *bytes.get(*position)? {
+        quote @ b'"' | quote @ b'\'' => {
+            *position += 1;
+            loop {
+                let byte = *bytes.get(*position)?;
+                *position += 1;
+                if byte == quote {
+                    return Some((name, value));
+                }
+                value.push(byte.to_ascii_lowercase());
+            }
+        }
+        b'>' => Some((name, value)),
+        _ => {
+            while let Some(&byte) = bytes.get(*position) {
+                if is_space(byte) || byte == b'>' {
+                    break;
+                }
+                value.push(byte.to_ascii_lowercase());
+                *position += 1;
+            }
+            if *position >= bytes.len() {
+                None
+            } else {
+                Some((name, value))
+            }
+        }
+    }
+}
+
+/// The "extracting a character encoding from a meta element" algorithm.
+fn charset_from_content(content: &[u8]) -> Option<&'static Encoding> {
+    let mut position = 0;
+    loop {
+        position += find(&content[position..], b"charset")? + b"charset".len();
+        while content.get(position).is_some_and(|&b| is_space(b)) {
+            position += 1;
+        }
+        if content.get(position) == Some(&b'=') {
+            position += 1;
+            break;
+        }
+    }
+    while content.get(position).is_some_and(|&b| is_space(b)) {
+        position += 1;
+    }
+    let rest = &content[position..];
+    let label = match *rest.first()? {
+        quote @ b'"' | quote @ b'\'' => {
+            let end = rest[1..].iter().position(|&b| b == quote)?;
+            &rest[1..1 + end]
+        }
+        _ => {
+            let end = rest
+                .iter()
+                .position(|&b| is_space(b) || b == b';')
+                .unwrap_or(rest.len());
+            &rest[..end]
+        }
+    };
+    Encoding::for_label(label)
+}
+
+fn is_space(byte: u8) -> bool {
+    matches!(byte, b'\t' | b'\n' | b'\x0C' | b'\r' | b' ')
+}
+
+fn starts_with_ignore_case(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.len() >= needle.len() && haystack[..needle.len()].eq_ignore_ascii_case(needle)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
@@ -0,0 +1,211 @@
+//! Escaping for interpolated values, depending on where in the document they end up.
+//!
+//! Text and attribute values are always HTML-escaped when serialized; these escapers go on
+//! top of that, for the places where HTML escaping is not enough.
+
+use std::fmt::Write;
+
+/// A string that is trusted to go into the output as is, without any escaping.
+///
+/// Made in rhai with `safe(value)`; in text it is spliced in as HTML, like `{{{ }}}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Safe(pub String);
+
+/// Where an interpolated value ends up, which decides how it is escaped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EscapeContext {
+    /// Text, or an ordinary attribute value: HTML escaping is enough.
+    Html,
+
+    /// A URL attribute, such as `href` or `src`. At the start of the value the URL is checked
+    /// for a safe scheme, anywhere else it is percent-encoded as a URL component.
+    Url { start: bool },
+
+    /// A `srcset` or `imagesrcset` attribute: the URL of every comma-separated image
+    /// candidate is checked for a safe scheme.
+    SrcSet,
+
+    /// The text of a `<script>`: escaped as the inside of a JavaScript string literal.
+    Script,
+
+    /// The text of a `<style>`, or a `style` attribute: escaped as CSS.
+    Style,
+
+    /// An event handler attribute, such as `onclick`: escaped as the inside of a JavaScript
+    /// string literal, and HTML-escaped like any attribute value.
+    EventHandler,
+}
+
+impl EscapeContext {
+    /// The context of a value in the text of an element.
+    pub fn text(element_local_name: &str) -> Self {
+        match element_local_name {
+            "script" => EscapeContext::Script,
+            "style" => EscapeContext::Style,
+            _ => EscapeContext::Html,
+        }
+    }
+
+    /// The context of a value in an attribute, `start` telling whether it is at the start of
+    /// the attribute value.
+    pub fn attribute(name: &str, start: bool) -> Self {
+        if name == "srcset" || name == "imagesrcset" {
+            EscapeContext::SrcSet
+        } else if is_url_attribute(name) {
+            EscapeContext::Url { start }
+        } else if name == "style" {
+            EscapeContext::Style
+        } else if name.len() > 2 && name.starts_with("on") {
+            EscapeContext::EventHandler
+        } else {
+            EscapeContext::Html
+        }
+    }
+}
+
+/// What an unsafe URL is replaced with.
+pub const UNSAFE_URL: &str = "about:invalid#kolo-unsafe-url";
+
+/// The URL schemes allowed at the start of a URL attribute.
+const SAFE_SCHEMES: &[&str] = &["http", "https", "mailto", "tel"];
+
+/// Escapes `value` for `context`, or returns `None` for a URL with an unsafe scheme, such as
+/// `javascript:`.
+pub fn escape(value: &str, context: EscapeContext) -> Option<String> {
+    match context {
+        EscapeContext::Html => Some(String::from(value)),
+        EscapeContext::Url { start: true } => {
+            if is_unsafe_url(value) {
+                None
+            } else {
+                Some(percent_encode(value, |byte| {
+                    byte.is_ascii_graphic() && !b"\"'<>\\^`{|}".contains(&byte)
+                }))
+            }
+        }
+        EscapeContext::SrcSet => {
+            let mut candidates = value.split(',');
+            if candidates.any(|candidate| is_unsafe_url(candidate.trim_start())) {
+                None
+            } else {
+                Some(String::from(value))
+            }
+        }
+        EscapeContext::Url { start: false } => Some(percent_encode(value, |byte| {
+            byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte)
+        })),
+        EscapeContext::Script | EscapeContext::EventHandler => Some(escape_js(value)),
+        EscapeContext::Style => Some(escape_css(value)),
+    }
+}
+
+/// Whether an attribute holds a URL.
+pub fn is_url_attribute(name: &str) -> bool {
+    matches!(
+        name,
+        "action"
+            | "archive"
+            | "background"
+            | "cite"
+            | "classid"
+            | "codebase"
+            | "data"
+            | "formaction"
+            | "href"
+            | "icon"
+            | "longdesc"
+            | "manifest"
+            | "ping"
+            | "poster"
+            | "profile"
+            | "src"
+            | "usemap"
+            | "xlink:href"
+    )
+}
+
+/// Whether a URL has a scheme other than the [`SAFE_SCHEMES`].
+fn is_unsafe_url(url: &str) -> bool {
+    url_scheme(url).is_some_and(|scheme| !SAFE_SCHEMES.contains(&&*scheme))
+}
+
+/// The lowercased scheme of a URL, ignoring the whitespace and control characters browsers
+/// ignore, or `None` for a relative URL.
+fn url_scheme(url: &str) -> Option<String> {
+    let url: String = url
+        .trim_start_matches(|c: char| c <= ' ')
+        .chars()
+        .filter(|&c| c != '\t' && c != '\n' && c != '\r')
+        .collect();
+    let (scheme, _) = url.split_once(':')?;
+    let mut chars = scheme.chars();
+    let is_scheme = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.');
+    if is_scheme {
+        Some(scheme.to_ascii_lowercase())
+    } else {
+        None
+    }
+}
+
+fn percent_encode(value: &str, keep: impl Fn(u8) -> bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for &byte in value.as_bytes() {
+        if keep(byte) {
+            encoded.push(byte as char);
+        } else {
+            write!(encoded, "%{:02X}", byte).unwrap();
+        }
+    }
+    encoded
+}
+
+/// Escapes the inside of a JavaScript string literal (with any quotes), so that it can not
+/// end the literal, the `<script>` or the attribute either.
+fn escape_js(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\'' => escaped.push_str("\\'"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '`' | '$' | '<' | '>' | '&' | '/' | '=' | '\u{2028}' | '\u{2029}' => {
+                write!(escaped, "\\u{:04x}", c as u32).unwrap()
+            }
+            c if c < ' ' => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escapes CSS, so that a value can not end a string, declaration or rule, nor the
+/// `<style>` itself.
+fn escape_css(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\0'..='\u{1f}'
+            | '"'
+            | '&'
+            | '\''
+            | '('
+            | ')'
+            | '+'
+            | '/'
+            | ':'
+            | ';'
+            | '<'
+            | '>'
+            | '\\'
+            | '{'
+            | '}'
+            | '`' => write!(escaped, "\\{:x} ", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
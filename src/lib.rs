@@ -0,0 +1,18 @@
+pub mod ast;
+pub mod attributes;
+pub mod encoding;
+pub mod escape;
+pub mod lossless;
+pub mod minify;
+pub mod pretty;
+pub mod props;
+pub mod render;
+pub mod scoped;
+pub mod serializer;
+pub mod sink;
+pub mod slots;
+pub mod source_map;
+pub mod span;
+pub mod streaming;
+pub mod tree;
+pub mod whitespace;
@@ -0,0 +1,264 @@
+use html5ever::serialize::{HtmlSerializer, Serialize, SerializeOpts, Serializer, TraversalScope};
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::serializer::SerializableNode;
+use crate::span::{SourceFile, Span};
+use crate::tree::*;
+
+/// Discards output while muted.
+///
+/// Raw source is written around html5ever's serializer, which still has to see every start
+/// and end tag to know how to escape text; it is muted while it does.
+struct Muting<W: Write> {
+    writer: W,
+    muted: bool,
+}
+
+impl<W: Write> Write for Muting<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.muted {
+            Ok(buf.len())
+        } else {
+            self.writer.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+struct LosslessSerializer<'a, W: Write> {
+    node_tree: &'a NodeTree,
+    serializer: HtmlSerializer<Muting<W>>,
+    /// Nodes that, together with all their descendants, were not modified.
+    untouched: HashSet<NodeRef>,
+    /// Where the last raw source written ended, unless something was generated since.
+    cursor: Option<(Rc<SourceFile>, usize)>,
+}
+
+impl<'a, W: Write> LosslessSerializer<'a, W> {
+    /// Finds the untouched nodes under `node_ref`, returning whether it is one itself.
+    fn find_untouched(&mut self, node_ref: NodeRef) -> bool {
+        let node = self.node_tree.get_node(node_ref);
+        let mut untouched = !node.is_modified() && !node.children_modified();
+        if let Some(template_contents) = node.as_element().and_then(|e| e.template_contents) {
+            untouched &= self.find_untouched(template_contents);
+        }
+        for child_ref in self.node_tree.children(node_ref) {
+            untouched &= self.find_untouched(child_ref);
+        }
+        if untouched {
+            self.untouched.insert(node_ref);
+        }
+        untouched
+    }
+
+    /// Writes a slice of a node's source.
+    ///
+    /// When the last thing written was source right before this one, whatever the parser
+    /// dropped in between (like stray end tags) is written as well. Unless `fill_gap` is set,
+    /// that is only done for end tags, since a removed node could be in between.
+    fn raw(&mut self, source: &Rc<SourceFile>, span: Span, fill_gap: bool) -> io::Result<()> {
+        let start = match &self.cursor {
+            Some((cursor_source, end))
+                if Rc::ptr_eq(cursor_source, source)
+                    && *end < span.start
+                    && (fill_gap || only_end_tags(&source.text[*end..span.start])) =>
+            {
+                *end
+            }
+            _ => span.start,
+        };
+        self.serializer
+            .writer
+            .write_all(&source.text.as_bytes()[start..span.end])?;
+        self.cursor = Some((source.clone(), span.end));
+        Ok(())
+    }
+
+    fn node(
+        &mut self,
+        node_ref: NodeRef,
+        parent_ref: Option<NodeRef>,
+        parent_untouched: bool,
+    ) -> io::Result<()> {
+        let node = self.node_tree.get_node(node_ref);
+        if let (true, Some(source), Some(span)) = (
+            self.untouched.contains(&node_ref),
+            node.source(),
+            node.span(),
+        ) {
+            let end = self.extent_end(node_ref, parent_ref, source);
+            if end.is_none_or(|end| span.end <= end) {
+                return self.raw(source, span, parent_untouched);
+            }
+        }
+
+        match node.data() {
+            NodeData::Document(_) | NodeData::DocumentFragment => {
+                self.children(node_ref, !node.children_modified())?;
+                if node.children_modified() {
+                    self.cursor = None;
+                }
+                Ok(())
+            }
+            NodeData::Element(element) if !node.is_modified() => {
+                // keep the tags as written, or leave them out if the parser implied them
+                self.serializer.writer.muted = true;
+                self.serializer
+                    .start_elem(element.name.clone(), std::iter::empty())?;
+                self.serializer.writer.muted = false;
+                if let (Some(source), Some(start_tag_span)) = (node.source(), node.start_tag_span())
+                {
+                    self.raw(source, start_tag_span, parent_untouched)?;
+                }
+
+                let children_parent_ref = element.template_contents.unwrap_or(node_ref);
+                let children_untouched = !self
+                    .node_tree
+                    .get_node(children_parent_ref)
+                    .children_modified();
+                self.children(children_parent_ref, children_untouched)?;
+                if !children_untouched {
+                    self.cursor = None;
+                }
+
+                self.serializer.writer.muted = true;
+                self.serializer.end_elem(element.name.clone())?;
+                self.serializer.writer.muted = false;
+                if let (Some(source), Some(end_tag_span)) = (node.source(), node.end_tag_span()) {
+                    self.raw(source, end_tag_span, children_untouched)?;
+                }
+                Ok(())
+            }
+            NodeData::Element(element) => {
+                self.cursor = None;
+                SerializableNode::new(self.node_tree, node_ref)
+                    .serialize_start_tag(&mut self.serializer, element)?;
+                let children_parent_ref = element.template_contents.unwrap_or(node_ref);
+                let children_untouched = !self
+                    .node_tree
+                    .get_node(children_parent_ref)
+                    .children_modified();
+                self.children(children_parent_ref, children_untouched)?;
+                self.cursor = None;
+                // an end tag the source left out (like a `</li>`) stays left out
+                let implied_end = node.start_tag_span().is_some() && node.end_tag_span().is_none();
+                self.serializer.writer.muted = implied_end;
+                self.serializer.end_elem(element.name.clone())?;
+                self.serializer.writer.muted = false;
+                Ok(())
+            }
+            _ => {
+                self.cursor = None;
+                SerializableNode::new(self.node_tree, node_ref)
+                    .serialize(&mut self.serializer, TraversalScope::IncludeNode)
+            }
+        }
+    }
+
+    /// Where the source of a node must end at the latest: before the next sibling from the
+    /// same source, and before the end tag of its parent. A span going further is wrong, and
+    /// the node is serialized instead.
+    fn extent_end(
+        &self,
+        node_ref: NodeRef,
+        parent_ref: Option<NodeRef>,
+        source: &Rc<SourceFile>,
+    ) -> Option<usize> {
+        let same_source = |node: &Node| node.source().is_some_and(|s| Rc::ptr_eq(s, source));
+        let node = self.node_tree.get_node(node_ref);
+        let next_start = node
+            .next_sibling()
+            .map(|sibling_ref| self.node_tree.get_node(sibling_ref))
+            .filter(|sibling| same_source(sibling))
+            .and_then(|sibling| sibling.start_tag_span().or_else(|| sibling.span()))
+            .map(|span| span.start);
+        let parent_end = parent_ref
+            .map(|parent_ref| self.node_tree.get_node(parent_ref))
+            .filter(|parent| same_source(parent))
+            .and_then(|parent| parent.end_tag_span())
+            .map(|span| span.start);
+        match (next_start, parent_end) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn children(&mut self, parent_ref: NodeRef, parent_untouched: bool) -> io::Result<()> {
+        for child_ref in self.node_tree.children(parent_ref) {
+            self.node(child_ref, Some(parent_ref), parent_untouched)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `text` is nothing but end tags, which the parser never makes nodes for.
+fn only_end_tags(text: &str) -> bool {
+    let mut rest = text;
+    while !rest.is_empty() {
+        match rest
+            .strip_prefix("</")
+            .and_then(|tag| tag.find('>').map(|i| &tag[i + 1..]))
+        {
+            Some(after) => rest = after,
+            None => return false,
+        }
+    }
+    true
+}
+
+impl NodeTree {
+    /// Serialize a node and its descendants into `writer`, reusing the source they were
+    /// parsed from wherever it is still accurate.
+    ///
+    /// Nodes nothing changed since parsing (see [`Node::is_modified`]) are written exactly as
+    /// they were, with the original quoting, attribute order and character references; only
+    /// modified nodes are serialized again. Nodes without a source are always serialized.
+    pub fn serialize_lossless<W: Write>(&self, node_ref: NodeRef, writer: W) -> io::Result<()> {
+        let mut serializer = LosslessSerializer {
+            node_tree: self,
+            serializer: HtmlSerializer::new(
+                Muting {
+                    writer,
+                    muted: false,
+                },
+                SerializeOpts::default(),
+            ),
+            untouched: HashSet::new(),
+            cursor: None,
+        };
+        serializer.find_untouched(node_ref);
+        if node_ref == self.root() {
+            // whatever the parser dropped before the first node is written too
+            serializer.cursor = self.source().map(|source| (source.clone(), 0));
+        }
+        serializer.node(node_ref, self.parent(node_ref), false)?;
+
+        // whatever the parser dropped at the end of the document, if nothing was removed there
+        let root = self.get_node(node_ref);
+        if let (Some((source, end)), false, true) = (
+            serializer.cursor.take(),
+            root.children_modified(),
+            node_ref == self.root(),
+        ) {
+            serializer
+                .serializer
+                .writer
+                .write_all(&source.text.as_bytes()[end..])?;
+        }
+        Ok(())
+    }
+
+    /// Serialize the whole tree as an HTML string, reusing the source wherever it is still
+    /// accurate, see [`NodeTree::serialize_lossless`].
+    pub fn to_lossless_html_string(&self) -> String {
+        let mut html = Vec::new();
+        self.serialize_lossless(self.root(), &mut html)
+            .expect("writing to a Vec<u8> can not fail");
+        String::from_utf8(html).unwrap()
+    }
+}
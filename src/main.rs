@@ -1,8 +1,46 @@
-mod attributes;
-mod sink;
-mod tree;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use kolo::sink::{parse_html_bytes, parse_xml_bytes};
+
+const USAGE: &str = "usage: kolo ast <file>";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["ast", path] => ast(Path::new(path)),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+/// Prints the JSON AST of a file; `.xml`, `.xhtml` and `.svg` files are parsed as XML.
+fn ast(path: &Path) {
+    let bytes = fs::read(path).unwrap_or_else(|error| {
+        eprintln!("kolo: {}: {}", path.display(), error);
+        process::exit(1);
+    });
+    let is_xml = matches!(
+        path.extension().and_then(|extension| extension.to_str()),
+        Some("xml") | Some("xhtml") | Some("svg")
+    );
+    let node_tree = if is_xml {
+        parse_xml_bytes(&bytes)
+    } else {
+        parse_html_bytes(&bytes)
+    };
+    println!("{}", node_tree.to_json_ast());
+}
 
-fn main() {}
 /*
 /// Node data specific to the node type.
 #[derive(Debug, PartialEq, Clone)]
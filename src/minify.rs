@@ -0,0 +1,529 @@
+use html5ever::{local_name, namespace_url, ns, LocalName};
+use std::io::{self, Write};
+
+use crate::serializer::{
+    attribute_name, collapse, drops_leading_newline, html_local_name, is_raw_text, is_void,
+    preserves_whitespace,
+};
+use crate::tree::*;
+
+/// Options for minified output. Everything is enabled by default.
+#[derive(Debug, Clone)]
+pub struct MinifyOpts {
+    /// Collapse runs of whitespace outside `pre`, `textarea`, `script` and `style`, and drop
+    /// whitespace next to block-level elements.
+    pub collapse_whitespace: bool,
+
+    /// Remove comments, except conditional comments and ones starting with `!` or
+    /// containing `@license` or `@preserve`.
+    pub remove_comments: bool,
+
+    /// Leave out start and end tags the HTML parser will infer on its own.
+    pub omit_optional_tags: bool,
+
+    /// Leave out attribute value quotes when the value allows it.
+    pub unquote_attributes: bool,
+
+    /// Write boolean attributes such as `disabled="disabled"` as just `disabled`.
+    pub shorten_boolean_attributes: bool,
+}
+
+impl Default for MinifyOpts {
+    fn default() -> Self {
+        MinifyOpts {
+            collapse_whitespace: true,
+            remove_comments: true,
+            omit_optional_tags: true,
+            unquote_attributes: true,
+            shorten_boolean_attributes: true,
+        }
+    }
+}
+
+impl NodeTree {
+    /// Collapses whitespace and removes comments below `node_ref`.
+    ///
+    /// This only changes the tree; use [`NodeTree::serialize_minified`] for the parts of
+    /// minification that are about how the tree is written out.
+    pub fn minify(&mut self, node_ref: NodeRef, opts: &MinifyOpts) {
+        let preserve_whitespace = self.get_node(node_ref).as_element().is_some_and(|element| {
+            element.name.ns == ns!(html) && preserves_whitespace(&element.name.local)
+        });
+        self.minify_children(node_ref, opts, preserve_whitespace)
+    }
+
+    fn minify_children(&mut self, parent_ref: NodeRef, opts: &MinifyOpts, preserve: bool) {
+        let children: Vec<NodeRef> = self.children(parent_ref).collect();
+        for child_ref in children {
+            match self.get_node(child_ref).data() {
+                NodeData::Comment(text) if opts.remove_comments && !is_kept_comment(text) => {
+                    self.remove_subtree(child_ref)
+                }
+                NodeData::Element(element) => {
+                    let preserve = preserve
+                        || (element.name.ns == ns!(html)
+                            && preserves_whitespace(&element.name.local));
+                    let parent_ref = element.template_contents.unwrap_or(child_ref);
+                    self.minify_children(parent_ref, opts, preserve)
+                }
+                _ => {}
+            }
+        }
+
+        // removing comments can leave text nodes next to each other, which the parser would
+        // merge back into one
+        let children: Vec<NodeRef> = self.children(parent_ref).collect();
+        for pair in children.windows(2) {
+            let (previous_ref, child_ref) = (pair[0], pair[1]);
+            if let (Some(previous), Some(text)) = (
+                self.get_node(previous_ref).as_text(),
+                self.get_node(child_ref).as_text(),
+            ) {
+                let merged = format!("{}{}", previous, text);
+                *self.get_node_mut(child_ref).as_text_mut().unwrap() = merged;
+                self.remove_subtree(previous_ref);
+            }
+        }
+
+        if opts.collapse_whitespace && !preserve {
+            self.collapse_whitespace(parent_ref)
+        }
+    }
+
+    fn collapse_whitespace(&mut self, parent_ref: NodeRef) {
+        let parent_is_block = self.is_block(parent_ref)
+            || matches!(
+                self.get_node(parent_ref).data(),
+                NodeData::Document(_) | NodeData::DocumentFragment
+            );
+        let parent_holds_text =
+            !html_local_name(self.get_node(parent_ref)).is_some_and(holds_no_text);
+
+        let children: Vec<NodeRef> = self.children(parent_ref).collect();
+        for child_ref in children {
+            let node = self.get_node(child_ref);
+            let text = match node.as_text() {
+                Some(text) => text,
+                None => continue,
+            };
+
+            let trim_start = match node.previous_sibling() {
+                Some(previous_ref) => self.is_block(previous_ref),
+                None => parent_is_block,
+            };
+            let trim_end = match node.next_sibling() {
+                Some(next_ref) => self.is_block(next_ref),
+                None => parent_is_block,
+            };
+
+            let mut collapsed = collapse(text);
+            if trim_end {
+                collapsed.truncate(collapsed.trim_end_matches(' ').len());
+            }
+            if trim_start {
+                collapsed = collapsed.trim_start_matches(' ').to_owned();
+            }
+
+            if collapsed.is_empty() || (collapsed == " " && !parent_holds_text) {
+                self.remove_subtree(child_ref)
+            } else {
+                *self.get_node_mut(child_ref).as_text_mut().unwrap() = collapsed;
+            }
+        }
+    }
+
+    fn is_block(&self, node_ref: NodeRef) -> bool {
+        html_local_name(self.get_node(node_ref)).is_some_and(is_block_level)
+    }
+
+    /// Serialize a node as compact HTML into `writer`.
+    ///
+    /// Combine with [`NodeTree::minify`] to also drop whitespace and comments.
+    pub fn serialize_minified<W: Write>(
+        &self,
+        node_ref: NodeRef,
+        writer: W,
+        opts: &MinifyOpts,
+    ) -> io::Result<()> {
+        MinifyingSerializer {
+            node_tree: self,
+            writer,
+            opts,
+        }
+        .write_node(node_ref, false)
+    }
+
+    /// Serialize the whole tree as a compact HTML string.
+    pub fn to_minified_html_string(&self, opts: &MinifyOpts) -> String {
+        let mut html = Vec::new();
+        self.serialize_minified(self.root(), &mut html, opts)
+            .expect("writing to a Vec<u8> can not fail");
+        String::from_utf8(html).unwrap()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Escape {
+    Text,
+    /// An attribute value, with the quote character around it if any.
+    Attribute(Option<char>),
+}
+
+struct MinifyingSerializer<'a, W: Write> {
+    node_tree: &'a NodeTree,
+    writer: W,
+    opts: &'a MinifyOpts,
+}
+
+impl<'a, W: Write> MinifyingSerializer<'a, W> {
+    fn write_node(&mut self, node_ref: NodeRef, raw_text: bool) -> io::Result<()> {
+        let node_tree = self.node_tree;
+        match node_tree.get_node(node_ref).data() {
+            NodeData::Element(element) => self.write_element(node_ref, element),
+            NodeData::Text(text) if raw_text => self.writer.write_all(text.as_bytes()),
+            NodeData::Text(text) => self.write_escaped(text, Escape::Text),
+            NodeData::Comment(text) => write!(self.writer, "<!--{}-->", text),
+            NodeData::ProcessingInstruction((target, data)) => {
+                write!(self.writer, "<?{} {}>", target, data)
+            }
+            NodeData::Doctype(doctype) => write!(self.writer, "<!DOCTYPE {}>", doctype.name),
+            NodeData::Document(_) | NodeData::DocumentFragment => {
+                for child_ref in node_tree.children(node_ref) {
+                    self.write_node(child_ref, false)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn write_element(&mut self, node_ref: NodeRef, element: &ElementData) -> io::Result<()> {
+        let node_tree = self.node_tree;
+        let local = &element.name.local;
+        let html_local = if element.name.ns == ns!(html) {
+            Some(local)
+        } else {
+            None
+        };
+
+        let omit_start = self.opts.omit_optional_tags
+            && element.attributes.map.is_empty()
+            && html_local.is_some_and(|local| self.can_omit_start_tag(node_ref, local));
+        if !omit_start {
+            write!(self.writer, "<{}", local)?;
+            self.write_attributes(element)?;
+            self.writer.write_all(b">")?;
+        }
+
+        if html_local.is_some_and(is_void) {
+            return Ok(());
+        }
+
+        let raw_text = html_local.is_some_and(is_raw_text);
+        if html_local.is_some_and(drops_leading_newline) {
+            // the parser drops a newline right after the start tag, so one that is part of the
+            // content needs another in front of it
+            let first_child = node_tree.get_node(node_ref).first_child();
+            if first_child
+                .and_then(|first_child_ref| node_tree.get_node(first_child_ref).as_text())
+                .is_some_and(|text| text.starts_with('\n'))
+            {
+                self.writer.write_all(b"\n")?;
+            }
+        }
+        let parent_ref = element.template_contents.unwrap_or(node_ref);
+        for child_ref in node_tree.children(parent_ref) {
+            self.write_node(child_ref, raw_text)?;
+        }
+
+        let omit_end = self.opts.omit_optional_tags
+            && html_local.is_some_and(|local| self.can_omit_end_tag(node_ref, local));
+        if !omit_end {
+            write!(self.writer, "</{}>", local)?;
+        }
+        Ok(())
+    }
+
+    fn write_attributes(&mut self, element: &ElementData) -> io::Result<()> {
+        for (name, attribute) in &element.attributes.map {
+            write!(self.writer, " {}", attribute_name(name, attribute))?;
+
+            let value = &attribute.value;
+            let is_boolean = self.opts.shorten_boolean_attributes
+                && name.ns == ns!()
+                && is_boolean_attribute(&name.local)
+                && (value.is_empty() || value.eq_ignore_ascii_case(&name.local));
+            if is_boolean || (self.opts.unquote_attributes && value.is_empty()) {
+                continue;
+            }
+
+            if self.opts.unquote_attributes && can_be_unquoted(value) {
+                self.writer.write_all(b"=")?;
+                self.write_escaped(value, Escape::Attribute(None))?;
+            } else {
+                let quote = if value.contains('"') && !value.contains('\'') {
+                    '\''
+                } else {
+                    '"'
+                };
+                write!(self.writer, "={}", quote)?;
+                self.write_escaped(value, Escape::Attribute(Some(quote)))?;
+                write!(self.writer, "{}", quote)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_escaped(&mut self, text: &str, escape: Escape) -> io::Result<()> {
+        for c in text.chars() {
+            match (c, escape) {
+                ('&', _) => self.writer.write_all(b"&amp;"),
+                ('\u{00A0}', _) => self.writer.write_all(b"&nbsp;"),
+                ('<', Escape::Text) => self.writer.write_all(b"&lt;"),
+                ('>', Escape::Text) => self.writer.write_all(b"&gt;"),
+                ('"', Escape::Attribute(Some('"'))) => self.writer.write_all(b"&quot;"),
+                (c, _) => write!(self.writer, "{}", c),
+            }?;
+        }
+        Ok(())
+    }
+
+    /// <https://html.spec.whatwg.org/multipage/syntax.html#optional-tags>
+    fn can_omit_start_tag(&self, node_ref: NodeRef, local: &LocalName) -> bool {
+        let first_child = self
+            .node_tree
+            .get_node(node_ref)
+            .first_child()
+            .map(|first_child_ref| self.node_tree.get_node(first_child_ref));
+        match *local {
+            local_name!("html") => !first_child.is_some_and(is_comment),
+            local_name!("head") => first_child.is_none_or(|node| node.as_element().is_some()),
+            local_name!("body") => match first_child {
+                None => true,
+                Some(node) => {
+                    !starts_with_whitespace(node)
+                        && !is_comment(node)
+                        && !matches!(
+                            html_local_name(node),
+                            Some(&local_name!("meta"))
+                                | Some(&local_name!("link"))
+                                | Some(&local_name!("script"))
+                                | Some(&local_name!("style"))
+                                | Some(&local_name!("template"))
+                                | Some(&local_name!("noscript"))
+                        )
+                }
+            },
+            _ => false,
+        }
+    }
+
+    /// <https://html.spec.whatwg.org/multipage/syntax.html#optional-tags>
+    fn can_omit_end_tag(&self, node_ref: NodeRef, local: &LocalName) -> bool {
+        let next = self
+            .node_tree
+            .get_node(node_ref)
+            .next_sibling()
+            .map(|next_ref| self.node_tree.get_node(next_ref));
+        let last = next.is_none();
+        let next_is = |names: &[LocalName]| {
+            next.and_then(html_local_name)
+                .is_some_and(|next_local| names.contains(next_local))
+        };
+        match *local {
+            local_name!("html") | local_name!("body") => !next.is_some_and(is_comment),
+            local_name!("head") | local_name!("colgroup") | local_name!("caption") => {
+                !next.is_some_and(|node| is_comment(node) || starts_with_whitespace(node))
+            }
+            local_name!("li") => last || next_is(&[local_name!("li")]),
+            local_name!("dt") => next_is(&[local_name!("dt"), local_name!("dd")]),
+            local_name!("dd") => last || next_is(&[local_name!("dd"), local_name!("dt")]),
+            local_name!("rt") | local_name!("rp") => {
+                last || next_is(&[local_name!("rt"), local_name!("rp")])
+            }
+            local_name!("optgroup") => {
+                last || next_is(&[local_name!("optgroup"), local_name!("hr")])
+            }
+            local_name!("option") => {
+                last || next_is(&[
+                    local_name!("option"),
+                    local_name!("optgroup"),
+                    local_name!("hr"),
+                ])
+            }
+            local_name!("thead") => next_is(&[local_name!("tbody"), local_name!("tfoot")]),
+            local_name!("tbody") => last || next_is(&[local_name!("tbody"), local_name!("tfoot")]),
+            local_name!("tfoot") => last,
+            local_name!("tr") => last || next_is(&[local_name!("tr")]),
+            local_name!("td") | local_name!("th") => {
+                last || next_is(&[local_name!("td"), local_name!("th")])
+            }
+            local_name!("p") => match next {
+                Some(node) => html_local_name(node).is_some_and(closes_p),
+                None => self
+                    .node_tree
+                    .parent(node_ref)
+                    .and_then(|parent_ref| html_local_name(self.node_tree.get_node(parent_ref)))
+                    .is_some_and(|parent| {
+                        !matches!(
+                            *parent,
+                            local_name!("a")
+                                | local_name!("audio")
+                                | local_name!("del")
+                                | local_name!("ins")
+                                | local_name!("map")
+                                | local_name!("noscript")
+                                | local_name!("video")
+                        ) && !parent.contains('-')
+                    }),
+            },
+            _ => false,
+        }
+    }
+}
+
+fn is_comment(node: &Node) -> bool {
+    node.as_comment().is_some()
+}
+
+fn starts_with_whitespace(node: &Node) -> bool {
+    node.as_text()
+        .and_then(|text| text.chars().next())
+        .is_some_and(|c| c.is_ascii_whitespace())
+}
+
+/// Conditional comments and licence comments survive minification.
+fn is_kept_comment(text: &str) -> bool {
+    let text = text.trim_start();
+    text.starts_with('!')
+        || text.starts_with("[if ")
+        || text.ends_with("[endif]")
+        || text.contains("@license")
+        || text.contains("@preserve")
+}
+
+/// <https://html.spec.whatwg.org/multipage/syntax.html#unquoted>
+fn can_be_unquoted(value: &str) -> bool {
+    !value.is_empty()
+        && !value
+            .chars()
+            .any(|c| c.is_ascii_whitespace() || matches!(c, '"' | '\'' | '=' | '<' | '>' | '`'))
+}
+
+/// Elements where whitespace-only text is never rendered.
+fn holds_no_text(local: &LocalName) -> bool {
+    matches!(
+        *local,
+        local_name!("html")
+            | local_name!("head")
+            | local_name!("table")
+            | local_name!("thead")
+            | local_name!("tbody")
+            | local_name!("tfoot")
+            | local_name!("tr")
+            | local_name!("colgroup")
+            | local_name!("select")
+            | local_name!("optgroup")
+    )
+}
+
+fn is_boolean_attribute(local: &LocalName) -> bool {
+    matches!(
+        *local,
+        local_name!("allowfullscreen")
+            | local_name!("async")
+            | local_name!("autofocus")
+            | local_name!("autoplay")
+            | local_name!("checked")
+            | local_name!("controls")
+            | local_name!("default")
+            | local_name!("defer")
+            | local_name!("disabled")
+            | local_name!("formnovalidate")
+            | local_name!("hidden")
+            | local_name!("ismap")
+            | local_name!("itemscope")
+            | local_name!("loop")
+            | local_name!("multiple")
+            | local_name!("muted")
+            | local_name!("nomodule")
+            | local_name!("novalidate")
+            | local_name!("open")
+            | local_name!("readonly")
+            | local_name!("required")
+            | local_name!("reversed")
+            | local_name!("selected")
+    )
+}
+
+/// Elements whose start tag implicitly closes an open `p`.
+fn closes_p(local: &LocalName) -> bool {
+    matches!(
+        *local,
+        local_name!("address")
+            | local_name!("article")
+            | local_name!("aside")
+            | local_name!("blockquote")
+            | local_name!("details")
+            | local_name!("dialog")
+            | local_name!("div")
+            | local_name!("dl")
+            | local_name!("fieldset")
+            | local_name!("figcaption")
+            | local_name!("figure")
+            | local_name!("footer")
+            | local_name!("form")
+            | local_name!("h1")
+            | local_name!("h2")
+            | local_name!("h3")
+            | local_name!("h4")
+            | local_name!("h5")
+            | local_name!("h6")
+            | local_name!("header")
+            | local_name!("hgroup")
+            | local_name!("hr")
+            | local_name!("main")
+            | local_name!("menu")
+            | local_name!("nav")
+            | local_name!("ol")
+            | local_name!("p")
+            | local_name!("pre")
+            | local_name!("section")
+            | local_name!("table")
+            | local_name!("ul")
+    )
+}
+
+/// Elements next to which whitespace does not render.
+pub(crate) fn is_block_level(local: &LocalName) -> bool {
+    closes_p(local)
+        || matches!(
+            *local,
+            local_name!("html")
+                | local_name!("head")
+                | local_name!("body")
+                | local_name!("title")
+                | local_name!("meta")
+                | local_name!("link")
+                | local_name!("base")
+                | local_name!("script")
+                | local_name!("style")
+                | local_name!("template")
+                | local_name!("noscript")
+                | local_name!("li")
+                | local_name!("dd")
+                | local_name!("dt")
+                | local_name!("caption")
+                | local_name!("colgroup")
+                | local_name!("col")
+                | local_name!("thead")
+                | local_name!("tbody")
+                | local_name!("tfoot")
+                | local_name!("tr")
+                | local_name!("td")
+                | local_name!("th")
+                | local_name!("option")
+                | local_name!("optgroup")
+                | local_name!("legend")
+                | local_name!("summary")
+        )
+}
@@ -0,0 +1,418 @@
+use html5ever::{local_name, namespace_url, ns, LocalName};
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use crate::minify::is_block_level;
+use crate::serializer::{
+    attribute_name, drops_leading_newline, escape, html_local_name, is_raw_text, is_void,
+    preserves_whitespace,
+};
+use crate::tree::*;
+
+/// Options for pretty-printed output.
+#[derive(Debug, Clone)]
+pub struct PrettyOpts {
+    /// Spaces per nesting level.
+    pub indent_width: usize,
+
+    /// Text is wrapped, and attributes are put on their own lines, to stay under this width.
+    /// Words and inline tags are never split, so long ones can still go over it.
+    pub max_line_length: usize,
+
+    /// Elements that flow with the text around them instead of getting their own lines.
+    /// Other elements are laid out as blocks, except custom elements and other HTML elements
+    /// that are not known to be block-level or void, which flow with the text as well.
+    pub inline_elements: HashSet<LocalName>,
+}
+
+impl Default for PrettyOpts {
+    fn default() -> Self {
+        let inline_elements = [
+            local_name!("a"),
+            local_name!("abbr"),
+            local_name!("acronym"),
+            local_name!("audio"),
+            local_name!("b"),
+            local_name!("bdi"),
+            local_name!("bdo"),
+            local_name!("big"),
+            local_name!("br"),
+            local_name!("button"),
+            local_name!("canvas"),
+            local_name!("cite"),
+            local_name!("code"),
+            local_name!("data"),
+            local_name!("del"),
+            local_name!("dfn"),
+            local_name!("em"),
+            local_name!("embed"),
+            local_name!("font"),
+            local_name!("i"),
+            local_name!("iframe"),
+            local_name!("img"),
+            local_name!("input"),
+            local_name!("ins"),
+            local_name!("kbd"),
+            local_name!("label"),
+            local_name!("map"),
+            local_name!("mark"),
+            local_name!("math"),
+            local_name!("meter"),
+            local_name!("object"),
+            local_name!("output"),
+            local_name!("picture"),
+            local_name!("progress"),
+            local_name!("q"),
+            local_name!("rp"),
+            local_name!("rt"),
+            local_name!("ruby"),
+            local_name!("s"),
+            local_name!("samp"),
+            local_name!("select"),
+            local_name!("small"),
+            local_name!("span"),
+            local_name!("strike"),
+            local_name!("strong"),
+            local_name!("sub"),
+            local_name!("sup"),
+            local_name!("svg"),
+            local_name!("textarea"),
+            local_name!("time"),
+            local_name!("tt"),
+            local_name!("u"),
+            local_name!("var"),
+            local_name!("video"),
+            local_name!("wbr"),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        PrettyOpts {
+            indent_width: 2,
+            max_line_length: 100,
+            inline_elements,
+        }
+    }
+}
+
+impl NodeTree {
+    /// Serialize a node as indented HTML into `writer`.
+    ///
+    /// Only whitespace that does not render is changed, and pretty-printing the output again
+    /// gives the same output.
+    pub fn serialize_pretty<W: Write>(
+        &self,
+        node_ref: NodeRef,
+        mut writer: W,
+        opts: &PrettyOpts,
+    ) -> io::Result<()> {
+        let mut printer = PrettyPrinter {
+            node_tree: self,
+            opts,
+            out: String::new(),
+        };
+        match self.get_node(node_ref).data() {
+            NodeData::Document(_) | NodeData::DocumentFragment => {
+                printer.write_block_children(node_ref, 0)
+            }
+            _ => printer.write_items(&[node_ref], 0),
+        }
+        printer.out.push('\n');
+        writer.write_all(printer.out.trim_start_matches('\n').as_bytes())
+    }
+
+    /// Serialize the whole tree as an indented HTML string.
+    pub fn to_pretty_html_string(&self, opts: &PrettyOpts) -> String {
+        let mut html = Vec::new();
+        self.serialize_pretty(self.root(), &mut html, opts)
+            .expect("writing to a Vec<u8> can not fail");
+        String::from_utf8(html).unwrap()
+    }
+}
+
+/// A piece of flowing inline content.
+enum Piece {
+    /// Text and tags that must stay together.
+    Word(String),
+    /// Collapsed whitespace, where a line may be broken.
+    Space,
+}
+
+struct PrettyPrinter<'a> {
+    node_tree: &'a NodeTree,
+    opts: &'a PrettyOpts,
+    out: String,
+}
+
+impl<'a> PrettyPrinter<'a> {
+    fn children_parent(&self, node_ref: NodeRef) -> NodeRef {
+        self.node_tree
+            .get_node(node_ref)
+            .as_element()
+            .and_then(|element| element.template_contents)
+            .unwrap_or(node_ref)
+    }
+
+    fn children(&self, node_ref: NodeRef) -> Vec<NodeRef> {
+        self.node_tree
+            .children(self.children_parent(node_ref))
+            .collect()
+    }
+
+    /// Whether a node can be part of a line of text.
+    fn is_inline(&self, node_ref: NodeRef) -> bool {
+        let node = self.node_tree.get_node(node_ref);
+        match node.data() {
+            NodeData::Text(_) | NodeData::Comment(_) => true,
+            NodeData::Element(element) => {
+                let local = &element.name.local;
+                let not_block =
+                    element.name.ns == ns!(html) && !is_block_level(local) && !is_void(local);
+                (self.opts.inline_elements.contains(local) || not_block)
+                    && (self.is_verbatim(node_ref)
+                        || self
+                            .children(node_ref)
+                            .into_iter()
+                            .all(|child_ref| self.is_inline(child_ref)))
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether an element's contents have to be written exactly as they are.
+    fn is_verbatim(&self, node_ref: NodeRef) -> bool {
+        html_local_name(self.node_tree.get_node(node_ref))
+            .is_some_and(|local| preserves_whitespace(local) || is_raw_text(local))
+    }
+
+    fn newline(&mut self, depth: usize) {
+        self.out.push('\n');
+        self.out
+            .extend(std::iter::repeat_n(' ', depth * self.opts.indent_width));
+    }
+
+    fn write_block_children(&mut self, node_ref: NodeRef, depth: usize) {
+        let children = self.children(node_ref);
+        self.write_items(&children, depth)
+    }
+
+    /// Writes nodes as lines at `depth`, grouping runs of inline nodes into paragraphs.
+    fn write_items(&mut self, node_refs: &[NodeRef], depth: usize) {
+        let mut run = Vec::new();
+        for &node_ref in node_refs {
+            if self.is_inline(node_ref) {
+                run.push(node_ref);
+                continue;
+            }
+            self.write_run(&run, depth);
+            run.clear();
+            self.newline(depth);
+            self.write_block(node_ref, depth);
+        }
+        self.write_run(&run, depth);
+    }
+
+    fn write_block(&mut self, node_ref: NodeRef, depth: usize) {
+        let node_tree = self.node_tree;
+        match node_tree.get_node(node_ref).data() {
+            NodeData::Element(element) => self.write_element(node_ref, element, depth),
+            NodeData::Doctype(doctype) => {
+                self.out.push_str(&format!("<!DOCTYPE {}>", doctype.name))
+            }
+            NodeData::ProcessingInstruction((target, data)) => {
+                self.out.push_str(&format!("<?{} {}>", target, data))
+            }
+            NodeData::Document(_) | NodeData::DocumentFragment => {
+                self.write_block_children(node_ref, depth)
+            }
+            NodeData::Text(_) | NodeData::Comment(_) => unreachable!("inline content"),
+        }
+    }
+
+    fn write_element(&mut self, node_ref: NodeRef, element: &ElementData, depth: usize) {
+        let local = &element.name.local;
+        let start_tag = self.start_tag(element, Some(depth));
+        self.out.push_str(&start_tag);
+
+        if element.name.ns == ns!(html) && is_void(local) {
+            return;
+        }
+        if self.is_verbatim(node_ref) {
+            self.out.push_str(&self.verbatim_contents(node_ref));
+            self.out.push_str(&format!("</{}>", local));
+            return;
+        }
+
+        let children = self.children(node_ref);
+        if children.iter().all(|&child_ref| self.is_inline(child_ref)) {
+            let pieces = self.pieces(&children);
+            let end_tag = format!("</{}>", local);
+            let line = join_on_one_line(&pieces);
+            let width = self.current_column() + line.len() + end_tag.len();
+            if width <= self.opts.max_line_length && !line.contains('\n') {
+                self.out.push_str(&line);
+                self.out.push_str(&end_tag);
+                return;
+            }
+        }
+
+        self.write_items(&children, depth + 1);
+        self.newline(depth);
+        self.out.push_str(&format!("</{}>", local));
+    }
+
+    /// The start tag of an element. With a `depth`, attributes go on their own lines when the
+    /// tag would otherwise be too long.
+    fn start_tag(&self, element: &ElementData, depth: Option<usize>) -> String {
+        let attributes: Vec<String> = element
+            .attributes
+            .map
+            .iter()
+            .map(|(name, attribute)| {
+                format!(
+                    "{}=\"{}\"",
+                    attribute_name(name, attribute),
+                    escape(&attribute.value, true)
+                )
+            })
+            .collect();
+
+        let mut start_tag = format!("<{}", element.name.local);
+        let one_line_width =
+            start_tag.len() + attributes.iter().map(|a| a.len() + 1).sum::<usize>() + 1;
+        match depth {
+            Some(depth)
+                if attributes.len() > 1
+                    && self.current_column() + one_line_width > self.opts.max_line_length =>
+            {
+                let indent = " ".repeat((depth + 1) * self.opts.indent_width);
+                for attribute in attributes {
+                    start_tag.push('\n');
+                    start_tag.push_str(&indent);
+                    start_tag.push_str(&attribute);
+                }
+            }
+            _ => {
+                for attribute in attributes {
+                    start_tag.push(' ');
+                    start_tag.push_str(&attribute);
+                }
+            }
+        }
+        start_tag.push('>');
+        start_tag
+    }
+
+    /// The contents of a whitespace-sensitive or raw text element, unchanged.
+    fn verbatim_contents(&self, node_ref: NodeRef) -> String {
+        let mut contents = self.node_tree.inner_html(node_ref);
+        let keeps_newline =
+            html_local_name(self.node_tree.get_node(node_ref)).is_some_and(drops_leading_newline);
+        if keeps_newline && contents.starts_with('\n') {
+            // the parser drops a newline right after the start tag
+            contents.insert(0, '\n');
+        }
+        contents
+    }
+
+    fn current_column(&self) -> usize {
+        match self.out.rfind('\n') {
+            Some(index) => self.out.len() - index - 1,
+            None => self.out.len(),
+        }
+    }
+
+    /// Writes a run of inline nodes as wrapped lines at `depth`.
+    fn write_run(&mut self, node_refs: &[NodeRef], depth: usize) {
+        let pieces = self.pieces(node_refs);
+        let mut at_line_start = true;
+        let mut pending_space = false;
+        for piece in pieces {
+            let word = match piece {
+                Piece::Space => {
+                    pending_space = !at_line_start;
+                    continue;
+                }
+                Piece::Word(word) => word,
+            };
+            let word_width = word.split('\n').next().unwrap_or("").len();
+            if at_line_start {
+                self.newline(depth);
+            } else if pending_space {
+                if self.current_column() + 1 + word_width > self.opts.max_line_length {
+                    self.newline(depth);
+                } else {
+                    self.out.push(' ');
+                }
+            }
+            self.out.push_str(&word);
+            at_line_start = false;
+            pending_space = false;
+        }
+    }
+
+    /// Splits inline nodes into words and the whitespace between them.
+    fn pieces(&self, node_refs: &[NodeRef]) -> Vec<Piece> {
+        let mut pieces = Vec::new();
+        let mut word = String::new();
+        for &node_ref in node_refs {
+            self.push_pieces(node_ref, &mut pieces, &mut word);
+        }
+        if !word.is_empty() {
+            pieces.push(Piece::Word(word));
+        }
+        // whitespace next to a block boundary does not render
+        while let Some(Piece::Space) = pieces.last() {
+            pieces.pop();
+        }
+        pieces
+    }
+
+    fn push_pieces(&self, node_ref: NodeRef, pieces: &mut Vec<Piece>, word: &mut String) {
+        let node_tree = self.node_tree;
+        match node_tree.get_node(node_ref).data() {
+            NodeData::Text(text) => {
+                for (index, part) in text.split(|c: char| c.is_ascii_whitespace()).enumerate() {
+                    if index > 0 {
+                        if !word.is_empty() {
+                            pieces.push(Piece::Word(std::mem::take(word)));
+                        }
+                        if !pieces.is_empty() && !matches!(pieces.last(), Some(Piece::Space)) {
+                            pieces.push(Piece::Space);
+                        }
+                    }
+                    word.push_str(&escape(part, false));
+                }
+            }
+            NodeData::Comment(text) => word.push_str(&format!("<!--{}-->", text)),
+            NodeData::Element(element) => {
+                word.push_str(&self.start_tag(element, None));
+                let local = &element.name.local;
+                if element.name.ns == ns!(html) && is_void(local) {
+                    return;
+                }
+                if self.is_verbatim(node_ref) {
+                    word.push_str(&self.verbatim_contents(node_ref));
+                } else {
+                    for child_ref in self.children(node_ref) {
+                        self.push_pieces(child_ref, pieces, word);
+                    }
+                }
+                word.push_str(&format!("</{}>", local));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Joins pieces the way they would be written if everything fit on one line.
+fn join_on_one_line(pieces: &[Piece]) -> String {
+    let mut line = String::new();
+    for piece in pieces {
+        match piece {
+            Piece::Word(word) => line.push_str(word),
+            Piece::Space => line.push(' '),
+        }
+    }
+    line
+}
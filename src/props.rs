@@ -0,0 +1,184 @@
+//! The props a component declares with `props(#{ ... })` in its `<script build>`.
+//!
+//! Each entry of the map declares a prop: either just its type, as in `title: "string"`, for
+//! a required prop, or a map with any of `type`, `required` and `default`, as in
+//! `count: #{ type: "int", "default": 1 }` (quoted, since `default` is a rhai keyword). A
+//! prop with a default is optional.
+
+use rhai::{Dynamic, Map};
+
+/// The type of a prop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropType {
+    String,
+    Int,
+    Float,
+    Bool,
+    Array,
+    Map,
+    /// Anything, left as it is.
+    Any,
+}
+
+impl PropType {
+    pub fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "string" => PropType::String,
+            "int" => PropType::Int,
+            "float" => PropType::Float,
+            "bool" => PropType::Bool,
+            "array" => PropType::Array,
+            "map" => PropType::Map,
+            "any" => PropType::Any,
+            _ => return None,
+        })
+    }
+
+    /// The type with its article, for messages, such as "an int".
+    pub fn describe(self) -> &'static str {
+        match self {
+            PropType::String => "a string",
+            PropType::Int => "an int",
+            PropType::Float => "a float",
+            PropType::Bool => "a bool",
+            PropType::Array => "an array",
+            PropType::Map => "a map",
+            PropType::Any => "anything",
+        }
+    }
+
+    /// Converts a value passed for a prop of this type, or returns `None` when it can not be.
+    ///
+    /// The string values of plain attributes (`from_attribute`) are parsed as numbers and
+    /// booleans, where an empty value, as in `<my-card active>`, is `true`; ints are taken
+    /// as floats.
+    pub fn coerce(self, value: Dynamic, from_attribute: bool) -> Option<Dynamic> {
+        let coerced = match self {
+            PropType::Any => value,
+            PropType::String if value.is_string() => value,
+            PropType::Int if value.is_int() => value,
+            PropType::Float if value.is_float() => value,
+            PropType::Float if value.is_int() => Dynamic::from(value.as_int().ok()? as f64),
+            PropType::Bool if value.is_bool() => value,
+            PropType::Array if value.is_array() => value,
+            PropType::Map if value.is_map() => value,
+            _ if from_attribute && value.is_string() => {
+                let text = value.into_string().ok()?;
+                let text = text.trim();
+                match self {
+                    PropType::Int => Dynamic::from(text.parse::<i64>().ok()?),
+                    PropType::Float => Dynamic::from(text.parse::<f64>().ok()?),
+                    PropType::Bool => match text {
+                        "" | "true" => Dynamic::TRUE,
+                        "false" => Dynamic::FALSE,
+                        _ => return None,
+                    },
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        Some(coerced)
+    }
+}
+
+/// A prop a component declares.
+#[derive(Debug, Clone)]
+pub struct PropDeclaration {
+    /// The name of the variable the prop is bound to.
+    pub name: String,
+    pub prop_type: PropType,
+    pub required: bool,
+    /// The value of the prop when it is not passed: the `default`, or `()`.
+    pub default: Dynamic,
+}
+
+/// Reads the declarations in the map passed to `props`, in the order of their names.
+pub fn parse_declarations(declarations: Map) -> Result<Vec<PropDeclaration>, String> {
+    let mut parsed = Vec::new();
+    for (name, declaration) in declarations {
+        let name = name.to_string();
+        let declaration = if declaration.is_string() {
+            let mut map = Map::new();
+            map.insert("type".into(), declaration);
+            map
+        } else {
+            declaration.try_cast::<Map>().ok_or_else(|| {
+                format!(
+                    "prop `{}` must be declared with a type, like \"string\", or a map",
+                    name
+                )
+            })?
+        };
+
+        let mut prop_type = PropType::Any;
+        let mut required = None;
+        let mut default = None;
+        for (key, value) in declaration {
+            match key.as_str() {
+                "type" => {
+                    let type_name = value.to_string();
+                    prop_type = PropType::parse(&type_name).ok_or_else(|| {
+                        format!(
+                            "`{}` is not a prop type, it must be one of string, int, float, \
+                             bool, array, map or any",
+                            type_name
+                        )
+                    })?;
+                }
+                "required" => {
+                    required = Some(value.as_bool().map_err(|_| {
+                        format!("`required` of prop `{}` must be true or false", name)
+                    })?)
+                }
+                "default" => default = Some(value),
+                key => {
+                    return Err(format!(
+                        "`{}` of prop `{}` is not one of `type`, `required` or `default`",
+                        key, name
+                    ))
+                }
+            }
+        }
+        let default = match default {
+            Some(default) => Some(prop_type.coerce(default, false).ok_or_else(|| {
+                format!(
+                    "the default of prop `{}` must be {}",
+                    name,
+                    prop_type.describe()
+                )
+            })?),
+            None => None,
+        };
+        parsed.push(PropDeclaration {
+            name,
+            prop_type,
+            required: required.unwrap_or(default.is_none()),
+            default: default.unwrap_or(Dynamic::UNIT),
+        });
+    }
+    Ok(parsed)
+}
+
+/// A value passed for a prop, for messages: strings as they are, other values by their type.
+pub fn describe_value(value: &Dynamic) -> String {
+    if let Ok(text) = value.clone().into_string() {
+        return format!("`{}`", text);
+    }
+    let described = if value.is_int() {
+        PropType::Int.describe()
+    } else if value.is_float() {
+        PropType::Float.describe()
+    } else if value.is_bool() {
+        PropType::Bool.describe()
+    } else if value.is_array() {
+        PropType::Array.describe()
+    } else if value.is_map() {
+        PropType::Map.describe()
+    } else if value.is_unit() {
+        "nothing"
+    } else {
+        value.type_name()
+    };
+    String::from(described)
+}
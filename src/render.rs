@@ -0,0 +1,2382 @@
+use html5ever::tendril::TendrilSink;
+use html5ever::{local_name, namespace_url, ns, QualName};
+use rhai::{Dynamic, Engine, EvalAltResult, Position, Scope};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::ops::{Range, RangeInclusive};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+use crate::attributes::Attribute;
+use crate::escape::{self, EscapeContext, Safe, UNSAFE_URL};
+use crate::props::{self, describe_value, PropDeclaration};
+use crate::scoped::{is_scope_attribute, scope_attribute};
+use crate::serializer::html_local_name;
+use crate::sink::{parse_fragment, parse_fragment_bytes_with_options, ParseOpts};
+use crate::span::Location;
+use crate::streaming::{Streamed, StreamingSerializer};
+use crate::tree::*;
+use crate::whitespace::WhitespacePolicy;
+
+/// The scope variable holding the `loop` object of the innermost loop.
+const LOOP_VARIABLE: &str = "__kolo_loop";
+
+/// The scope variable holding the value a `<kolo-switch>` is on while its cases are tried.
+const SWITCH_VARIABLE: &str = "__kolo_switch";
+
+/// A problem found while rendering a template, and where it is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+
+    /// The name of the template, see [`SourceFile::name`](crate::span::SourceFile::name).
+    pub file: Option<String>,
+
+    pub location: Option<Location>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>) -> Self {
+        Diagnostic {
+            message: message.into(),
+            file: None,
+            location: None,
+        }
+    }
+
+    /// Points the diagnostic at `offset` in the source of `node`.
+    pub fn at(mut self, node: &Node, offset: usize) -> Self {
+        if let Some(source) = node.source() {
+            self.file = source.name.clone();
+            self.location = Some(source.location(offset));
+        }
+        self
+    }
+
+    /// Points the diagnostic at the value of an attribute of `node` (or at its name, when it
+    /// has no value).
+    pub fn at_attribute(self, node: &Node, local_name: &str) -> Self {
+        match node.attribute_span(local_name) {
+            Some(attribute_span) => {
+                let span = attribute_span.value.unwrap_or(attribute_span.name);
+                self.at(node, span.start)
+            }
+            None => self.at_node(node),
+        }
+    }
+
+    /// Points the diagnostic `offset` bytes into the value of an attribute of `node`.
+    fn in_attribute(self, node: &Node, local_name: &str, offset: usize) -> Self {
+        match node
+            .attribute_span(local_name)
+            .and_then(|attribute_span| attribute_span.value)
+        {
+            Some(span) => self.at(node, span.start + offset),
+            None => self.at_attribute(node, local_name),
+        }
+    }
+
+    /// Points the diagnostic at the byte `index` of the text in a text node.
+    fn at_text(self, node: &Node, index: usize) -> Self {
+        match text_offset(node, index) {
+            Some(offset) => self.at(node, offset),
+            None => self,
+        }
+    }
+
+    /// Points the diagnostic at the start of `node`.
+    pub fn at_node(self, node: &Node) -> Self {
+        match node.span() {
+            Some(span) => self.at(node, span.start),
+            None => self,
+        }
+    }
+
+    /// Turns a rhai error in the code starting at `offset` in the source of `node` into a
+    /// diagnostic pointing at the same place in the template.
+    pub(crate) fn from_rhai(mut error: EvalAltResult, node: &Node, offset: Option<usize>) -> Self {
+        let position = error.position();
+        error.clear_position();
+        let diagnostic = Diagnostic::new(error.to_string());
+        match offset {
+            Some(offset) => diagnostic.at_rhai_position(node, offset, position),
+            None => diagnostic.at_node(node),
+        }
+    }
+
+    /// Points the diagnostic at `position` in rhai code that starts at `offset` in the source
+    /// of `node`.
+    fn at_rhai_position(self, node: &Node, offset: usize, position: Position) -> Self {
+        let mut diagnostic = self.at(node, offset);
+        if let (Some(location), Some(line)) = (&mut diagnostic.location, position.line()) {
+            let column = position.position().unwrap_or(1);
+            if line == 1 {
+                location.column += column - 1;
+            } else {
+                location.line += line - 1;
+                location.column = column;
+            }
+        }
+        diagnostic
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.file, &self.location) {
+            (Some(file), Some(location)) => {
+                write!(f, "{}:{}:{}: ", file, location.line, location.column)?
+            }
+            (None, Some(location)) => write!(f, "{}:{}: ", location.line, location.column)?,
+            (Some(file), None) => write!(f, "{}: ", file)?,
+            (None, None) => {}
+        }
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for Diagnostic {}
+
+/// Options for rendering templates.
+#[derive(Default)]
+pub struct RenderOpts {
+    /// What to do with whitespace next to `kolo-*` directives.
+    pub whitespace: WhitespacePolicy,
+
+    /// How `k-show` hides an element: with a `hidden` attribute when `None`, or by adding this
+    /// declaration (such as `display: none`) to its `style`.
+    pub hide_style: Option<String>,
+
+    /// A callback for problems that do not stop rendering, such as unused bindings.
+    pub on_warning: Option<Box<dyn FnMut(Diagnostic)>>,
+
+    /// Reads the template a `<kolo-import src>` points to, given its path resolved against the
+    /// name of the importing template. Reads the file at that path when `None`.
+    pub load_template: Option<LoadTemplate>,
+}
+
+/// Reads a template given its path, see [`RenderOpts::load_template`].
+pub type LoadTemplate = Box<dyn FnMut(&str) -> io::Result<Vec<u8>>>;
+
+/// A template imported with `<kolo-import src="path" as="name">`, to be used as `<name>`.
+#[derive(Clone)]
+struct Import {
+    name: String,
+    path: String,
+    template: Rc<Vec<u8>>,
+}
+
+/// The element the component being rendered is used as, for `props(...)` to check the props
+/// passed to it.
+struct Caller {
+    name: String,
+    /// Points at the element.
+    at: Diagnostic,
+    props: Vec<PassedProp>,
+    /// What `props(...)` found wrong with the props passed, pointing at the element.
+    error: Option<Diagnostic>,
+}
+
+/// A prop passed to a component as an attribute.
+struct PassedProp {
+    /// The variable it is bound to.
+    variable: String,
+    attribute: String,
+    /// Whether it is a plain attribute, whose value is a string, rather than a `:` binding.
+    from_attribute: bool,
+    /// Points at the attribute.
+    at: Diagnostic,
+}
+
+impl Caller {
+    /// Remembers the problem `message`, at `at`, returning the message for rhai.
+    fn fail(&mut self, at: &Diagnostic, message: String) -> String {
+        self.error = Some(Diagnostic {
+            message: message.clone(),
+            ..at.clone()
+        });
+        message
+    }
+}
+
+/// Renders templates: runs their `<script build>` blocks and expands their directives.
+///
+/// When an element has more than one directive attribute, `k-for` is expanded first, then
+/// `k-let`, `k-if` and `k-show` are evaluated on every copy, in that order, with the loop
+/// variables in scope.
+pub struct Renderer {
+    engine: Engine,
+    opts: RenderOpts,
+    /// The scope positions of the variables expressions have read since they were pushed.
+    used_variables: Rc<RefCell<HashSet<usize>>>,
+    /// The warning for each `<kolo-let>` and `k-let` binding, and whether any copy of it was
+    /// read.
+    bindings: Vec<(Diagnostic, bool)>,
+    /// The imports of each template being rendered, the innermost component last.
+    imports: Vec<Vec<Import>>,
+    /// The names of the templates being rendered, the innermost component last.
+    components: Vec<Option<String>>,
+    /// The templates loaded so far, by path.
+    templates: HashMap<String, Rc<Vec<u8>>>,
+    /// The element the component whose build scripts run is used as, if any.
+    caller: Rc<RefCell<Option<Caller>>>,
+    /// How many instances of each template with per-instance scoped styles there are so far,
+    /// by the template's scope attribute.
+    scope_instances: HashMap<String, usize>,
+    /// The scope attributes of the templates whose shared scoped styles are written already.
+    written_styles: HashSet<String>,
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Renderer::new()
+    }
+}
+
+impl Renderer {
+    pub fn new() -> Self {
+        Renderer::with_options(RenderOpts::default())
+    }
+
+    pub fn with_options(opts: RenderOpts) -> Self {
+        let mut engine = Engine::new();
+        engine
+            .register_type_with_name::<Safe>("safe")
+            .register_fn("safe", |value: Dynamic| Safe(plain(value)))
+            .register_fn("to_string", |safe: &mut Safe| safe.0.clone());
+        // `loop` is a rhai keyword, so the loop object is reached through custom syntax
+        engine.disable_symbol("loop");
+        engine
+            .register_custom_syntax(["loop"], false, |context, _inputs| {
+                context
+                    .scope()
+                    .get_value::<Dynamic>(LOOP_VARIABLE)
+                    .ok_or_else(|| "`loop` is only defined inside a loop".into())
+            })
+            .expect("`loop` is disabled, so it can start custom syntax");
+        let caller: Rc<RefCell<Option<Caller>>> = Rc::default();
+        let props_caller = caller.clone();
+        engine
+            .register_custom_syntax(
+                ["props", "(", "$expr$", ")"],
+                false,
+                move |context, inputs| {
+                    let declarations = context
+                        .eval_expression_tree(&inputs[0])?
+                        .try_cast::<rhai::Map>()
+                        .ok_or(
+                            "`props` takes a map of declarations, like `#{ title: \"string\" }`",
+                        )?;
+                    let declarations = props::parse_declarations(declarations)?;
+                    let mut caller = props_caller.borrow_mut();
+                    bind_props(context.scope_mut(), &declarations, caller.as_mut())?;
+                    Ok(Dynamic::UNIT)
+                },
+            )
+            .expect("`props` is not a rhai keyword");
+
+        let used_variables = Rc::new(RefCell::new(HashSet::new()));
+        let used = used_variables.clone();
+        #[allow(deprecated)] // only marked as volatile
+        engine.on_var(move |name, index, context| {
+            let scope = context.scope();
+            // `index` counts from the end of the scope, when rhai resolved it ahead of time.
+            // Otherwise this relies on `iter_raw` going from the newest variable to the oldest,
+            // as it does in rhai 1.26 (the version Cargo.toml asks for, earlier ones went the
+            // other way), so that a shadowed name is found where it resolves to.
+            let position = match index {
+                0 => scope
+                    .iter_raw()
+                    .position(|(variable, _, _)| variable == name)
+                    .map(|from_end| scope.len() - 1 - from_end),
+                _ => Some(scope.len() - index),
+            };
+            if let Some(position) = position {
+                used.borrow_mut().insert(position);
+            }
+            Ok(None)
+        });
+        Renderer {
+            engine,
+            opts,
+            used_variables,
+            bindings: Vec::new(),
+            imports: Vec::new(),
+            components: Vec::new(),
+            templates: HashMap::new(),
+            caller,
+            scope_instances: HashMap::new(),
+            written_styles: HashSet::new(),
+        }
+    }
+
+    /// The rhai engine scripts and expressions run in, to register functions and types.
+    ///
+    /// Its `on_var` callback is how unused `<kolo-let>` bindings are found, so replacing it
+    /// turns those warnings off.
+    pub fn engine_mut(&mut self) -> &mut Engine {
+        &mut self.engine
+    }
+
+    /// Renders a component in place.
+    pub fn render(&mut self, node_tree: &mut NodeTree) -> Result<(), Diagnostic> {
+        self.render_with_scope(node_tree, &mut Scope::new())
+    }
+
+    /// Renders a component in place, starting from the variables in `scope`.
+    ///
+    /// Every `<kolo-import>` is loaded first and removed, then every `<script build>` runs, in
+    /// document order, and is removed; the variables it defines are then available to all the
+    /// component's directives and interpolations. With a `<style scoped>`, the component's
+    /// elements are stamped with its scope attribute, see [`crate::scoped`]; the styles are
+    /// only written for the first instance of a component, unless they interpolate values.
+    pub fn render_with_scope(
+        &mut self,
+        node_tree: &mut NodeTree,
+        scope: &mut Scope<'static>,
+    ) -> Result<(), Diagnostic> {
+        let root = node_tree.root();
+        self.begin_template(node_tree)?;
+        let rendered = self.prepare_template(node_tree, scope).and_then(|scoping| {
+            self.render_children(node_tree, root, scope)?;
+            if let Some((attribute, shared)) = scoping {
+                let styles = node_tree.scoped_styles(root);
+                if shared && !styles.is_empty() && !self.written_styles.insert(attribute.clone()) {
+                    for style_ref in styles {
+                        node_tree.remove_subtree(style_ref);
+                    }
+                } else {
+                    node_tree.scope_styles(root, &attribute);
+                }
+            }
+            Ok(())
+        });
+        self.end_template(rendered)
+    }
+
+    /// Renders a template into `writer`, writing every part of it out as soon as it is
+    /// rendered rather than once the whole template is, see [`crate::streaming`].
+    ///
+    /// Plain elements are written as their children are rendered, and each item of a
+    /// `<kolo-list>` or `k-for` loop is written and freed as soon as it is rendered, so a long
+    /// list never exists in full. Everything else is rendered whole before any of it is
+    /// written: each item of a loop, elements with `k-let`, `k-if` or `k-html`, the other
+    /// `kolo-*` elements, components and the template's scoped styles. A loop inside one of
+    /// those is held in full, so where the output is large the loop should be the outer one.
+    ///
+    /// The tree is emptied in the process, and what was written before an error stays written.
+    pub fn render_to<W: Write>(
+        &mut self,
+        node_tree: &mut NodeTree,
+        writer: W,
+    ) -> Result<W, Diagnostic> {
+        let mut scope = Scope::new();
+        let root = node_tree.root();
+        let mut serializer = StreamingSerializer::new(writer);
+        self.begin_template(node_tree)?;
+        let rendered = self
+            .prepare_template(node_tree, &mut scope)
+            .and_then(|scoping| {
+                // the template's own styles are the only ones to be scoped once they are rendered
+                let styles = match &scoping {
+                    Some((attribute, _)) => {
+                        self.written_styles.insert(attribute.clone());
+                        node_tree.scoped_styles(root)
+                    }
+                    None => Vec::new(),
+                };
+                let stream = RefCell::new(Stream {
+                    renderer: &mut *self,
+                    scope: &mut scope,
+                    styles,
+                    attribute: scoping.map(|(attribute, _)| attribute),
+                    error: None,
+                });
+                let written = serializer.stream(node_tree, root, &mut |node_tree, node_ref| {
+                    Stream::expand(&stream, node_tree, node_ref)
+                });
+                match (stream.into_inner().error, written) {
+                    (Some(diagnostic), _) => Err(diagnostic),
+                    (None, written) => written.map_err(write_error),
+                }
+            });
+        self.end_template(rendered)?;
+        serializer.finish().map_err(write_error)
+    }
+
+    /// Starts rendering a template: applies the whitespace policy and loads its imports.
+    fn begin_template(&mut self, node_tree: &mut NodeTree) -> Result<(), Diagnostic> {
+        let root = node_tree.root();
+        if self.components.is_empty() {
+            // every page gets all the styles it uses
+            self.scope_instances.clear();
+            self.written_styles.clear();
+        }
+        node_tree.apply_whitespace_policy(root, self.opts.whitespace);
+        let imports = self.load_imports(node_tree)?;
+        self.imports.push(imports);
+        self.components
+            .push(node_tree.source().and_then(|source| source.name.clone()));
+        Ok(())
+    }
+
+    /// Runs the build scripts of a template begun with [`Renderer::begin_template`] and, with
+    /// scoped styles, stamps its elements, giving back its scope attribute and whether it is
+    /// shared, see [`Renderer::scope_of`].
+    fn prepare_template(
+        &mut self,
+        node_tree: &mut NodeTree,
+        scope: &mut Scope<'static>,
+    ) -> Result<Option<(String, bool)>, Diagnostic> {
+        let root = node_tree.root();
+        self.run_build_scripts(node_tree, scope)?;
+        let styles = node_tree.scoped_styles(root);
+        // stamped before rendering, so that only the template's own elements are
+        Ok((!styles.is_empty()).then(|| {
+            let (attribute, shared) = self.scope_of(node_tree, &styles);
+            node_tree.stamp_scope(root, &attribute);
+            (attribute, shared)
+        }))
+    }
+
+    /// Finishes rendering a template begun with [`Renderer::begin_template`], warning about
+    /// its unused bindings unless it failed.
+    fn end_template(&mut self, rendered: Result<(), Diagnostic>) -> Result<(), Diagnostic> {
+        self.imports.pop();
+        self.components.pop();
+        for (unused, used) in std::mem::take(&mut self.bindings) {
+            if !used && rendered.is_ok() {
+                self.warn(unused);
+            }
+        }
+        rendered
+    }
+
+    /// The scope attribute of a template with the scoped `styles`, and whether all its
+    /// instances share it.
+    ///
+    /// Styles that interpolate values can differ from one instance to the next, so then every
+    /// instance gets an attribute of its own: the template's, numbered.
+    fn scope_of(&mut self, node_tree: &NodeTree, styles: &[NodeRef]) -> (String, bool) {
+        let attribute = scope_attribute(node_tree.source().map(|source| &**source));
+        let interpolates = styles.iter().any(|&style_ref| {
+            node_tree.children(style_ref).any(|child_ref| {
+                let child = node_tree.get_node(child_ref);
+                child.as_text().is_some_and(|text| text.contains("{{"))
+            })
+        });
+        if !interpolates {
+            return (attribute, true);
+        }
+        let instances = self.scope_instances.entry(attribute.clone()).or_insert(0);
+        *instances += 1;
+        (format!("{}-{}", attribute, instances), false)
+    }
+
+    /// Fills the `<slot>` elements of a rendered component with the children of `caller_ref`,
+    /// the element of `caller` it was used as, see [`NodeTree::fill_slots`].
+    ///
+    /// The children are projected as they are, so they should be rendered first, with the
+    /// caller's variables. The ones the component has no slot for are warned about.
+    pub fn fill_slots(&mut self, component: &mut NodeTree, caller: &NodeTree, caller_ref: NodeRef) {
+        let root = component.root();
+        for unused_ref in component.fill_slots(root, caller, caller_ref) {
+            let node = caller.get_node(unused_ref);
+            let diagnostic = match node.as_element().and_then(|e| e.attributes.get("slot")) {
+                Some(name) => Diagnostic::new(format!(
+                    "there is no `<slot name=\"{}\">` for this content, so it is never shown",
+                    name
+                ))
+                .at_attribute(node, "slot"),
+                None => Diagnostic::new(
+                    "there is no default `<slot>` for this content, so it is never shown",
+                )
+                .at_node(node),
+            };
+            self.warn(diagnostic);
+        }
+    }
+
+    /// Loads the templates of the `<kolo-import>` elements of a template, and removes them.
+    fn load_imports(&mut self, node_tree: &mut NodeTree) -> Result<Vec<Import>, Diagnostic> {
+        let mut import_refs = Vec::new();
+        let mut stack = vec![node_tree.root()];
+        while let Some(node_ref) = stack.pop() {
+            let node = node_tree.get_node(node_ref);
+            if html_local_name(node).is_some_and(|local| &**local == "kolo-import") {
+                import_refs.push(node_ref);
+                continue;
+            }
+            if let Some(template_contents) = node.as_element().and_then(|e| e.template_contents) {
+                stack.push(template_contents);
+            }
+            stack.extend(node_tree.children(node_ref));
+        }
+
+        let mut imports: Vec<Import> = Vec::new();
+        for import_ref in import_refs {
+            let node = node_tree.get_node(import_ref);
+            // without its end tag, the rest of the template would end up inside it
+            let has_content = node_tree.children(import_ref).any(|child_ref| {
+                let child = node_tree.get_node(child_ref);
+                child.as_text().is_none_or(|text| !text.trim().is_empty())
+            });
+            if has_content {
+                return Err(Diagnostic::new(
+                    "`<kolo-import>` must be empty, and closed with `</kolo-import>`",
+                )
+                .at_node(node));
+            }
+            let src = required_attribute(node, "src")?;
+            let name = required_attribute(node, "as")?;
+            if !is_custom_element_name(name) {
+                return Err(Diagnostic::new(format!(
+                    "`as` must be a custom element name with a `-`, like `my-card`, not `{}`",
+                    name
+                ))
+                .at_attribute(node, "as"));
+            }
+            if imports.iter().any(|import| import.name == name) {
+                return Err(Diagnostic::new(format!("`<{}>` is already imported", name))
+                    .at_attribute(node, "as"));
+            }
+            let importer = node.source().and_then(|source| source.name.as_deref());
+            let path = resolve_path(importer, src);
+            let template = match self.templates.get(&path) {
+                Some(template) => template.clone(),
+                None => {
+                    let loaded = match &mut self.opts.load_template {
+                        Some(load_template) => load_template(&path),
+                        None => fs::read(&path),
+                    };
+                    let template = Rc::new(loaded.map_err(|error| {
+                        Diagnostic::new(format!("can not read `{}`: {}", path, error))
+                            .at_attribute(node, "src")
+                    })?);
+                    self.templates.insert(path.clone(), template.clone());
+                    template
+                }
+            };
+            imports.push(Import {
+                name: String::from(name),
+                path,
+                template,
+            });
+            node_tree.remove_subtree(import_ref);
+        }
+        Ok(imports)
+    }
+
+    fn run_build_scripts(
+        &mut self,
+        node_tree: &mut NodeTree,
+        scope: &mut Scope<'static>,
+    ) -> Result<(), Diagnostic> {
+        let mut scripts = Vec::new();
+        let mut stack = vec![node_tree.root()];
+        while let Some(node_ref) = stack.pop() {
+            let node = node_tree.get_node(node_ref);
+            if is_build_script(node) {
+                scripts.push(node_ref);
+                continue;
+            }
+            if let Some(template_contents) = node.as_element().and_then(|e| e.template_contents) {
+                stack.push(template_contents);
+            }
+            // reversed, so the stack pops them in document order
+            let children: Vec<NodeRef> = node_tree.children(node_ref).collect();
+            stack.extend(children.into_iter().rev());
+        }
+
+        for script_ref in scripts {
+            let mut code = String::new();
+            let mut code_node = script_ref;
+            for (i, child_ref) in node_tree.children(script_ref).enumerate() {
+                if let Some(text) = node_tree.get_node(child_ref).as_text() {
+                    if i == 0 {
+                        code_node = child_ref;
+                    }
+                    code.push_str(text);
+                }
+            }
+            if let Err(error) = self.engine.run_with_scope(scope, &code) {
+                // a problem with the props passed is reported where they are passed
+                let caller_error = self
+                    .caller
+                    .borrow_mut()
+                    .as_mut()
+                    .and_then(|caller| caller.error.take());
+                if let Some(diagnostic) = caller_error {
+                    return Err(diagnostic);
+                }
+                let node = node_tree.get_node(code_node);
+                let offset = node.span().map(|span| span.start);
+                return Err(Diagnostic::from_rhai(*error, node, offset));
+            }
+            node_tree.remove_subtree(script_ref);
+        }
+        Ok(())
+    }
+
+    fn render_children(
+        &mut self,
+        node_tree: &mut NodeTree,
+        parent_ref: NodeRef,
+        scope: &mut Scope<'static>,
+    ) -> Result<(), Diagnostic> {
+        // collected first, since rendering a child can replace it, and the branches of a
+        // `<kolo-if>` take their siblings away with them
+        let children: Vec<NodeRef> = node_tree.children(parent_ref).collect();
+        for child_ref in children {
+            if node_tree.contains(child_ref) {
+                self.render_node(node_tree, child_ref, scope)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn render_node(
+        &mut self,
+        node_tree: &mut NodeTree,
+        node_ref: NodeRef,
+        scope: &mut Scope<'static>,
+    ) -> Result<(), Diagnostic> {
+        if let Some(element) = node_tree.get_node(node_ref).as_element() {
+            // `k-for` goes first, so the others are evaluated for every copy, and `k-let`
+            // next, so `k-if` and `k-show` can use its variable
+            if element.attributes.contains("k-for") {
+                return self.render_for(node_tree, node_ref, scope);
+            }
+            if element.attributes.contains("k-let") {
+                return self.render_k_let(node_tree, node_ref, scope);
+            }
+            if element.attributes.contains("k-if") {
+                return self.render_k_if(node_tree, node_ref, scope);
+            }
+            if element.attributes.contains("k-show") {
+                self.render_show(node_tree, node_ref, scope)?;
+            }
+        }
+        let node = node_tree.get_node(node_ref);
+        if let Some(import) = html_local_name(node).and_then(|local| self.import(local)) {
+            return self.render_component(node_tree, node_ref, import, scope);
+        }
+        match html_local_name(node).map(|local| &**local) {
+            Some("kolo-list") => self.render_list(node_tree, node_ref, scope),
+            Some("kolo-if") => self.render_if(node_tree, node_ref, scope),
+            Some(local @ "kolo-else-if") | Some(local @ "kolo-else") => {
+                Err(Diagnostic::new(format!(
+                    "`<{}>` must follow a `<kolo-if>` or `<kolo-else-if>`",
+                    local
+                ))
+                .at_node(node))
+            }
+            Some("kolo-switch") => self.render_switch(node_tree, node_ref, scope),
+            Some("kolo-let") => self.render_let(node_tree, node_ref, scope),
+            Some(local @ "kolo-case") | Some(local @ "kolo-default") => Err(Diagnostic::new(
+                format!("`<{}>` must be inside a `<kolo-switch>`", local),
+            )
+            .at_node(node)),
+            _ if node.as_text().is_some() => self.render_text(node_tree, node_ref, scope),
+            _ => match node.as_element() {
+                Some(element) => {
+                    let template_contents = element.template_contents;
+                    let has_html = element.attributes.contains("k-html");
+                    if has_html {
+                        self.render_html(node_tree, node_ref, scope)?;
+                    }
+                    self.render_attributes(node_tree, node_ref, scope)?;
+                    self.render_bindings(node_tree, node_ref, scope)?;
+                    match template_contents {
+                        _ if has_html => Ok(()),
+                        Some(template_contents) => {
+                            self.render_children(node_tree, template_contents, scope)
+                        }
+                        None => self.render_children(node_tree, node_ref, scope),
+                    }
+                }
+                None => self.render_children(node_tree, node_ref, scope),
+            },
+        }
+    }
+
+    /// The import of the template being rendered named `local`, if any.
+    fn import(&self, local: &str) -> Option<Import> {
+        self.imports
+            .last()?
+            .iter()
+            .find(|import| import.name == local)
+            .cloned()
+    }
+
+    /// Replaces an element an imported component is used as with the rendered component.
+    ///
+    /// The component's template is rendered with the element's attributes as variables (with
+    /// `-` in their names turned into `_`): the values of `:name` bindings as they are, and
+    /// the others as strings, until the component's `props(...)` checks and converts them
+    /// (see [`crate::props`]). The element's children are rendered here and fill the
+    /// component's slots, see [`Renderer::fill_slots`].
+    fn render_component(
+        &mut self,
+        node_tree: &mut NodeTree,
+        element_ref: NodeRef,
+        import: Import,
+        scope: &mut Scope<'static>,
+    ) -> Result<(), Diagnostic> {
+        let node = node_tree.get_node(element_ref);
+        if let Some(start) = self
+            .components
+            .iter()
+            .position(|name| name.as_deref() == Some(&*import.path))
+        {
+            let mut cycle: Vec<&str> = self.components[start..]
+                .iter()
+                .map(|name| name.as_deref().unwrap_or("?"))
+                .collect();
+            cycle.push(&import.path);
+            return Err(Diagnostic::new(format!(
+                "`<{}>` is used inside itself: {}",
+                import.name,
+                cycle.join(" → ")
+            ))
+            .at_node(node));
+        }
+
+        self.render_attributes(node_tree, element_ref, scope)?;
+        let node = node_tree.get_node(element_ref);
+        let mut props = Scope::new();
+        let mut caller = Caller {
+            name: import.name.clone(),
+            at: Diagnostic::new("").at_node(node),
+            props: Vec::new(),
+            error: None,
+        };
+        let mut scope_attributes = Vec::new();
+        for (name, attribute) in &node.as_element().unwrap().attributes.map {
+            // the caller's scoped styles reach the component's root elements
+            if name.ns == ns!() && is_scope_attribute(&name.local) {
+                scope_attributes.push(name.local.clone());
+                continue;
+            }
+            let (prop, value) = match name.local.strip_prefix(':') {
+                Some(prop) => (
+                    prop,
+                    self.eval_in_attribute(node, &name.local, &attribute.value, 0, scope)?,
+                ),
+                None => (&*name.local, Dynamic::from(attribute.value.clone())),
+            };
+            let variable = prop.replace('-', "_");
+            if name.ns != ns!() || !is_identifier(&variable) {
+                return Err(Diagnostic::new(format!(
+                    "`{}` can not be passed to `<{}>`, since `{}` is not a variable name",
+                    name.local, import.name, variable
+                ))
+                .at_attribute(node, &name.local));
+            }
+            caller.props.push(PassedProp {
+                variable: variable.clone(),
+                attribute: name.local.to_string(),
+                from_attribute: !name.local.starts_with(':'),
+                at: Diagnostic::new("").at_attribute(node, &name.local),
+            });
+            props.push_dynamic(variable, value);
+        }
+        self.render_children(node_tree, element_ref, scope)?;
+
+        let mut component = parse_fragment_bytes_with_options(
+            ParseOpts {
+                source_name: Some(import.path.clone()),
+                ..Default::default()
+            },
+            QualName::new(None, ns!(html), local_name!("body")),
+            Vec::new(),
+            &import.template,
+        );
+        // the component's bindings are positioned in its own scope
+        let bindings = std::mem::take(&mut self.bindings);
+        let used_variables = std::mem::take(&mut *self.used_variables.borrow_mut());
+        *self.caller.borrow_mut() = Some(caller);
+        let rendered = self.render_with_scope(&mut component, &mut props);
+        self.caller.borrow_mut().take();
+        self.bindings = bindings;
+        *self.used_variables.borrow_mut() = used_variables;
+        rendered?;
+        self.fill_slots(&mut component, node_tree, element_ref);
+
+        for node_ref in fragment_nodes(&component) {
+            let copy_ref = node_tree.import_subtree(&component, node_ref);
+            if let Some(element) = node_tree.get_node_mut(copy_ref).as_element_mut() {
+                for scope_attribute in &scope_attributes {
+                    element
+                        .attributes
+                        .insert(scope_attribute.clone(), String::new());
+                }
+            }
+            node_tree.append_before_sibling(element_ref, copy_ref);
+        }
+        node_tree.remove_subtree(element_ref);
+        Ok(())
+    }
+
+    /// Replaces the `{{ expression }}` interpolations in a text node with their values, which
+    /// are escaped like any other text, or for JavaScript or CSS in a `<script>` or `<style>`.
+    /// The values of `{{{ expression }}}`, and [`Safe`] values outside of those, are parsed
+    /// as HTML instead, and their nodes spliced in.
+    ///
+    /// Like the `-` markers of directive tags (see [`WhitespacePolicy`]), a `-` right inside
+    /// the braces, as in `{{- expression -}}`, trims the whitespace on that side of them.
+    fn render_text(
+        &mut self,
+        node_tree: &mut NodeTree,
+        text_ref: NodeRef,
+        scope: &mut Scope<'static>,
+    ) -> Result<(), Diagnostic> {
+        let node = node_tree.get_node(text_ref);
+        let text = node.as_text().unwrap();
+        if !text.contains("{{") {
+            return Ok(());
+        }
+        let segments = segments(text)
+            .map_err(|index| Diagnostic::new("`{{` is never closed").at_text(node, index))?;
+        let parent = node_tree
+            .parent(text_ref)
+            .and_then(|parent_ref| node_tree.get_node(parent_ref).as_element());
+        let context = EscapeContext::text(parent.map_or("", |element| &element.name.local));
+
+        // the text, cut wherever raw HTML goes
+        let mut pieces = vec![String::new()];
+        let mut html = Vec::new();
+        for segment in segments {
+            match segment {
+                Segment::Text(text) => pieces.last_mut().unwrap().push_str(text),
+                Segment::Expression(code, index, raw) => {
+                    let offset = text_offset(node, index);
+                    let value = self.eval_at(node, code, offset, scope)?;
+                    if raw || (value.is::<Safe>() && context == EscapeContext::Html) {
+                        html.push(plain(value));
+                        pieces.push(String::new());
+                    } else {
+                        let text = self.escape(value, context, node, offset);
+                        pieces.last_mut().unwrap().push_str(&text);
+                    }
+                }
+            }
+        }
+        if html.is_empty() {
+            *node_tree.get_node_mut(text_ref).as_text_mut().unwrap() = pieces.pop().unwrap();
+            return Ok(());
+        }
+
+        let context = parent
+            .map(|element| element.name.clone())
+            .unwrap_or_else(|| QualName::new(None, ns!(html), local_name!("body")));
+        let mut html = html.into_iter();
+        for piece in pieces {
+            if !piece.is_empty() {
+                let piece_ref = node_tree.new_node(NodeData::Text(piece));
+                node_tree.append_before_sibling(text_ref, piece_ref);
+            }
+            if let Some(html) = html.next() {
+                let fragment = parse_html_fragment(&html, context.clone());
+                for node_ref in fragment_nodes(&fragment) {
+                    let copy_ref = node_tree.import_subtree(&fragment, node_ref);
+                    node_tree.append_before_sibling(text_ref, copy_ref);
+                }
+            }
+        }
+        node_tree.remove_subtree(text_ref);
+        Ok(())
+    }
+
+    /// Replaces the `{{ expression }}` interpolations in the attribute values of an element,
+    /// other than `:name` bindings, escaping them for the attribute.
+    fn render_attributes(
+        &mut self,
+        node_tree: &mut NodeTree,
+        element_ref: NodeRef,
+        scope: &mut Scope<'static>,
+    ) -> Result<(), Diagnostic> {
+        let node = node_tree.get_node(element_ref);
+        let mut values = Vec::new();
+        for (name, attribute) in &node.as_element().unwrap().attributes.map {
+            // the values of bindings are expressions
+            if !attribute.value.contains("{{") || name.local.starts_with(':') {
+                continue;
+            }
+            let local_name = &*name.local;
+            let segments = segments(&attribute.value).map_err(|index| {
+                Diagnostic::new("`{{` is never closed").in_attribute(node, local_name, index)
+            })?;
+            let mut value = String::new();
+            for segment in segments {
+                match segment {
+                    Segment::Text(text) => value.push_str(text),
+                    Segment::Expression(code, index, false) => {
+                        let interpolated =
+                            self.eval_in_attribute(node, local_name, code, index, scope)?;
+                        let context = EscapeContext::attribute(local_name, value.is_empty());
+                        let offset = attribute_offset(node, local_name, index);
+                        value.push_str(&self.escape(interpolated, context, node, offset));
+                    }
+                    Segment::Expression(_, index, true) => {
+                        return Err(Diagnostic::new(
+                            "`{{{ }}}` can only be used in text, attribute values are always \
+                             escaped",
+                        )
+                        .in_attribute(node, local_name, index))
+                    }
+                }
+            }
+            values.push((name.clone(), value));
+        }
+        if values.is_empty() {
+            // taking the element mutably would mark it modified
+            return Ok(());
+        }
+
+        let element = node_tree
+            .get_node_mut(element_ref)
+            .as_element_mut()
+            .unwrap();
+        for (name, value) in values {
+            element.attributes.map.get_mut(&name).unwrap().value = value;
+        }
+        Ok(())
+    }
+
+    /// Evaluates the `:name="expression"` bindings of an element, setting or removing the
+    /// `name` attributes.
+    ///
+    /// `true` sets a boolean attribute, and `false` or `()` removes the attribute; any other
+    /// value becomes its value, escaped like an interpolated one: URLs are checked, and
+    /// `:on*` and `:style` values escaped as JavaScript and CSS. For `:class` an array of
+    /// class names, or a map from class names to booleans, adds to the static `class`, and
+    /// for `:style` a map of properties, or an array of declarations, adds to the static
+    /// `style`, with each property and value escaped.
+    fn render_bindings(
+        &mut self,
+        node_tree: &mut NodeTree,
+        element_ref: NodeRef,
+        scope: &mut Scope<'static>,
+    ) -> Result<(), Diagnostic> {
+        let node = node_tree.get_node(element_ref);
+        let attributes = &node.as_element().unwrap().attributes;
+        let mut bound = Vec::new();
+        for (name, attribute) in &attributes.map {
+            let target = match name.local.strip_prefix(':') {
+                Some(target) if name.ns == ns!() => target,
+                _ => continue,
+            };
+            if target.is_empty() {
+                return Err(
+                    Diagnostic::new("`:` needs the name of the attribute to set after it")
+                        .at_attribute(node, &name.local),
+                );
+            }
+            let value = self.eval_in_attribute(node, &name.local, &attribute.value, 0, scope)?;
+            let value = if value.is_unit() || value.is_bool() || value.is_array() || value.is_map()
+            {
+                bound_value(target, value, attributes.get_qualified(target)).map_err(
+                    |type_name| {
+                        Diagnostic::new(format!("`:{}` can not be set to {}", target, type_name))
+                            .at_attribute(node, &name.local)
+                    },
+                )?
+            } else {
+                let offset = attribute_offset(node, &name.local, 0);
+                let context = EscapeContext::attribute(target, true);
+                Some(self.escape(value, context, node, offset))
+            };
+            bound.push((name.local.clone(), String::from(target), value));
+        }
+        if bound.is_empty() {
+            return Ok(());
+        }
+
+        let element = node_tree
+            .get_node_mut(element_ref)
+            .as_element_mut()
+            .unwrap();
+        for (binding, target, value) in bound {
+            element.attributes.remove(binding);
+            match value {
+                Some(value) => element.attributes.insert_qualified(&target, value),
+                None => element.attributes.remove_qualified(&target),
+            };
+        }
+        Ok(())
+    }
+
+    /// Replaces the children of an element with a `k-html="expression"` attribute with the
+    /// value of the expression, parsed as HTML, and takes the attribute off.
+    fn render_html(
+        &mut self,
+        node_tree: &mut NodeTree,
+        element_ref: NodeRef,
+        scope: &mut Scope<'static>,
+    ) -> Result<(), Diagnostic> {
+        let node = node_tree.get_node(element_ref);
+        let html = self.eval_attribute(node, "k-html", scope)?.to_string();
+        let element = node_tree
+            .get_node_mut(element_ref)
+            .as_element_mut()
+            .unwrap();
+        element.attributes.remove("k-html");
+        let context = element.name.clone();
+
+        let children: Vec<NodeRef> = node_tree.children(element_ref).collect();
+        for child_ref in children {
+            node_tree.remove_subtree(child_ref);
+        }
+        let fragment = parse_html_fragment(&html, context);
+        for node_ref in fragment_nodes(&fragment) {
+            let copy_ref = node_tree.import_subtree(&fragment, node_ref);
+            node_tree.append(element_ref, copy_ref);
+        }
+        Ok(())
+    }
+
+    /// Expands `<kolo-list for="item" in="expression">`: its children are repeated for every
+    /// item, with `item` bound to it, and the `kolo-list` element itself is left out.
+    /// `for="(key, value)"` goes through the keys and values of a map (or the items and
+    /// indexes of anything else). The children of a `<kolo-empty>` child are rendered
+    /// instead when there are no items.
+    fn render_list(
+        &mut self,
+        node_tree: &mut NodeTree,
+        list_ref: NodeRef,
+        scope: &mut Scope<'static>,
+    ) -> Result<(), Diagnostic> {
+        let (mut list, body) = self.start_list(node_tree, list_ref, scope)?;
+        while self.render_item(node_tree, list_ref, &body, &mut list, scope)? {}
+        node_tree.remove_subtree(list_ref);
+        Ok(())
+    }
+
+    /// Starts expanding a `<kolo-list>`, giving back its loop and the children to repeat for
+    /// every item. Without items, the children of its `<kolo-empty>` are rendered right away.
+    fn start_list(
+        &mut self,
+        node_tree: &mut NodeTree,
+        list_ref: NodeRef,
+        scope: &mut Scope<'static>,
+    ) -> Result<(Loop, Vec<NodeRef>), Diagnostic> {
+        let node = node_tree.get_node(list_ref);
+        let pattern = required_attribute(node, "for")?;
+        let binding = LoopBinding::parse(pattern).ok_or_else(|| {
+            Diagnostic::new(format!(
+                "`for` must be a variable name or `(key, value)`, not `{}`",
+                pattern
+            ))
+            .at_attribute(node, "for")
+        })?;
+        let iterable = self.eval_attribute(node, "in", scope)?;
+        let items = items(iterable).map_err(|type_name| {
+            Diagnostic::new(format!(
+                "`in` must be an array, map, range or string, not {}",
+                type_name
+            ))
+            .at_attribute(node, "in")
+        })?;
+
+        let (empty, body): (Vec<NodeRef>, Vec<NodeRef>) =
+            node_tree.children(list_ref).partition(|&child_ref| {
+                html_local_name(node_tree.get_node(child_ref))
+                    .is_some_and(|local| &**local == "kolo-empty")
+            });
+        if items.entries.is_empty() {
+            let empty_body: Vec<NodeRef> = empty
+                .iter()
+                .flat_map(|&empty_ref| node_tree.children(empty_ref))
+                .collect();
+            for child_ref in empty_body {
+                let clone_ref = node_tree.clone_subtree(child_ref);
+                node_tree.append_before_sibling(list_ref, clone_ref);
+                self.render_node(node_tree, clone_ref, scope)?;
+            }
+        }
+        Ok((Loop::new(binding, items, scope), body))
+    }
+
+    /// Expands `<kolo-if condition="expression">` and the `<kolo-else-if condition>` and
+    /// `<kolo-else>` elements right after it (with only whitespace in between): the children
+    /// of the first branch whose condition is true take the place of them all.
+    fn render_if(
+        &mut self,
+        node_tree: &mut NodeTree,
+        if_ref: NodeRef,
+        scope: &mut Scope<'static>,
+    ) -> Result<(), Diagnostic> {
+        let mut chain = vec![if_ref];
+        let mut sibling_ref = node_tree.get_node(if_ref).next_sibling();
+        while let Some(node_ref) = sibling_ref {
+            let node = node_tree.get_node(node_ref);
+            let is_else = match html_local_name(node).map(|local| &**local) {
+                Some("kolo-else-if") => false,
+                Some("kolo-else") => true,
+                _ if node.as_text().is_some_and(|text| text.trim().is_empty()) => {
+                    sibling_ref = node.next_sibling();
+                    continue;
+                }
+                _ => break,
+            };
+            // the whitespace between branches goes with them
+            while let Some(&last_ref) = chain.last() {
+                let next_ref = node_tree.get_node(last_ref).next_sibling().unwrap();
+                if next_ref == node_ref {
+                    break;
+                }
+                chain.push(next_ref);
+            }
+            chain.push(node_ref);
+            if is_else {
+                break;
+            }
+            sibling_ref = node.next_sibling();
+        }
+
+        let mut taken = None;
+        for &branch_ref in &chain {
+            let node = node_tree.get_node(branch_ref);
+            let is_taken = match html_local_name(node).map(|local| &**local) {
+                Some("kolo-if") | Some("kolo-else-if") => {
+                    self.eval_condition(node, "condition", scope)?
+                }
+                Some("kolo-else") => true,
+                // whitespace between branches
+                _ => false,
+            };
+            if is_taken {
+                taken = Some(branch_ref);
+                break;
+            }
+        }
+
+        if let Some(taken_ref) = taken {
+            // all moved first, so a `<kolo-if>` among them finds its branches
+            let body: Vec<NodeRef> = node_tree.children(taken_ref).collect();
+            for &child_ref in &body {
+                node_tree.remove_from_parent(child_ref);
+                node_tree.append_before_sibling(if_ref, child_ref);
+            }
+            for child_ref in body {
+                if node_tree.contains(child_ref) {
+                    self.render_node(node_tree, child_ref, scope)?;
+                }
+            }
+        }
+        for branch_ref in chain {
+            node_tree.remove_subtree(branch_ref);
+        }
+        Ok(())
+    }
+
+    /// Expands `<kolo-switch on="expression">`: the children of the first `<kolo-case>` whose
+    /// `value` matches take its place, or those of the `<kolo-default>` when none does.
+    ///
+    /// A `value` is a rhai `switch` case, so it can list several values (`"a" | "b"`), be a
+    /// range (`1..10`) or have a guard (`0 if user.admin`). Values already handled by an
+    /// earlier case, and cases after the `<kolo-default>`, are warned about.
+    fn render_switch(
+        &mut self,
+        node_tree: &mut NodeTree,
+        switch_ref: NodeRef,
+        scope: &mut Scope<'static>,
+    ) -> Result<(), Diagnostic> {
+        let node = node_tree.get_node(switch_ref);
+        let on = self.eval_attribute(node, "on", scope)?;
+
+        let mut cases = Vec::new();
+        let mut default_ref = None;
+        let mut handled = Vec::new();
+        for child_ref in node_tree.children(switch_ref) {
+            let child = node_tree.get_node(child_ref);
+            match html_local_name(child).map(|local| &**local) {
+                Some(local @ "kolo-case") | Some(local @ "kolo-default")
+                    if default_ref.is_some() =>
+                {
+                    self.warn(
+                        Diagnostic::new(format!(
+                            "`<{}>` after the `<kolo-default>` is never used",
+                            local
+                        ))
+                        .at_node(child),
+                    );
+                }
+                Some("kolo-case") => {
+                    let pattern = required_attribute(child, "value")?;
+                    let values = case_values(pattern);
+                    if has_guard(pattern) {
+                        // a guard can make the same value mean something else
+                        cases.push((child_ref, String::from(pattern)));
+                        continue;
+                    }
+                    let mut unhandled = Vec::new();
+                    for value in values.iter().copied() {
+                        if !handled.contains(&value) {
+                            handled.push(value);
+                            unhandled.push(value);
+                        } else if values.len() > 1 {
+                            self.warn(
+                                Diagnostic::new(format!(
+                                    "`{}` is already handled earlier in the `<kolo-switch>`",
+                                    value
+                                ))
+                                .at_attribute(child, "value"),
+                            );
+                        }
+                    }
+                    if unhandled.is_empty() {
+                        self.warn(
+                            Diagnostic::new(
+                                "`<kolo-case>` is never used, its values are all handled by \
+                                 earlier cases",
+                            )
+                            .at_attribute(child, "value"),
+                        );
+                    } else if unhandled.len() == values.len() {
+                        cases.push((child_ref, String::from(pattern)));
+                    } else {
+                        cases.push((child_ref, unhandled.join(" | ")));
+                    }
+                }
+                Some("kolo-default") => default_ref = Some(child_ref),
+                _ if child.as_comment().is_some()
+                    || child.as_text().is_some_and(|text| text.trim().is_empty()) => {}
+                _ => {
+                    return Err(Diagnostic::new(
+                        "only `<kolo-case>` and `<kolo-default>` can be in a `<kolo-switch>`",
+                    )
+                    .at_node(child))
+                }
+            }
+        }
+
+        let scope_len = scope.len();
+        scope.push_dynamic(SWITCH_VARIABLE, on);
+        let mut taken = Ok(default_ref);
+        for (case_ref, pattern) in cases {
+            match self.eval_case(node_tree.get_node(case_ref), &pattern, scope) {
+                Ok(false) => {}
+                Ok(true) => {
+                    taken = Ok(Some(case_ref));
+                    break;
+                }
+                Err(error) => {
+                    taken = Err(error);
+                    break;
+                }
+            }
+        }
+        scope.rewind(scope_len);
+
+        if let Some(taken_ref) = taken? {
+            let body: Vec<NodeRef> = node_tree.children(taken_ref).collect();
+            for &child_ref in &body {
+                node_tree.remove_from_parent(child_ref);
+                node_tree.append_before_sibling(switch_ref, child_ref);
+            }
+            for child_ref in body {
+                if node_tree.contains(child_ref) {
+                    self.render_node(node_tree, child_ref, scope)?;
+                }
+            }
+        }
+        node_tree.remove_subtree(switch_ref);
+        Ok(())
+    }
+
+    /// Whether the value of the enclosing `<kolo-switch>` matches the `pattern` of a
+    /// `<kolo-case>`.
+    fn eval_case(
+        &self,
+        case: &Node,
+        pattern: &str,
+        scope: &mut Scope<'static>,
+    ) -> Result<bool, Diagnostic> {
+        let prefix = format!("switch {} {{ ", SWITCH_VARIABLE);
+        let code = format!("{}{} => true, _ => false }}", prefix, pattern);
+        self.engine
+            .eval_expression_with_scope::<bool>(scope, &code)
+            .map_err(|mut error| {
+                // the position is made relative to the `value` attribute
+                let position = error.position();
+                if let (Some(1), Some(column)) = (position.line(), position.position()) {
+                    let column = column.saturating_sub(prefix.len()).max(1);
+                    error.set_position(Position::new(1, column as u16));
+                }
+                let offset = case
+                    .attribute_span("value")
+                    .and_then(|attribute_span| attribute_span.value)
+                    .map(|span| span.start);
+                Diagnostic::from_rhai(*error, case, offset)
+            })
+    }
+
+    /// Expands `<kolo-let name="variable" value="expression">`: its children take its place,
+    /// rendered with `variable` bound to the value of the expression. The binding shadows any
+    /// variable with the same name, and is gone after the element.
+    fn render_let(
+        &mut self,
+        node_tree: &mut NodeTree,
+        let_ref: NodeRef,
+        scope: &mut Scope<'static>,
+    ) -> Result<(), Diagnostic> {
+        let node = node_tree.get_node(let_ref);
+        let name = required_attribute(node, "name")?;
+        if !is_identifier(name) {
+            return Err(
+                Diagnostic::new(format!("`name` must be a variable name, not `{}`", name))
+                    .at_attribute(node, "name"),
+            );
+        }
+        let name = String::from(name);
+        let value = self.eval_attribute(node, "value", scope)?;
+        let unused =
+            Diagnostic::new(format!("`{}` is never used", name)).at_attribute(node, "name");
+
+        let body: Vec<NodeRef> = node_tree.children(let_ref).collect();
+        for &child_ref in &body {
+            node_tree.remove_from_parent(child_ref);
+            node_tree.append_before_sibling(let_ref, child_ref);
+        }
+        node_tree.remove_subtree(let_ref);
+        let index = self.bind(scope, &name, value);
+        for child_ref in body {
+            if node_tree.contains(child_ref) {
+                self.render_node(node_tree, child_ref, scope)?;
+            }
+        }
+        self.unbind(scope, index, unused);
+        Ok(())
+    }
+
+    /// Renders an element with a `k-let="variable = expression"` attribute (taken off) with
+    /// `variable` bound, like `<kolo-let>`.
+    fn render_k_let(
+        &mut self,
+        node_tree: &mut NodeTree,
+        element_ref: NodeRef,
+        scope: &mut Scope<'static>,
+    ) -> Result<(), Diagnostic> {
+        let node = node_tree.get_node(element_ref);
+        let binding = required_attribute(node, "k-let")?;
+        let (name, expression_start) = parse_let(binding).ok_or_else(|| {
+            Diagnostic::new(format!(
+                "`k-let` must be `variable = expression`, not `{}`",
+                binding
+            ))
+            .at_attribute(node, "k-let")
+        })?;
+        let value = self.eval_in_attribute(
+            node,
+            "k-let",
+            &binding[expression_start..],
+            expression_start,
+            scope,
+        )?;
+        let unused =
+            Diagnostic::new(format!("`{}` is never used", name)).at_attribute(node, "k-let");
+        let name = String::from(name);
+
+        let element = node_tree
+            .get_node_mut(element_ref)
+            .as_element_mut()
+            .unwrap();
+        element.attributes.remove("k-let");
+        let index = self.bind(scope, &name, value);
+        self.render_node(node_tree, element_ref, scope)?;
+        self.unbind(scope, index, unused);
+        Ok(())
+    }
+
+    /// Pushes a `<kolo-let>` or `k-let` binding, returning its position in the scope.
+    fn bind(&mut self, scope: &mut Scope<'static>, name: &str, value: Dynamic) -> usize {
+        let index = scope.len();
+        // reads of whatever was at this position before do not count
+        self.used_variables
+            .borrow_mut()
+            .retain(|&used| used < index);
+        scope.push_dynamic(name, value);
+        index
+    }
+
+    /// Pops the binding at `index`, remembering whether it was read. `unused` is the warning
+    /// for when no copy of the binding (in a loop) ever is.
+    fn unbind(&mut self, scope: &mut Scope<'static>, index: usize, unused: Diagnostic) {
+        let used = self.used_variables.borrow().contains(&index);
+        scope.rewind(index);
+        match self
+            .bindings
+            .iter_mut()
+            .find(|(diagnostic, _)| *diagnostic == unused)
+        {
+            Some((_, any_used)) => *any_used |= used,
+            None => self.bindings.push((unused, used)),
+        }
+    }
+
+    /// Keeps an element with a `k-if="expression"` attribute (without the attribute) when the
+    /// expression is true, and removes it otherwise.
+    fn render_k_if(
+        &mut self,
+        node_tree: &mut NodeTree,
+        element_ref: NodeRef,
+        scope: &mut Scope<'static>,
+    ) -> Result<(), Diagnostic> {
+        let node = node_tree.get_node(element_ref);
+        if self.eval_condition(node, "k-if", scope)? {
+            let element = node_tree
+                .get_node_mut(element_ref)
+                .as_element_mut()
+                .unwrap();
+            element.attributes.remove("k-if");
+            self.render_node(node_tree, element_ref, scope)
+        } else {
+            node_tree.remove_subtree(element_ref);
+            Ok(())
+        }
+    }
+
+    /// Takes the `k-show="expression"` attribute off an element, hiding it with `hidden` (or
+    /// [`RenderOpts::hide_style`]) when the expression is false.
+    fn render_show(
+        &mut self,
+        node_tree: &mut NodeTree,
+        element_ref: NodeRef,
+        scope: &mut Scope<'static>,
+    ) -> Result<(), Diagnostic> {
+        let node = node_tree.get_node(element_ref);
+        let shown = self.eval_condition(node, "k-show", scope)?;
+        let element = node_tree
+            .get_node_mut(element_ref)
+            .as_element_mut()
+            .unwrap();
+        element.attributes.remove("k-show");
+        if shown {
+            return Ok(());
+        }
+        match &self.opts.hide_style {
+            Some(declaration) => {
+                let style = element.attributes.entry("style").or_insert(Attribute {
+                    prefix: None,
+                    value: String::new(),
+                });
+                style.value = append_declaration(&style.value, declaration);
+            }
+            None => {
+                element.attributes.insert("hidden", String::new());
+            }
+        }
+        Ok(())
+    }
+
+    /// Expands an element with a `k-for="item in expression"` attribute: the element is
+    /// repeated for every item, without the attribute. `(item, index) in expression` also
+    /// binds the index, and `(key, value) in expression` the keys of a map.
+    fn render_for(
+        &mut self,
+        node_tree: &mut NodeTree,
+        element_ref: NodeRef,
+        scope: &mut Scope<'static>,
+    ) -> Result<(), Diagnostic> {
+        let mut list = self.start_for(node_tree, element_ref, scope)?;
+        while self.render_item(node_tree, element_ref, &[element_ref], &mut list, scope)? {}
+        node_tree.remove_subtree(element_ref);
+        Ok(())
+    }
+
+    /// Starts expanding an element with a `k-for`, giving back its loop.
+    fn start_for(
+        &mut self,
+        node_tree: &mut NodeTree,
+        element_ref: NodeRef,
+        scope: &mut Scope<'static>,
+    ) -> Result<Loop, Diagnostic> {
+        let node = node_tree.get_node(element_ref);
+        let value = required_attribute(node, "k-for")?;
+        let (binding, expression_start) = parse_for(value).ok_or_else(|| {
+            Diagnostic::new(format!(
+                "`k-for` must be `item in items`, `(item, index) in items` or \
+                 `(key, value) in map`, not `{}`",
+                value
+            ))
+            .at_attribute(node, "k-for")
+        })?;
+        let iterable = self.eval_in_attribute(
+            node,
+            "k-for",
+            &value[expression_start..],
+            expression_start,
+            scope,
+        )?;
+        let items = items(iterable).map_err(|type_name| {
+            Diagnostic::new(format!(
+                "`k-for` must go through an array, map, range or string, not {}",
+                type_name
+            ))
+            .in_attribute(node, "k-for", expression_start)
+        })?;
+
+        // taken off the element itself, so none of the copies have it
+        let element = node_tree
+            .get_node_mut(element_ref)
+            .as_element_mut()
+            .unwrap();
+        element.attributes.remove("k-for");
+        Ok(Loop::new(binding, items, scope))
+    }
+
+    /// Renders a copy of the `body` nodes before `anchor_ref` for the next item of `list`,
+    /// with the loop variables and the `loop` object bound, or returns `false` when there are
+    /// no items left.
+    ///
+    /// `loop` has the 1-based `index` of the item, its 0-based `index0`, whether it is the
+    /// `first` or `last` one, the `length` of the loop, and the `loop` object of the
+    /// enclosing loop as `parent` (or `()`).
+    fn render_item(
+        &mut self,
+        node_tree: &mut NodeTree,
+        anchor_ref: NodeRef,
+        body: &[NodeRef],
+        list: &mut Loop,
+        scope: &mut Scope<'static>,
+    ) -> Result<bool, Diagnostic> {
+        let (index0, (key, item)) = match list.entries.next() {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+        let scope_len = scope.len();
+        let mut loop_object = rhai::Map::new();
+        loop_object.insert("index".into(), Dynamic::from((index0 + 1) as rhai::INT));
+        loop_object.insert("index0".into(), Dynamic::from(index0 as rhai::INT));
+        loop_object.insert("first".into(), Dynamic::from(index0 == 0));
+        loop_object.insert("last".into(), Dynamic::from(index0 + 1 == list.length));
+        loop_object.insert("length".into(), Dynamic::from(list.length as rhai::INT));
+        loop_object.insert("parent".into(), list.parent.clone());
+        scope.push_dynamic(LOOP_VARIABLE, loop_object.into());
+        list.binding.bind(scope, key, item, list.keyed);
+        for &body_ref in body {
+            let clone_ref = node_tree.clone_subtree(body_ref);
+            node_tree.append_before_sibling(anchor_ref, clone_ref);
+            self.render_node(node_tree, clone_ref, scope)?;
+        }
+        scope.rewind(scope_len);
+        Ok(true)
+    }
+
+    /// Evaluates the rhai expression in an attribute of `node`.
+    fn eval_attribute(
+        &self,
+        node: &Node,
+        local_name: &str,
+        scope: &mut Scope<'static>,
+    ) -> Result<Dynamic, Diagnostic> {
+        let expression = required_attribute(node, local_name)?;
+        self.eval_in_attribute(node, local_name, expression, 0, scope)
+    }
+
+    /// Turns an interpolated value into text escaped for `context`, or kept as is for a
+    /// [`Safe`] value. An unsafe URL is replaced, with a warning pointing at `offset`.
+    fn escape(
+        &mut self,
+        value: Dynamic,
+        context: EscapeContext,
+        node: &Node,
+        offset: Option<usize>,
+    ) -> String {
+        if value.is::<Safe>() {
+            return plain(value);
+        }
+        let text = value.to_string();
+        match escape::escape(&text, context) {
+            Some(escaped) => escaped,
+            None => {
+                let diagnostic = Diagnostic::new(format!(
+                    "`{}` is not a safe URL, so it was replaced with `{}`",
+                    text, UNSAFE_URL
+                ));
+                self.warn(match offset {
+                    Some(offset) => diagnostic.at(node, offset),
+                    None => diagnostic.at_node(node),
+                });
+                String::from(UNSAFE_URL)
+            }
+        }
+    }
+
+    fn warn(&mut self, diagnostic: Diagnostic) {
+        if let Some(on_warning) = &mut self.opts.on_warning {
+            on_warning(diagnostic);
+        }
+    }
+
+    /// Evaluates the rhai expression in an attribute of `node`, which must give a boolean.
+    fn eval_condition(
+        &self,
+        node: &Node,
+        local_name: &str,
+        scope: &mut Scope<'static>,
+    ) -> Result<bool, Diagnostic> {
+        let condition = self.eval_attribute(node, local_name, scope)?;
+        let type_name = condition.type_name();
+        condition.as_bool().map_err(|_| {
+            Diagnostic::new(format!(
+                "`{}` must be a boolean, not {}",
+                local_name, type_name
+            ))
+            .at_attribute(node, local_name)
+        })
+    }
+
+    /// Evaluates a rhai expression found `start` bytes into the value of an attribute.
+    fn eval_in_attribute(
+        &self,
+        node: &Node,
+        local_name: &str,
+        expression: &str,
+        start: usize,
+        scope: &mut Scope<'static>,
+    ) -> Result<Dynamic, Diagnostic> {
+        let offset = attribute_offset(node, local_name, start);
+        self.eval_at(node, expression, offset, scope)
+    }
+
+    /// Evaluates a rhai expression found at `offset` in the source of `node`.
+    fn eval_at(
+        &self,
+        node: &Node,
+        expression: &str,
+        offset: Option<usize>,
+        scope: &mut Scope<'static>,
+    ) -> Result<Dynamic, Diagnostic> {
+        self.engine
+            .eval_expression_with_scope::<Dynamic>(scope, expression)
+            .map_err(|error| Diagnostic::from_rhai(*error, node, offset))
+    }
+}
+
+/// The state of [`Renderer::render_to`], shared with the expansions it hands the serializer.
+struct Stream<'r> {
+    renderer: &'r mut Renderer,
+    scope: &'r mut Scope<'static>,
+    /// The scoped styles of the template, scoped as they are rendered.
+    styles: Vec<NodeRef>,
+    /// The scope attribute of the template, when it has scoped styles.
+    attribute: Option<String>,
+    /// The problem rendering stopped at; nothing else is written after it.
+    error: Option<Diagnostic>,
+}
+
+/// How a node is rendered before it is written, see [`Stream::step`].
+enum Step {
+    /// All but its children, which are streamed in turn.
+    Node,
+    /// Whole, into the nodes before `end`.
+    Rendered { end: Option<NodeRef> },
+    /// One item at a time, each one into the nodes before the loop's element.
+    Loop(Loop, Vec<NodeRef>),
+}
+
+impl<'r> Stream<'r> {
+    /// Tells the serializer what to write for a node, rendering it first.
+    fn expand<'a>(
+        stream: &'a RefCell<Self>,
+        node_tree: &mut NodeTree,
+        node_ref: NodeRef,
+    ) -> Streamed<'a> {
+        // what comes before the node has been written and freed, so whatever rendering it
+        // leaves at the start of its parent is what has to be written in its place
+        let parent_ref = node_tree.parent(node_ref);
+        let mut state = stream.borrow_mut();
+        let step = match state.error {
+            Some(_) => Err(()),
+            None => state.step(node_tree, node_ref).map_err(|diagnostic| {
+                state.error = Some(diagnostic);
+            }),
+        };
+        drop(state);
+        match (step, parent_ref) {
+            (Ok(Step::Node), _) => Streamed::Node,
+            (Ok(Step::Rendered { end }), Some(parent_ref)) => {
+                Streamed::Expanded(Box::new(move |node_tree| {
+                    let first_ref = node_tree.get_node(parent_ref).first_child();
+                    first_ref.filter(|&first_ref| Some(first_ref) != end)
+                }))
+            }
+            (Ok(Step::Loop(mut list, body)), Some(parent_ref)) => {
+                Streamed::Expanded(Box::new(move |node_tree| loop {
+                    let first_ref = node_tree.get_node(parent_ref).first_child();
+                    if first_ref != Some(node_ref) {
+                        return first_ref;
+                    }
+                    let mut state = stream.borrow_mut();
+                    if state.error.is_some() {
+                        return None;
+                    }
+                    let Stream {
+                        renderer, scope, ..
+                    } = &mut *state;
+                    match renderer.render_item(node_tree, node_ref, &body, &mut list, scope) {
+                        Ok(true) => {}
+                        Ok(false) => return None,
+                        Err(diagnostic) => {
+                            state.error = Some(diagnostic);
+                            return None;
+                        }
+                    }
+                }))
+            }
+            _ => Streamed::Expanded(Box::new(|_| None)),
+        }
+    }
+
+    /// Renders what has to be rendered of a node before it is written, the same way
+    /// [`Renderer::render_node`] would.
+    fn step(&mut self, node_tree: &mut NodeTree, node_ref: NodeRef) -> Result<Step, Diagnostic> {
+        let renderer = &mut *self.renderer;
+        let node = node_tree.get_node(node_ref);
+        let element = match node.as_element() {
+            Some(element) => element,
+            None if node.as_text().is_some() => return self.render_whole(node_tree, node_ref),
+            None => return Ok(Step::Node),
+        };
+        if element.attributes.contains("k-for") {
+            let list = renderer.start_for(node_tree, node_ref, self.scope)?;
+            return Ok(Step::Loop(list, vec![node_ref]));
+        }
+        let local = html_local_name(node).map(|local| &**local);
+        let is_component = local.and_then(|local| renderer.import(local)).is_some();
+        let has_show = element.attributes.contains("k-show");
+        if ["k-let", "k-if", "k-html"]
+            .iter()
+            .any(|&name| element.attributes.contains(name))
+            || is_component
+            || (local != Some("kolo-list") && local.is_some_and(|l| l.starts_with("kolo-")))
+            || self.styles.contains(&node_ref)
+        {
+            return self.render_whole(node_tree, node_ref);
+        }
+        let is_list = local == Some("kolo-list");
+        if has_show {
+            renderer.render_show(node_tree, node_ref, self.scope)?;
+        }
+        if is_list {
+            let (list, body) = renderer.start_list(node_tree, node_ref, self.scope)?;
+            return Ok(Step::Loop(list, body));
+        }
+        renderer.render_attributes(node_tree, node_ref, self.scope)?;
+        renderer.render_bindings(node_tree, node_ref, self.scope)?;
+        Ok(Step::Node)
+    }
+
+    fn render_whole(
+        &mut self,
+        node_tree: &mut NodeTree,
+        node_ref: NodeRef,
+    ) -> Result<Step, Diagnostic> {
+        let end = rendered_end(node_tree, node_ref);
+        self.renderer.render_node(node_tree, node_ref, self.scope)?;
+        if let (true, Some(attribute)) = (self.styles.contains(&node_ref), &self.attribute) {
+            node_tree.scope_styles(node_ref, attribute);
+        }
+        Ok(Step::Rendered { end })
+    }
+}
+
+/// The first node after `node_ref` that rendering it leaves alone: its next sibling, or the
+/// one after the `<kolo-else-if>` and `<kolo-else>` branches of a `<kolo-if>`.
+fn rendered_end(node_tree: &NodeTree, node_ref: NodeRef) -> Option<NodeRef> {
+    let node = node_tree.get_node(node_ref);
+    let mut end_ref = node.next_sibling();
+    if html_local_name(node).map(|local| &**local) != Some("kolo-if") {
+        return end_ref;
+    }
+    while let Some(sibling_ref) = end_ref {
+        let sibling = node_tree.get_node(sibling_ref);
+        match html_local_name(sibling).map(|local| &**local) {
+            Some("kolo-else") => return sibling.next_sibling(),
+            Some("kolo-else-if") => {}
+            _ if sibling.as_text().is_some_and(|text| text.trim().is_empty()) => {}
+            _ => break,
+        }
+        end_ref = sibling.next_sibling();
+    }
+    end_ref
+}
+
+/// A problem writing the output of [`Renderer::render_to`].
+fn write_error(error: io::Error) -> Diagnostic {
+    Diagnostic::new(format!("could not write the output: {}", error))
+}
+
+/// The value of an attribute a directive can not do without.
+fn required_attribute<'a>(node: &'a Node, local_name: &str) -> Result<&'a str, Diagnostic> {
+    let element = node.as_element().unwrap();
+    element.attributes.get(local_name).ok_or_else(|| {
+        let article = if local_name.starts_with(|c| "aeiou".contains(c)) {
+            "an"
+        } else {
+            "a"
+        };
+        Diagnostic::new(format!(
+            "`<{}>` needs {} `{}` attribute",
+            element.name.local, article, local_name
+        ))
+        .at_node(node)
+    })
+}
+
+/// The variables a loop binds for every item.
+enum LoopBinding {
+    /// `item`
+    Item(String),
+    /// `(item, index)`, or `(key, value)` when going through a map.
+    Pair(String, String),
+}
+
+impl LoopBinding {
+    /// Parses `item` or `(first, second)`.
+    fn parse(pattern: &str) -> Option<Self> {
+        match pattern
+            .strip_prefix('(')
+            .and_then(|pattern| pattern.strip_suffix(')'))
+        {
+            Some(pair) => {
+                let (first, second) = pair.split_once(',')?;
+                let (first, second) = (first.trim(), second.trim());
+                if is_identifier(first) && is_identifier(second) {
+                    Some(LoopBinding::Pair(first.into(), second.into()))
+                } else {
+                    None
+                }
+            }
+            None if is_identifier(pattern) => Some(LoopBinding::Item(pattern.into())),
+            None => None,
+        }
+    }
+
+    fn bind(&self, scope: &mut Scope<'static>, key: Dynamic, item: Dynamic, keyed: bool) {
+        match self {
+            LoopBinding::Item(name) => {
+                scope.push_dynamic(name.as_str(), item);
+            }
+            LoopBinding::Pair(key_name, item_name) if keyed => {
+                scope.push_dynamic(key_name.as_str(), key);
+                scope.push_dynamic(item_name.as_str(), item);
+            }
+            LoopBinding::Pair(item_name, index_name) => {
+                scope.push_dynamic(item_name.as_str(), item);
+                scope.push_dynamic(index_name.as_str(), key);
+            }
+        }
+    }
+}
+
+/// Splits the `value` of a `<kolo-case>` into the values it lists, on the `|` between them.
+fn case_values(pattern: &str) -> Vec<&str> {
+    let mut values = Vec::new();
+    let mut start = 0;
+    let bytes = pattern.as_bytes();
+    for i in top_level(pattern) {
+        if bytes[i] == b'|' && bytes.get(i + 1) != Some(&b'|') && (i == 0 || bytes[i - 1] != b'|') {
+            values.push(pattern[start..i].trim());
+            start = i + 1;
+        }
+    }
+    values.push(pattern[start..].trim());
+    values
+}
+
+/// Whether the `value` of a `<kolo-case>` has an `if` guard.
+fn has_guard(pattern: &str) -> bool {
+    let bytes = pattern.as_bytes();
+    let is_word = |i: usize| {
+        bytes
+            .get(i)
+            .is_some_and(|&b| b.is_ascii_alphanumeric() || b == b'_')
+    };
+    top_level(pattern)
+        .any(|i| i > 0 && pattern[i..].starts_with("if") && !is_word(i - 1) && !is_word(i + 2))
+}
+
+/// The byte indexes of a rhai expression outside of its strings and brackets.
+fn top_level(code: &str) -> impl Iterator<Item = usize> + '_ {
+    let bytes = code.as_bytes();
+    let mut depth = 0;
+    let mut quote = None;
+    (0..bytes.len()).filter(move |&i| {
+        let byte = bytes[i];
+        match (quote, byte) {
+            (Some(q), _) if byte == q && bytes[i - 1] != b'\\' => quote = None,
+            (Some(_), _) => {}
+            (None, b'"') | (None, b'\'') | (None, b'`') => quote = Some(byte),
+            (None, b'(') | (None, b'[') | (None, b'{') => depth += 1,
+            (None, b')') | (None, b']') | (None, b'}') => depth -= 1,
+            (None, _) => return depth == 0,
+        }
+        false
+    })
+}
+
+/// Splits a `k-let` value into its variable name and where the expression after `=` starts.
+fn parse_let(binding: &str) -> Option<(&str, usize)> {
+    let equals = binding.find('=')?;
+    let name = binding[..equals].trim();
+    let expression = &binding[equals + 1..];
+    if !is_identifier(name) || expression.starts_with('=') || expression.trim().is_empty() {
+        return None;
+    }
+    Some((name, binding.len() - expression.trim_start().len()))
+}
+
+/// Splits a `k-for` value into its binding and where the expression after `in` starts.
+fn parse_for(value: &str) -> Option<(LoopBinding, usize)> {
+    let (in_start, _) = value.match_indices("in").find(|&(i, _)| {
+        let before = value[..i].chars().next_back();
+        let after = value[i + 2..].chars().next();
+        before.is_some_and(|c| c.is_ascii_whitespace() || c == ')')
+            && after.is_some_and(|c| c.is_ascii_whitespace())
+    })?;
+    let binding = LoopBinding::parse(value[..in_start].trim())?;
+    let expression = &value[in_start + 2..];
+    let expression_start = in_start + 2 + expression.len() - expression.trim_start().len();
+    if value[expression_start..].trim().is_empty() {
+        return None;
+    }
+    Some((binding, expression_start))
+}
+
+/// What a loop goes through: every item with its index, or with its key for a map.
+struct Items {
+    keyed: bool,
+    entries: Vec<(Dynamic, Dynamic)>,
+}
+
+/// A loop being expanded, one item at a time, see [`Renderer::render_item`].
+struct Loop {
+    binding: LoopBinding,
+    keyed: bool,
+    length: usize,
+    /// The `loop` object of the enclosing loop, or `()`.
+    parent: Dynamic,
+    entries: std::iter::Enumerate<std::vec::IntoIter<(Dynamic, Dynamic)>>,
+}
+
+impl Loop {
+    fn new(binding: LoopBinding, items: Items, scope: &Scope<'static>) -> Self {
+        Loop {
+            binding,
+            keyed: items.keyed,
+            length: items.entries.len(),
+            parent: scope
+                .get_value::<Dynamic>(LOOP_VARIABLE)
+                .unwrap_or(Dynamic::UNIT),
+            entries: items.entries.into_iter().enumerate(),
+        }
+    }
+}
+
+/// The items of a value a loop can go through: the elements of an array, the values of a
+/// map, the numbers in a range or the characters of a string. Anything else gives back the
+/// name of its type.
+fn items(value: Dynamic) -> Result<Items, &'static str> {
+    fn indexed(items: impl Iterator<Item = Dynamic>) -> Items {
+        Items {
+            keyed: false,
+            entries: items
+                .enumerate()
+                .map(|(index, item)| (Dynamic::from(index as rhai::INT), item))
+                .collect(),
+        }
+    }
+
+    let type_name = value.type_name();
+    if value.is_array() {
+        let array = value.into_array().map_err(|_| type_name)?;
+        Ok(indexed(array.into_iter()))
+    } else if value.is_map() {
+        let map = value.try_cast::<rhai::Map>().ok_or(type_name)?;
+        Ok(Items {
+            keyed: true,
+            entries: map
+                .into_iter()
+                .map(|(key, item)| (Dynamic::from(key.to_string()), item))
+                .collect(),
+        })
+    } else if value.is::<Range<rhai::INT>>() {
+        let range = value.try_cast::<Range<rhai::INT>>().ok_or(type_name)?;
+        Ok(indexed(range.map(Dynamic::from)))
+    } else if value.is::<RangeInclusive<rhai::INT>>() {
+        let range = value
+            .try_cast::<RangeInclusive<rhai::INT>>()
+            .ok_or(type_name)?;
+        Ok(indexed(range.map(Dynamic::from)))
+    } else if value.is_string() {
+        let string = value.into_string().map_err(|_| type_name)?;
+        Ok(indexed(string.chars().map(Dynamic::from)))
+    } else {
+        Err(type_name)
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Checks the props passed to a component against its declarations, converting their values
+/// to the declared types, and binds the props not passed to their defaults.
+///
+/// Without a caller, as for the template rendered first, the variables in `scope` are the
+/// props passed.
+fn bind_props(
+    scope: &mut Scope,
+    declarations: &[PropDeclaration],
+    mut caller: Option<&mut Caller>,
+) -> Result<(), String> {
+    if let Some(declaration) = declarations.iter().find(|d| !is_identifier(&d.name)) {
+        return Err(format!(
+            "`{}` can not be a prop, since it is not a variable name",
+            declaration.name
+        ));
+    }
+    if let Some(caller) = caller.as_deref_mut() {
+        let unknown = caller.props.iter().find(|passed| {
+            declarations
+                .iter()
+                .all(|declaration| declaration.name != passed.variable)
+        });
+        if let Some(passed) = unknown {
+            let message = format!("`<{}>` has no `{}` prop", caller.name, passed.attribute);
+            let at = passed.at.clone();
+            return Err(caller.fail(&at, message));
+        }
+    }
+
+    for declaration in declarations {
+        let name = &declaration.name;
+        // the attribute the prop was passed as, whether it is a plain one, and where it is
+        let passed = match caller.as_deref() {
+            Some(caller) => caller
+                .props
+                .iter()
+                .find(|passed| passed.variable == *name)
+                .map(|passed| {
+                    let at = Some(passed.at.clone());
+                    (passed.attribute.clone(), passed.from_attribute, at)
+                }),
+            None if scope.contains(name) => Some((name.clone(), false, None)),
+            None => None,
+        };
+        match passed {
+            Some((attribute, from_attribute, at)) => {
+                let value: Dynamic = scope.get_value(name).unwrap();
+                match declaration.prop_type.coerce(value.clone(), from_attribute) {
+                    Some(value) => {
+                        scope.set_value(name.clone(), value);
+                    }
+                    None => {
+                        let message = format!(
+                            "`{}` must be {}, not {}",
+                            attribute,
+                            declaration.prop_type.describe(),
+                            describe_value(&value)
+                        );
+                        return Err(match (caller.as_deref_mut(), at) {
+                            (Some(caller), Some(at)) => caller.fail(&at, message),
+                            _ => message,
+                        });
+                    }
+                }
+            }
+            None if declaration.required => {
+                return Err(match caller.as_deref_mut() {
+                    Some(caller) => {
+                        let message = format!(
+                            "`<{}>` needs a `{}` prop",
+                            caller.name,
+                            name.replace('_', "-")
+                        );
+                        let at = caller.at.clone();
+                        caller.fail(&at, message)
+                    }
+                    None => format!("the `{}` prop is required, but was not passed", name),
+                });
+            }
+            None => {
+                scope.push_dynamic(name.clone(), declaration.default.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether `name` can be the name of a custom element: lowercase, starting with a letter, with
+/// a `-` in it.
+fn is_custom_element_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name.contains('-')
+        && name.chars().all(|c| {
+            c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_' || c == '.'
+        })
+}
+
+/// The path of `src` relative to the template named `importer`, with the `.` and `..` in it
+/// resolved.
+fn resolve_path(importer: Option<&str>, src: &str) -> String {
+    let joined = match importer.and_then(|importer| Path::new(importer).parent()) {
+        Some(directory) => directory.join(src),
+        None => PathBuf::from(src),
+    };
+    let mut path = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(path.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                path.pop();
+            }
+            component => path.push(component),
+        }
+    }
+    path.to_string_lossy().into_owned()
+}
+
+/// The value a `:target` binding to `()`, a boolean, an array or a map gives its attribute,
+/// `None` to remove it, or else the name of the type it can not be set to.
+fn bound_value(
+    target: &str,
+    value: Dynamic,
+    static_value: Option<&str>,
+) -> Result<Option<String>, &'static str> {
+    if value.is_unit() {
+        return Ok(None);
+    }
+    if let Ok(set) = value.as_bool() {
+        return Ok(if set { Some(String::new()) } else { None });
+    }
+    match target {
+        "class" => {
+            let mut classes: Vec<String> = static_value
+                .unwrap_or("")
+                .split_ascii_whitespace()
+                .map(String::from)
+                .collect();
+            for class in class_names(value)? {
+                if !classes.contains(&class) {
+                    classes.push(class);
+                }
+            }
+            Ok(Some(classes.join(" ")))
+        }
+        "style" => {
+            let mut style = String::from(static_value.unwrap_or(""));
+            for declaration in declarations(value)? {
+                style = append_declaration(&style, &declaration);
+            }
+            Ok(Some(style))
+        }
+        _ => Err(value.type_name()),
+    }
+}
+
+/// The class names in an array of names (or of maps, or of `()` to skip), or in a map from
+/// names to whether to add them.
+fn class_names(value: Dynamic) -> Result<Vec<String>, &'static str> {
+    let type_name = value.type_name();
+    let mut names = Vec::new();
+    if value.is_map() {
+        for (name, add) in value.try_cast::<rhai::Map>().ok_or(type_name)? {
+            if add.as_bool().map_err(|_| add.type_name())? {
+                names.push(name.to_string());
+            }
+        }
+    } else if value.is_array() {
+        for item in value.into_array().map_err(|_| type_name)? {
+            if item.is_map() || item.is_array() {
+                names.extend(class_names(item)?);
+            } else if item.is_string() {
+                names.extend(item.to_string().split_ascii_whitespace().map(String::from));
+            } else if !item.is_unit() {
+                return Err(item.type_name());
+            }
+        }
+    } else {
+        return Err(type_name);
+    }
+    Ok(names)
+}
+
+/// The declarations in a map of properties (skipping `()` values), or in an array of
+/// declarations and such maps, escaped as CSS unless [`Safe`].
+fn declarations(value: Dynamic) -> Result<Vec<String>, &'static str> {
+    let type_name = value.type_name();
+    let mut list = Vec::new();
+    if value.is_map() {
+        for (property, value) in value.try_cast::<rhai::Map>().ok_or(type_name)? {
+            if !value.is_unit() {
+                list.push(declaration(&property, value));
+            }
+        }
+    } else if value.is_array() {
+        for item in value.into_array().map_err(|_| type_name)? {
+            if item.is_map() || item.is_array() {
+                list.extend(declarations(item)?);
+            } else if item.is::<Safe>() {
+                list.push(plain(item));
+            } else if item.is_string() {
+                let item = item.to_string();
+                list.push(match item.split_once(':') {
+                    Some((property, value)) => {
+                        declaration(property, Dynamic::from(value.to_string()))
+                    }
+                    None => escape_css(&item),
+                });
+            } else if !item.is_unit() {
+                return Err(item.type_name());
+            }
+        }
+    } else {
+        return Err(type_name);
+    }
+    Ok(list)
+}
+
+/// A CSS declaration setting `property` to `value`, with both escaped.
+fn declaration(property: &str, value: Dynamic) -> String {
+    let value = if value.is::<Safe>() {
+        plain(value)
+    } else {
+        escape_css(value.to_string().trim())
+    };
+    format!("{}: {}", escape_css(property.trim()), value)
+}
+
+/// Escapes text as CSS, for a `style` attribute.
+fn escape_css(value: &str) -> String {
+    escape::escape(value, EscapeContext::Style).unwrap()
+}
+
+/// Adds a CSS declaration to the end of a `style` attribute value.
+fn append_declaration(style: &str, declaration: &str) -> String {
+    let style = style.trim_end();
+    let declaration = declaration.trim().trim_end_matches(';');
+    if style.is_empty() {
+        String::from(declaration)
+    } else if style.ends_with(';') {
+        format!("{} {}", style, declaration)
+    } else {
+        format!("{}; {}", style, declaration)
+    }
+}
+
+/// A piece of text with interpolations.
+enum Segment<'a> {
+    Text(&'a str),
+    /// An expression, the byte index it starts at, and whether it was in triple braces.
+    Expression(&'a str, usize, bool),
+}
+
+/// Splits text on its `{{ expression }}` and `{{{ expression }}}` interpolations, or gives
+/// back where an unclosed one starts. The whitespace next to `{{-` and `-}}` is left out.
+fn segments(text: &str) -> Result<Vec<Segment<'_>>, usize> {
+    let mut segments = Vec::new();
+    let mut rest_start = 0;
+    let mut trim_next = false;
+    while let Some(open) = text[rest_start..].find("{{").map(|i| rest_start + i) {
+        let raw = text[open..].starts_with("{{{");
+        let (start, close) = if raw {
+            (open + 3, "}}}")
+        } else {
+            (open + 2, "}}")
+        };
+        let end = text[start..].find(close).ok_or(open)? + start;
+        let trim_before = text[start..end].starts_with('-');
+        let code_start = start + trim_before as usize;
+        let trim_after = end > code_start && text[code_start..end].ends_with('-');
+        let code_end = end - trim_after as usize;
+
+        let mut before = &text[rest_start..open];
+        if trim_next {
+            before = before.trim_start_matches(|c: char| c.is_ascii_whitespace());
+        }
+        if trim_before {
+            before = before.trim_end_matches(|c: char| c.is_ascii_whitespace());
+        }
+        if !before.is_empty() {
+            segments.push(Segment::Text(before));
+        }
+        let code = &text[code_start..code_end];
+        let leading = code.len() - code.trim_start().len();
+        segments.push(Segment::Expression(code.trim(), code_start + leading, raw));
+        rest_start = end + close.len();
+        trim_next = trim_after;
+    }
+    let mut rest = &text[rest_start..];
+    if trim_next {
+        rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace());
+    }
+    if !rest.is_empty() {
+        segments.push(Segment::Text(rest));
+    }
+    Ok(segments)
+}
+
+/// Where the byte `index` of the value of an attribute is in the source of `node`, or where
+/// its name is when it has no value.
+fn attribute_offset(node: &Node, local_name: &str, index: usize) -> Option<usize> {
+    node.attribute_span(local_name)
+        .map(|attribute_span| match attribute_span.value {
+            Some(span) => span.start + index,
+            None => attribute_span.name.start,
+        })
+}
+
+/// The text of a value, unwrapping a [`Safe`] one.
+fn plain(value: Dynamic) -> String {
+    if value.is::<Safe>() {
+        value.cast::<Safe>().0
+    } else {
+        value.to_string()
+    }
+}
+
+/// Where the byte `index` of the text of a text node is in its source: exactly when the text
+/// is written as is there, or else at the start of the node.
+fn text_offset(node: &Node, index: usize) -> Option<usize> {
+    let (source, span) = (node.source()?, node.span()?);
+    if node.as_text().map(|text| &**text) == Some(&source.text[span.range()]) {
+        Some(span.start + index)
+    } else {
+        Some(span.start)
+    }
+}
+
+/// Parses trusted HTML from an expression as if it were inside a `context` element.
+fn parse_html_fragment(html: &str, context: QualName) -> NodeTree {
+    parse_fragment(context, Vec::new()).one(html)
+}
+
+/// The nodes of a parsed fragment, which html5ever puts in an `<html>` element.
+fn fragment_nodes(fragment: &NodeTree) -> Vec<NodeRef> {
+    fragment
+        .children(fragment.root())
+        .flat_map(|html_ref| fragment.children(html_ref))
+        .collect()
+}
+
+fn is_build_script(node: &Node) -> bool {
+    html_local_name(node) == Some(&local_name!("script"))
+        && node
+            .as_element()
+            .is_some_and(|element| element.attributes.contains("build"))
+}
@@ -0,0 +1,363 @@
+//! Scoped styles: the rules of a `<style scoped>` only apply to the elements of its own
+//! template.
+//!
+//! Every element of the template is stamped with an attribute unique to it, such as
+//! `data-k-3fa2c1d0`, and every selector of the stylesheet is rewritten to require it, so
+//! that `.card .title` becomes `.card .title[data-k-3fa2c1d0]`. In selectors:
+//!
+//! - `:global(selector)` is left as `selector`, without the attribute, to reach elements
+//!   outside of the template, as in `:global(.dark) .title`;
+//! - `:host` matches the root elements of the template, and `:host(selector)` the ones
+//!   matching `selector`.
+
+use html5ever::{namespace_url, ns};
+
+use crate::serializer::html_local_name;
+use crate::span::SourceFile;
+use crate::tree::*;
+
+/// What the attributes scoped styles stamp on elements start with.
+pub const SCOPE_ATTRIBUTE_PREFIX: &str = "data-k-";
+
+/// At-rules holding rules whose selectors are scoped, rather than declarations or keyframes.
+const GROUPING_RULES: &[&str] = &[
+    "container",
+    "document",
+    "layer",
+    "media",
+    "scope",
+    "supports",
+];
+
+/// Pseudo-elements written with a single colon, for compatibility.
+const LEGACY_PSEUDO_ELEMENTS: &[&str] = &[":after", ":before", ":first-letter", ":first-line"];
+
+/// The attribute that scopes the styles of a template, such as `data-k-3fa2c1d0`.
+///
+/// It is a hash of the template's name (or its text, without one), so that it stays the same
+/// from one build to the next.
+pub fn scope_attribute(source: Option<&SourceFile>) -> String {
+    let key = source.map_or("", |source| source.name.as_deref().unwrap_or(&source.text));
+    // FNV-1a, whose output does not depend on the platform or the Rust version
+    let hash = key.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    });
+    format!("{}{:08x}", SCOPE_ATTRIBUTE_PREFIX, hash)
+}
+
+/// Whether `name` is an attribute scoped styles stamp, such as `data-k-3fa2c1d0`,
+/// `data-k-3fa2c1d0-2` or `data-k-3fa2c1d0-host`, rather than one that only starts the same.
+pub fn is_scope_attribute(name: &str) -> bool {
+    let rest = match name.strip_prefix(SCOPE_ATTRIBUTE_PREFIX) {
+        Some(rest) => rest.strip_suffix("-host").unwrap_or(rest),
+        None => return false,
+    };
+    match (rest.get(..8), rest.get(8..)) {
+        (Some(hash), Some(instance)) => {
+            hash.bytes()
+                .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+                && (instance.is_empty()
+                    || instance.strip_prefix('-').is_some_and(|number| {
+                        !number.is_empty() && number.bytes().all(|byte| byte.is_ascii_digit())
+                    }))
+        }
+        _ => false,
+    }
+}
+
+/// The attribute stamped on the root elements of a template, for `:host`.
+pub fn host_attribute(attribute: &str) -> String {
+    format!("{}-host", attribute)
+}
+
+fn is_scoped_style(node: &Node) -> bool {
+    html_local_name(node).is_some_and(|local| &**local == "style")
+        && node
+            .as_element()
+            .is_some_and(|element| element.attributes.contains("scoped"))
+}
+
+impl NodeTree {
+    /// The `<style scoped>` elements under `node_ref`.
+    pub fn scoped_styles(&self, node_ref: NodeRef) -> Vec<NodeRef> {
+        let mut styles = Vec::new();
+        let mut stack = vec![node_ref];
+        while let Some(node_ref) = stack.pop() {
+            let node = self.get_node(node_ref);
+            if is_scoped_style(node) {
+                styles.push(node_ref);
+            }
+            if let Some(template_contents) = node.as_element().and_then(|e| e.template_contents) {
+                stack.push(template_contents);
+            }
+            stack.extend(self.children(node_ref));
+        }
+        styles
+    }
+
+    /// Stamps `attribute` on every element under `node_ref`, and its [`host_attribute`] on
+    /// the root ones.
+    ///
+    /// `<html>`, `<head>`, `<body>`, `<style>`, `<script>`, `<slot>` and `kolo-*` directives
+    /// are not stamped; the elements in a directive or slot at the root are root elements too.
+    pub fn stamp_scope(&mut self, node_ref: NodeRef, attribute: &str) {
+        let host = host_attribute(attribute);
+        let mut stack = vec![(node_ref, true)];
+        while let Some((node_ref, is_root)) = stack.pop() {
+            let node = self.get_node(node_ref);
+            let mut children_are_roots = is_root;
+            if let Some(element) = node.as_element() {
+                let local = &*element.name.local;
+                let is_html = element.name.ns == ns!(html);
+                let transparent = is_html
+                    && (matches!(local, "html" | "head" | "body" | "slot")
+                        || local.starts_with("kolo-"));
+                let unstyled = is_html && matches!(local, "style" | "script");
+                if !transparent && !unstyled {
+                    let template_contents = element.template_contents;
+                    let element = self.get_node_mut(node_ref).as_element_mut().unwrap();
+                    element.attributes.insert(attribute, String::new());
+                    if is_root {
+                        element.attributes.insert(&*host, String::new());
+                    }
+                    children_are_roots = false;
+                    if let Some(template_contents) = template_contents {
+                        stack.push((template_contents, false));
+                    }
+                }
+            }
+            stack.extend(
+                self.children(node_ref)
+                    .map(|child_ref| (child_ref, children_are_roots)),
+            );
+        }
+    }
+
+    /// Rewrites the text of every `<style scoped>` under `node_ref` with [`scope_stylesheet`],
+    /// and takes its `scoped` attribute off.
+    pub fn scope_styles(&mut self, node_ref: NodeRef, attribute: &str) {
+        for style_ref in self.scoped_styles(node_ref) {
+            let element = self.get_node_mut(style_ref).as_element_mut().unwrap();
+            element.attributes.remove("scoped");
+            let text_refs: Vec<NodeRef> = self.children(style_ref).collect();
+            for text_ref in text_refs {
+                if let Some(text) = self.get_node(text_ref).as_text() {
+                    let scoped = scope_stylesheet(text, attribute);
+                    *self.get_node_mut(text_ref).as_text_mut().unwrap() = scoped;
+                }
+            }
+        }
+    }
+}
+
+/// Rewrites the selectors of a stylesheet to only match elements with `attribute`.
+///
+/// The rules inside `@media`, `@supports` and the like are rewritten too; other at-rules,
+/// such as `@keyframes` and `@font-face`, are left alone.
+pub fn scope_stylesheet(css: &str, attribute: &str) -> String {
+    let mut scoped = String::with_capacity(css.len());
+    scope_rules(css, attribute, &mut scoped);
+    scoped
+}
+
+/// Scopes a list of rules: a whole stylesheet, or the inside of an `@media` and the like.
+fn scope_rules(css: &str, attribute: &str, scoped: &mut String) {
+    let mut rest = css;
+    // the prelude of each rule runs up to its block, or the `;` ending an at-rule without one
+    while let Some(prelude_end) = scan(rest, |_, c, depth| depth == 0 && (c == '{' || c == ';')) {
+        let prelude = &rest[..prelude_end];
+        if rest[prelude_end..].starts_with(';') {
+            scoped.push_str(&rest[..=prelude_end]);
+            rest = &rest[prelude_end + 1..];
+            continue;
+        }
+        let mut nesting = 0;
+        let block_end = prelude_end
+            + scan(&rest[prelude_end..], |_, c, _| {
+                match c {
+                    '{' => nesting += 1,
+                    '}' => nesting -= 1,
+                    _ => {}
+                }
+                nesting == 0
+            })
+            .unwrap_or(rest.len() - prelude_end);
+        let block = &rest[prelude_end + 1..block_end];
+
+        let start = scan(prelude, |_, c, _| !c.is_whitespace()).unwrap_or(prelude.len());
+        match prelude[start..].strip_prefix('@') {
+            Some(at_rule) => {
+                let name_end = at_rule
+                    .find(|c: char| !c.is_alphanumeric() && c != '-')
+                    .unwrap_or(at_rule.len());
+                scoped.push_str(prelude);
+                scoped.push('{');
+                if GROUPING_RULES.contains(&&*at_rule[..name_end].to_ascii_lowercase()) {
+                    scope_rules(block, attribute, scoped);
+                } else {
+                    scoped.push_str(block);
+                }
+            }
+            None => {
+                scoped.push_str(&scope_selectors(prelude, attribute));
+                scoped.push('{');
+                scoped.push_str(block);
+            }
+        }
+        if block_end < rest.len() {
+            scoped.push('}');
+            rest = &rest[block_end + 1..];
+        } else {
+            rest = "";
+        }
+    }
+    scoped.push_str(rest);
+}
+
+/// Scopes a list of selectors, separated by commas.
+fn scope_selectors(selectors: &str, attribute: &str) -> String {
+    let mut scoped = String::with_capacity(selectors.len() + attribute.len() + 2);
+    let mut rest = selectors;
+    while let Some(comma) = scan(rest, |_, c, depth| depth == 0 && c == ',') {
+        scoped.push_str(&scope_selector(&rest[..comma], attribute));
+        scoped.push(',');
+        rest = &rest[comma + 1..];
+    }
+    scoped.push_str(&scope_selector(rest, attribute));
+    scoped
+}
+
+/// Scopes a complex selector, such as `.card > .title:hover`, by requiring `attribute` on the
+/// element it selects.
+fn scope_selector(selector: &str, attribute: &str) -> String {
+    // the compound selectors, between the whitespace and `>`, `+` and `~` combinators
+    let mut compounds = Vec::new();
+    let mut start = None;
+    scan(selector, |i, c, depth| {
+        let is_combinator = depth == 0 && (c.is_whitespace() || matches!(c, '>' | '+' | '~'));
+        match start {
+            Some(compound_start) if is_combinator => {
+                compounds.push(compound_start..i);
+                start = None;
+            }
+            None if !is_combinator => start = Some(i),
+            _ => {}
+        }
+        false
+    });
+    if let Some(compound_start) = start {
+        compounds.push(compound_start..selector.len());
+    }
+
+    let mut scoped = String::with_capacity(selector.len() + attribute.len() + 2);
+    let mut written = 0;
+    let count = compounds.len();
+    for (index, compound) in compounds.into_iter().enumerate() {
+        scoped.push_str(&selector[written..compound.start]);
+        written = compound.end;
+        let text = &selector[compound];
+        if let Some(inner) = function_argument(text, ":global") {
+            scoped.push_str(inner);
+        } else if let Some(rest) = text.strip_prefix(":host") {
+            let host = format!("[{}]", host_attribute(attribute));
+            match function_argument(text, ":host") {
+                Some(inner) => {
+                    scoped.push_str(inner);
+                    scoped.push_str(&host);
+                }
+                None => {
+                    scoped.push_str(&host);
+                    scoped.push_str(rest);
+                }
+            }
+        } else if index + 1 == count {
+            let pseudo_element = scan(text, |i, c, depth| {
+                depth == 0
+                    && c == ':'
+                    && (text[i..].starts_with("::")
+                        || LEGACY_PSEUDO_ELEMENTS.iter().any(|legacy| {
+                            text[i..]
+                                .get(..legacy.len())
+                                .is_some_and(|start| start.eq_ignore_ascii_case(legacy))
+                        }))
+            })
+            .unwrap_or(text.len());
+            scoped.push_str(&text[..pseudo_element]);
+            scoped.push('[');
+            scoped.push_str(attribute);
+            scoped.push(']');
+            scoped.push_str(&text[pseudo_element..]);
+        } else {
+            scoped.push_str(text);
+        }
+    }
+    scoped.push_str(&selector[written..]);
+    scoped
+}
+
+/// The argument of a functional pseudo-class that is all of `compound`, as in `:global(.a)`.
+fn function_argument<'a>(compound: &'a str, name: &str) -> Option<&'a str> {
+    let argument = compound.strip_prefix(name)?.strip_prefix('(')?;
+    let mut nesting = 1;
+    let end = scan(argument, |_, c, _| {
+        match c {
+            '(' => nesting += 1,
+            ')' => nesting -= 1,
+            _ => {}
+        }
+        nesting == 0
+    })?;
+    if end + 1 == argument.len() {
+        Some(argument[..end].trim())
+    } else {
+        None
+    }
+}
+
+/// Finds the first character of `css` for which `found` is true, given its byte index and
+/// how deep in `()` and `[]` it is. Strings, comments and escaped characters are skipped.
+fn scan(css: &str, mut found: impl FnMut(usize, char, usize) -> bool) -> Option<usize> {
+    let mut chars = css.char_indices().peekable();
+    let mut depth = 0_usize;
+    while let Some((i, c)) = chars.next() {
+        match c {
+            // an escaped character is part of a name
+            '\\' => {
+                if found(i, c, depth) {
+                    return Some(i);
+                }
+                chars.next();
+            }
+            '"' | '\'' => {
+                while let Some((_, inside)) = chars.next() {
+                    if inside == '\\' {
+                        chars.next();
+                    } else if inside == c {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek().is_some_and(|&(_, next)| next == '*') => {
+                chars.next();
+                let mut previous = ' ';
+                for (_, inside) in chars.by_ref() {
+                    if previous == '*' && inside == '/' {
+                        break;
+                    }
+                    previous = inside;
+                }
+            }
+            _ => {
+                if found(i, c, depth) {
+                    return Some(i);
+                }
+                match c {
+                    '(' | '[' => depth += 1,
+                    ')' | ']' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,377 @@
+use encoding_rs::Encoding;
+use html5ever::serialize::{serialize, Serialize, SerializeOpts, Serializer, TraversalScope};
+use html5ever::{
+    expanded_name, local_name, namespace_prefix, namespace_url, ns, LocalName, Prefix, QualName,
+};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::io::{self, Write};
+
+use crate::attributes;
+use crate::encoding;
+use crate::tree::*;
+
+/// A node together with the tree that owns it, which is what html5ever needs to serialize it.
+pub struct SerializableNode<'a> {
+    node_tree: &'a NodeTree,
+    node_ref: NodeRef,
+    /// When re-encoding, `<meta>` charset declarations are rewritten to this encoding.
+    charset: Option<&'static Encoding>,
+    /// When writing XML, the prefixes declared around the node: the XML serializer only
+    /// declares the namespaces of elements, so those of attributes are declared here.
+    xml_prefixes: Option<&'a RefCell<Vec<Prefix>>>,
+}
+
+impl<'a> SerializableNode<'a> {
+    pub fn new(node_tree: &'a NodeTree, node_ref: NodeRef) -> Self {
+        SerializableNode {
+            node_tree,
+            node_ref,
+            charset: None,
+            xml_prefixes: None,
+        }
+    }
+
+    /// Rewrites `<meta charset>` and `<meta http-equiv="content-type">` to declare `charset`.
+    pub fn with_charset(mut self, charset: &'static Encoding) -> Self {
+        self.charset = Some(charset);
+        self
+    }
+
+    fn child(&self, node_ref: NodeRef) -> Self {
+        SerializableNode { node_ref, ..*self }
+    }
+
+    fn serialize_children<S: Serializer>(&self, serializer: &mut S) -> io::Result<()> {
+        let node = self.node_tree.get_node(self.node_ref);
+        let parent_ref = match node.as_element().and_then(|e| e.template_contents) {
+            Some(template_contents) => template_contents,
+            None => self.node_ref,
+        };
+        for child_ref in self.node_tree.children(parent_ref) {
+            self.child(child_ref)
+                .serialize(serializer, TraversalScope::IncludeNode)?;
+        }
+        Ok(())
+    }
+
+    fn serialize_element<S: Serializer>(
+        &self,
+        serializer: &mut S,
+        element: &ElementData,
+    ) -> io::Result<()> {
+        let in_scope = self.xml_prefixes.map(|prefixes| prefixes.borrow().len());
+        self.serialize_start_tag(serializer, element)?;
+        self.serialize_children(serializer)?;
+        if let (Some(prefixes), Some(in_scope)) = (self.xml_prefixes, in_scope) {
+            prefixes.borrow_mut().truncate(in_scope);
+        }
+        serializer.end_elem(element.name.clone())
+    }
+
+    /// Writes only the start tag of an element (with the newline a `<pre>` starting with one
+    /// needs); the caller is responsible for the end tag.
+    pub(crate) fn serialize_start_tag<S: Serializer>(
+        &self,
+        serializer: &mut S,
+        element: &ElementData,
+    ) -> io::Result<()> {
+        let is_meta = element.name.expanded() == expanded_name!(html "meta");
+        let is_content_type = is_meta
+            && element
+                .attributes
+                .get(local_name!("http-equiv"))
+                .is_some_and(|v| v.eq_ignore_ascii_case("content-type"));
+
+        let mut attrs: Vec<(QualName, Cow<str>)> = Vec::new();
+        if let Some(prefixes) = self.xml_prefixes {
+            let mut prefixes = prefixes.borrow_mut();
+            prefixes.extend(element.name.prefix.clone());
+            for (name, attribute) in &element.attributes.map {
+                if name.ns == ns!(xmlns) {
+                    prefixes.push(Prefix::from(&*name.local));
+                    continue;
+                }
+                match &attribute.prefix {
+                    Some(prefix)
+                        if *prefix != namespace_prefix!("xml") && !prefixes.contains(prefix) =>
+                    {
+                        let declaration = QualName::new(
+                            Some(namespace_prefix!("xmlns")),
+                            ns!(xmlns),
+                            LocalName::from(&**prefix),
+                        );
+                        attrs.push((declaration, Cow::Owned(name.ns.to_string())));
+                        prefixes.push(prefix.clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        attrs.extend(element.attributes.map.iter().map(|(name, attribute)| {
+            let qual_name = QualName::new(
+                attribute.prefix.clone(),
+                name.ns.clone(),
+                name.local.clone(),
+            );
+            let value = match self.charset {
+                Some(charset) if is_meta && name.ns == ns!() => {
+                    let charset = charset.output_encoding().name();
+                    if name.local == local_name!("charset") {
+                        Cow::Borrowed(charset)
+                    } else if name.local == local_name!("content") && is_content_type {
+                        Cow::Owned(format!("text/html; charset={}", charset))
+                    } else {
+                        Cow::Borrowed(&*attribute.value)
+                    }
+                }
+                _ => Cow::Borrowed(&*attribute.value),
+            };
+            (qual_name, value)
+        }));
+
+        serializer.start_elem(
+            element.name.clone(),
+            attrs.iter().map(|(name, value)| (name, &**value)),
+        )?;
+
+        // the parser drops a newline right after the start tag, so one that is part of the
+        // content needs another in front of it
+        if element.name.ns == ns!(html) && drops_leading_newline(&element.name.local) {
+            let first_child = self.node_tree.get_node(self.node_ref).first_child();
+            if first_child
+                .and_then(|first_child_ref| self.node_tree.get_node(first_child_ref).as_text())
+                .is_some_and(|text| text.starts_with('\n'))
+            {
+                serializer.write_text("\n")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Serialize for SerializableNode<'a> {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: &mut S,
+        traversal_scope: TraversalScope,
+    ) -> io::Result<()> {
+        let node = self.node_tree.get_node(self.node_ref);
+        match (traversal_scope, node.data()) {
+            (TraversalScope::IncludeNode, NodeData::Element(element)) => {
+                self.serialize_element(serializer, element)
+            }
+            (_, NodeData::Element(_))
+            | (_, NodeData::Document(_))
+            | (_, NodeData::DocumentFragment) => self.serialize_children(serializer),
+            (TraversalScope::ChildrenOnly(_), _) => Ok(()),
+            (TraversalScope::IncludeNode, NodeData::Doctype(doctype)) => {
+                serializer.write_doctype(&doctype.name)
+            }
+            (TraversalScope::IncludeNode, NodeData::Text(text)) => serializer.write_text(text),
+            (TraversalScope::IncludeNode, NodeData::Comment(text)) => {
+                serializer.write_comment(text)
+            }
+            (TraversalScope::IncludeNode, NodeData::ProcessingInstruction((target, data))) => {
+                serializer.write_processing_instruction(target, data)
+            }
+        }
+    }
+}
+
+impl NodeTree {
+    /// Serialize a node (or only its children) as HTML into `writer`.
+    pub fn serialize<W: Write>(
+        &self,
+        node_ref: NodeRef,
+        writer: W,
+        traversal_scope: TraversalScope,
+    ) -> io::Result<()> {
+        serialize(
+            writer,
+            &SerializableNode::new(self, node_ref),
+            SerializeOpts {
+                traversal_scope,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Serialize the whole tree as an HTML string.
+    pub fn to_html_string(&self) -> String {
+        self.outer_html(self.root())
+    }
+
+    /// Serialize the whole tree as HTML encoded in `encoding`.
+    ///
+    /// `<meta>` charset declarations are rewritten to match, and characters `encoding` can not
+    /// represent are written as numeric character references.
+    pub fn to_html_bytes(&self, encoding: &'static Encoding) -> Vec<u8> {
+        let mut html = Vec::new();
+        serialize(
+            &mut html,
+            &SerializableNode::new(self, self.root()).with_charset(encoding),
+            SerializeOpts {
+                traversal_scope: TraversalScope::IncludeNode,
+                ..Default::default()
+            },
+        )
+        .expect("writing to a Vec<u8> can not fail");
+        encoding::encode(&String::from_utf8(html).unwrap(), encoding)
+    }
+
+    /// Serialize the whole tree as XML, such as one parsed with
+    /// [`parse_xml_bytes`](crate::sink::parse_xml_bytes).
+    ///
+    /// Elements and attributes keep their prefixes, and the namespaces they are in are
+    /// declared on the outermost elements using them.
+    pub fn to_xml_string(&self) -> String {
+        let mut xml = Vec::new();
+        let prefixes = RefCell::new(Vec::new());
+        let node = SerializableNode {
+            xml_prefixes: Some(&prefixes),
+            ..SerializableNode::new(self, self.root())
+        };
+        xml5ever::serialize::serialize(&mut xml, &node, Default::default())
+            .expect("writing to a Vec<u8> can not fail");
+        String::from_utf8(xml).unwrap()
+    }
+
+    /// Serialize a node and its descendants as an HTML string.
+    pub fn outer_html(&self, node_ref: NodeRef) -> String {
+        self.to_string_with_scope(node_ref, TraversalScope::IncludeNode)
+    }
+
+    /// Serialize the descendants of a node as an HTML string.
+    pub fn inner_html(&self, node_ref: NodeRef) -> String {
+        // the serializer needs the parent's name to know not to escape `<script>` contents
+        let parent_name = self
+            .get_node(node_ref)
+            .as_element()
+            .map(|element| element.name.clone());
+        self.to_string_with_scope(node_ref, TraversalScope::ChildrenOnly(parent_name))
+    }
+
+    fn to_string_with_scope(&self, node_ref: NodeRef, traversal_scope: TraversalScope) -> String {
+        let mut html = Vec::new();
+        self.serialize(node_ref, &mut html, traversal_scope)
+            .expect("writing to a Vec<u8> can not fail");
+        String::from_utf8(html).unwrap()
+    }
+}
+
+/// The name of an attribute as written in HTML, such as `xlink:href`.
+pub(crate) fn attribute_name<'a>(
+    name: &'a attributes::ExpandedName,
+    attribute: &attributes::Attribute,
+) -> Cow<'a, str> {
+    match name.ns {
+        ns!() => Cow::Borrowed(&*name.local),
+        ns!(xml) => Cow::Owned(format!("xml:{}", name.local)),
+        ns!(xmlns) if name.local == local_name!("xmlns") => Cow::Borrowed("xmlns"),
+        ns!(xmlns) => Cow::Owned(format!("xmlns:{}", name.local)),
+        ns!(xlink) => Cow::Owned(format!("xlink:{}", name.local)),
+        _ => match &attribute.prefix {
+            Some(prefix) => Cow::Owned(format!("{}:{}", prefix, name.local)),
+            None => Cow::Borrowed(&*name.local),
+        },
+    }
+}
+
+/// Escapes text content, or a double-quoted attribute value when `attr_mode` is set.
+pub(crate) fn escape(text: &str, attr_mode: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '\u{00A0}' => escaped.push_str("&nbsp;"),
+            '"' if attr_mode => escaped.push_str("&quot;"),
+            '<' if !attr_mode => escaped.push_str("&lt;"),
+            '>' if !attr_mode => escaped.push_str("&gt;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub(crate) fn html_local_name(node: &Node) -> Option<&LocalName> {
+    node.as_element()
+        .filter(|element| element.name.ns == ns!(html))
+        .map(|element| &element.name.local)
+}
+
+/// Replaces every run of ASCII whitespace with a single space.
+pub(crate) fn collapse(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut in_whitespace = false;
+    for c in text.chars() {
+        if c.is_ascii_whitespace() {
+            if !in_whitespace {
+                collapsed.push(' ');
+            }
+            in_whitespace = true;
+        } else {
+            collapsed.push(c);
+            in_whitespace = false;
+        }
+    }
+    collapsed
+}
+
+pub(crate) fn preserves_whitespace(local: &LocalName) -> bool {
+    matches!(
+        *local,
+        local_name!("pre")
+            | local_name!("textarea")
+            | local_name!("script")
+            | local_name!("style")
+            | local_name!("listing")
+            | local_name!("plaintext")
+            | local_name!("xmp")
+    )
+}
+
+pub(crate) fn drops_leading_newline(local: &LocalName) -> bool {
+    matches!(
+        *local,
+        local_name!("pre") | local_name!("textarea") | local_name!("listing")
+    )
+}
+
+pub(crate) fn is_void(local: &LocalName) -> bool {
+    matches!(
+        *local,
+        local_name!("area")
+            | local_name!("base")
+            | local_name!("basefont")
+            | local_name!("bgsound")
+            | local_name!("br")
+            | local_name!("col")
+            | local_name!("embed")
+            | local_name!("frame")
+            | local_name!("hr")
+            | local_name!("img")
+            | local_name!("input")
+            | local_name!("keygen")
+            | local_name!("link")
+            | local_name!("meta")
+            | local_name!("param")
+            | local_name!("source")
+            | local_name!("track")
+            | local_name!("wbr")
+    )
+}
+
+pub(crate) fn is_raw_text(local: &LocalName) -> bool {
+    matches!(
+        *local,
+        local_name!("style")
+            | local_name!("script")
+            | local_name!("xmp")
+            | local_name!("iframe")
+            | local_name!("noembed")
+            | local_name!("noframes")
+            | local_name!("plaintext")
+            | local_name!("noscript")
+    )
+}
@@ -1,67 +1,508 @@
-use html5ever::tendril::StrTendril;
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use html5ever::tendril::{fmt::UTF8, StrTendril, TendrilSink};
 use html5ever::tree_builder::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
 use html5ever::{self, Attribute, ExpandedName, QualName};
 use std::borrow::Cow;
+use std::cell::Cell;
 use std::collections::HashSet;
-use std::marker::PhantomData;
+use std::io::{self, Read};
+use std::rc::Rc;
 
-//use crate::attributes;
+use crate::attributes;
+use crate::encoding;
+use crate::serializer::{html_local_name, is_raw_text};
+use crate::span::{self, SourceFile, Span};
 use crate::tree::*;
 
+/// Options for the HTML parser.
+#[derive(Default)]
+pub struct ParseOpts {
+    /// Options for the HTML tokenizer.
+    pub tokenizer: html5ever::tokenizer::TokenizerOpts,
+
+    /// Options for the HTML tree builder.
+    pub tree_builder: html5ever::tree_builder::TreeBuilderOpts,
+
+    /// A callback for HTML parse errors (which are never fatal).
+    pub on_parse_error: Option<Box<dyn FnMut(Cow<'static, str>)>>,
+
+    /// The encoding assumed for byte input that has no BOM, no `<meta charset>` and is not
+    /// valid UTF-8. Defaults to `windows-1252`, like browsers in most locales.
+    pub fallback_encoding: Option<&'static Encoding>,
+
+    /// What to call the input in diagnostics and source maps, such as its path.
+    pub source_name: Option<String>,
+}
+
+/// Parse an HTML document with html5ever and the default configuration.
+pub fn parse_html() -> html5ever::Parser<Sink> {
+    parse_html_with_options(ParseOpts::default())
+}
+
+/// Parse an HTML document with html5ever with custom configuration.
+pub fn parse_html_with_options(opts: ParseOpts) -> html5ever::Parser<Sink> {
+    parse_html_with_encoding(opts, UTF_8)
+}
+
+/// Parse an HTML document from bytes, detecting its encoding, with the default configuration.
+pub fn parse_html_bytes(bytes: &[u8]) -> NodeTree {
+    parse_html_bytes_with_options(ParseOpts::default(), bytes)
+}
+
+/// Parse an HTML document from bytes, detecting its encoding, with custom configuration.
+///
+/// The detected encoding is recorded in the document's [`DocumentData`], and the decoded
+/// source in the tree, together with the [`Span`] of every node that came from it.
+pub fn parse_html_bytes_with_options(opts: ParseOpts, bytes: &[u8]) -> NodeTree {
+    let fallback = opts.fallback_encoding.unwrap_or(WINDOWS_1252);
+    let (text, encoding) = encoding::decode(bytes, fallback);
+    let source = Rc::new(SourceFile::new(opts.source_name, text));
+    let fed = Rc::new(Cell::new(Span::new(0, 0)));
+    let sink = Sink::new(encoding, opts.on_parse_error).with_spans(source.clone(), fed.clone());
+    let html5opts = html5ever::ParseOpts {
+        tokenizer: opts.tokenizer,
+        tree_builder: opts.tree_builder,
+    };
+    feed(
+        html5ever::parse_document(sink, html5opts),
+        &source.text,
+        &fed,
+    )
+}
+
+/// Read and parse an HTML document, detecting its encoding, with the default configuration.
+pub fn parse_html_from<R: Read>(reader: R) -> io::Result<NodeTree> {
+    parse_html_from_with_options(ParseOpts::default(), reader)
+}
+
+/// Read and parse an HTML document, detecting its encoding, with custom configuration.
+pub fn parse_html_from_with_options<R: Read>(
+    opts: ParseOpts,
+    mut reader: R,
+) -> io::Result<NodeTree> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    Ok(parse_html_bytes_with_options(opts, &bytes))
+}
+
+fn parse_html_with_encoding(
+    opts: ParseOpts,
+    encoding: &'static Encoding,
+) -> html5ever::Parser<Sink> {
+    let sink = Sink::new(encoding, opts.on_parse_error);
+    let html5opts = html5ever::ParseOpts {
+        tokenizer: opts.tokenizer,
+        tree_builder: opts.tree_builder,
+    };
+    html5ever::parse_document(sink, html5opts)
+}
+
+/// Parse an HTML fragment with html5ever and the default configuration.
+pub fn parse_fragment(ctx_name: QualName, ctx_attr: Vec<Attribute>) -> html5ever::Parser<Sink> {
+    parse_fragment_with_options(ParseOpts::default(), ctx_name, ctx_attr)
+}
+
+/// Parse an HTML fragment with html5ever with custom configuration.
+pub fn parse_fragment_with_options(
+    opts: ParseOpts,
+    ctx_name: QualName,
+    ctx_attr: Vec<Attribute>,
+) -> html5ever::Parser<Sink> {
+    let sink = Sink::new(UTF_8, opts.on_parse_error);
+    let html5opts = html5ever::ParseOpts {
+        tokenizer: opts.tokenizer,
+        tree_builder: opts.tree_builder,
+    };
+    html5ever::parse_fragment(sink, html5opts, ctx_name, ctx_attr)
+}
+
+/// Parse an HTML fragment from bytes, detecting its encoding, with custom configuration.
+///
+/// Like [`parse_html_bytes_with_options`], the decoded source is recorded in the tree,
+/// together with the [`Span`] of every node that came from it.
+pub fn parse_fragment_bytes_with_options(
+    opts: ParseOpts,
+    ctx_name: QualName,
+    ctx_attr: Vec<Attribute>,
+    bytes: &[u8],
+) -> NodeTree {
+    let fallback = opts.fallback_encoding.unwrap_or(WINDOWS_1252);
+    let (text, encoding) = encoding::decode(bytes, fallback);
+    let source = Rc::new(SourceFile::new(opts.source_name, text));
+    let fed = Rc::new(Cell::new(Span::new(0, 0)));
+    let sink = Sink::new(encoding, opts.on_parse_error).with_spans(source.clone(), fed.clone());
+    let html5opts = html5ever::ParseOpts {
+        tokenizer: opts.tokenizer,
+        tree_builder: opts.tree_builder,
+    };
+    feed(
+        html5ever::parse_fragment(sink, html5opts, ctx_name, ctx_attr),
+        &source.text,
+        &fed,
+    )
+}
+
+/// Options for the XML parser.
+#[derive(Default)]
+pub struct XmlParseOpts {
+    /// Options for the XML tokenizer.
+    pub tokenizer: xml5ever::tokenizer::XmlTokenizerOpts,
+
+    /// Options for the XML tree builder.
+    pub tree_builder: xml5ever::tree_builder::XmlTreeBuilderOpts,
+
+    /// A callback for XML parse errors (which are never fatal).
+    pub on_parse_error: Option<Box<dyn FnMut(Cow<'static, str>)>>,
+
+    /// What to call the input in diagnostics and source maps, such as its path.
+    pub source_name: Option<String>,
+}
+
+/// Parse an XML document (XHTML, SVG, RSS...) with xml5ever and the default configuration.
+pub fn parse_xml() -> xml5ever::driver::XmlParser<Sink> {
+    parse_xml_with_options(XmlParseOpts::default())
+}
+
+/// Parse an XML document with xml5ever with custom configuration.
+pub fn parse_xml_with_options(opts: XmlParseOpts) -> xml5ever::driver::XmlParser<Sink> {
+    parse_xml_with_encoding(opts, UTF_8)
+}
+
+/// Parse an XML document from bytes, detecting its encoding, with the default configuration.
+pub fn parse_xml_bytes(bytes: &[u8]) -> NodeTree {
+    parse_xml_bytes_with_options(XmlParseOpts::default(), bytes)
+}
+
+/// Parse an XML document from bytes, detecting its encoding, with custom configuration.
+///
+/// Like [`parse_html_bytes_with_options`], the tree keeps the source and node spans.
+pub fn parse_xml_bytes_with_options(opts: XmlParseOpts, bytes: &[u8]) -> NodeTree {
+    let (text, encoding) = encoding::decode_xml(bytes);
+    let source = Rc::new(SourceFile::new(opts.source_name, text));
+    let fed = Rc::new(Cell::new(Span::new(0, 0)));
+    let sink = Sink::new(encoding, opts.on_parse_error).with_spans(source.clone(), fed.clone());
+    let xml5opts = xml5ever::driver::XmlParseOpts {
+        tokenizer: opts.tokenizer,
+        tree_builder: opts.tree_builder,
+    };
+    feed(
+        xml5ever::driver::parse_document(sink, xml5opts),
+        &source.text,
+        &fed,
+    )
+}
+
+fn parse_xml_with_encoding(
+    opts: XmlParseOpts,
+    encoding: &'static Encoding,
+) -> xml5ever::driver::XmlParser<Sink> {
+    let sink = Sink::new(encoding, opts.on_parse_error);
+    let xml5opts = xml5ever::driver::XmlParseOpts {
+        tokenizer: opts.tokenizer,
+        tree_builder: opts.tree_builder,
+    };
+    xml5ever::driver::parse_document(sink, xml5opts)
+}
+
+/// Feeds `source` to `parser` one tag (or run of text) at a time, so that while the sink
+/// receives a node, `fed` holds the part of the source the node came from.
+fn feed<P: TendrilSink<UTF8>>(mut parser: P, source: &str, fed: &Cell<Span>) -> P::Output {
+    let mut start = 0;
+    for (i, byte) in source.bytes().enumerate() {
+        let end = match byte {
+            b'<' => i,
+            b'>' => i + 1,
+            _ => continue,
+        };
+        if end > start {
+            fed.set(Span::new(start, end));
+            parser.process(StrTendril::from_slice(&source[start..end]));
+            start = end;
+        }
+    }
+    if source.len() > start {
+        fed.set(Span::new(start, source.len()));
+        parser.process(StrTendril::from_slice(&source[start..]));
+    }
+    fed.set(Span::new(source.len(), source.len()));
+    parser.finish()
+}
+
 /// Receives new tree nodes during parsing.
+///
+/// html5ever and xml5ever share the `TreeSink` interface, so the same sink builds a
+/// `NodeTree` for both. Element and attribute names keep the namespaces the parser resolved.
 pub struct Sink {
     orphan_nodes: HashSet<NodeRef>,
     node_tree: NodeTree,
     on_parse_error: Option<Box<dyn FnMut(Cow<'static, str>)>>,
+    spans: Option<SpanTracker>,
+}
+
+/// What the sink needs to know to give nodes their [`Span`]s.
+struct SpanTracker {
+    source: Rc<SourceFile>,
+    /// The part of the source the parser is currently processing.
+    fed: Rc<Cell<Span>>,
+    /// The start of the last start tag given to an element, so an element the tree builder
+    /// clones while handling a tag does not get the same span.
+    last_start_tag: Option<usize>,
+    /// The start of what was being fed when text was last added.
+    last_text_fed: Option<usize>,
+}
+
+impl Sink {
+    fn new(
+        encoding: &'static Encoding,
+        on_parse_error: Option<Box<dyn FnMut(Cow<'static, str>)>>,
+    ) -> Self {
+        let node_tree = NodeTree::new(NodeData::Document(DocumentData {
+            _quirks_mode: QuirksMode::NoQuirks,
+            encoding,
+        }));
+        Sink {
+            orphan_nodes: HashSet::new(),
+            node_tree,
+            on_parse_error,
+            spans: None,
+        }
+    }
+
+    fn with_spans(mut self, source: Rc<SourceFile>, fed: Rc<Cell<Span>>) -> Self {
+        self.spans = Some(SpanTracker {
+            source,
+            fed,
+            last_start_tag: None,
+            last_text_fed: None,
+        });
+        self
+    }
+
+    /// The span of a node the parser is creating now, given how to find its start in the
+    /// source before the end of what is being fed.
+    fn current_span(&self, find_start: impl FnOnce(&str, usize) -> Option<usize>) -> Option<Span> {
+        let spans = self.spans.as_ref()?;
+        let end = spans.fed.get().end;
+        find_start(&spans.source.text, end).map(|start| Span::new(start, end))
+    }
+
+    /// Gives a new or grown text node in `parent_ref` the span of what is being fed.
+    ///
+    /// The tokenizer only knows that a character reference at the end of some text is over
+    /// once it sees the `<` after it, so the first text added while a tag is fed right after
+    /// one ends before that tag instead.
+    fn extend_text_span(&mut self, node_ref: NodeRef, parent_ref: NodeRef) {
+        let raw_text =
+            html_local_name(self.node_tree.get_node(parent_ref)).is_some_and(is_raw_text);
+        if let Some(spans) = &mut self.spans {
+            let mut fed = spans.fed.get();
+            let source = &spans.source.text;
+            let first_in_tag =
+                source[fed.range()].starts_with('<') && spans.last_text_fed != Some(fed.start);
+            spans.last_text_fed = Some(fed.start);
+            if let (true, false, Some(start)) = (
+                first_in_tag,
+                raw_text,
+                character_reference_at_end(&source[..fed.start]),
+            ) {
+                fed = Span::new(start, fed.start);
+            }
+            let node = self.node_tree.get_node_mut(node_ref);
+            let span = node.span().map_or(fed, |span| span.to(fed));
+            node.set_span(Some(span));
+        }
+    }
+
+    fn new_orphan_node(&mut self, data: NodeData) -> NodeRef {
+        let node = self.node_tree.new_node(data);
+        self.orphan_nodes.insert(node);
+        node
+    }
+}
+
+/// Where the character reference `text` ends with starts, like the `&` of `&eacute;`,
+/// `&#233;` or a legacy `&amp` without its semicolon.
+fn character_reference_at_end(text: &str) -> Option<usize> {
+    let start = text.rfind('&')?;
+    let name = text[start + 1..]
+        .strip_suffix(';')
+        .unwrap_or(&text[start + 1..]);
+    let name = name.strip_prefix('#').unwrap_or(name);
+    if !name.is_empty() && name.bytes().all(|byte| byte.is_ascii_alphanumeric()) {
+        Some(start)
+    } else {
+        None
+    }
+}
+
+/// Gives the elements under `node_ref` that have a start tag span the span of the whole
+/// element, and every node with a span its `source`, returning where the last of the subtree's source ends.
+///
+/// An element ends after its last content, and the end tag written right after it if there is
+/// one; elements closed implicitly (like a `<li>` by the next one) have no end tag of their own.
+fn close_element_spans(
+    node_tree: &mut NodeTree,
+    source: &Rc<SourceFile>,
+    node_ref: NodeRef,
+) -> Option<usize> {
+    let node = node_tree.get_node(node_ref);
+    if node.span().is_some() || node.start_tag_span().is_some() {
+        node_tree
+            .get_node_mut(node_ref)
+            .set_source(Some(source.clone()));
+    }
+    let node = node_tree.get_node(node_ref);
+    let children_parent_ref = node
+        .as_element()
+        .and_then(|element| element.template_contents)
+        .unwrap_or(node_ref);
+    let tag_name = node
+        .as_element()
+        .map(|element| span::tag_name(&element.name).into_owned());
+    let start_tag_span = node.start_tag_span();
+    let own_end = node.span().map(|span| span.end);
+
+    let children: Vec<NodeRef> = node_tree.children(children_parent_ref).collect();
+    let content_end = children
+        .into_iter()
+        .filter_map(|child_ref| close_element_spans(node_tree, source, child_ref))
+        .max();
+
+    match (start_tag_span, tag_name) {
+        (Some(start_tag_span), Some(tag_name)) => {
+            let content_end =
+                content_end.map_or(start_tag_span.end, |end| end.max(start_tag_span.end));
+            let end =
+                span::end_tag_end(&source.text, content_end, &tag_name).unwrap_or(content_end);
+            node_tree
+                .get_node_mut(node_ref)
+                .set_span(Some(Span::new(start_tag_span.start, end)));
+            Some(end)
+        }
+        _ => own_end.max(content_end),
+    }
+}
+
+/// Converts html5ever attributes into the [`attributes::Attributes`] model.
+pub(crate) fn convert_attributes(attrs: Vec<Attribute>) -> attributes::Attributes {
+    let map = attrs
+        .into_iter()
+        .map(|attr| {
+            let Attribute {
+                name: QualName { prefix, ns, local },
+                value,
+            } = attr;
+            let value = String::from(value);
+            (
+                attributes::ExpandedName { ns, local },
+                attributes::Attribute { prefix, value },
+            )
+        })
+        .collect();
+    attributes::Attributes { map }
 }
 
 impl TreeSink for Sink {
     type Handle = NodeRef;
     type Output = NodeTree;
 
-    fn finish(self) -> Self::Output {
-        todo!()
+    fn finish(mut self) -> Self::Output {
+        if let Some(spans) = self.spans.take() {
+            let root = self.node_tree.root();
+            close_element_spans(&mut self.node_tree, &spans.source, root);
+            self.node_tree.set_source(spans.source);
+        }
+        self.node_tree.mark_unmodified();
+        self.node_tree
     }
 
     fn parse_error(&mut self, msg: Cow<'static, str>) {
-        todo!()
+        if let Some(ref mut handler) = self.on_parse_error {
+            handler(msg)
+        }
     }
 
     fn get_document(&mut self) -> Self::Handle {
-        todo!()
+        self.node_tree.root()
     }
 
     fn elem_name<'b>(&'b self, target: &'b Self::Handle) -> ExpandedName<'b> {
-        todo!()
+        self.node_tree
+            .get_node(*target)
+            .as_element()
+            .unwrap()
+            .name
+            .expanded()
     }
 
     fn create_element(
         &mut self,
         name: QualName,
         attrs: Vec<Attribute>,
-        _flags: ElementFlags,
+        flags: ElementFlags,
     ) -> Self::Handle {
-        let node = self.node_tree.new_node(NodeData::Element(ElementData {
+        let template_contents = if flags.template {
+            Some(self.node_tree.new_node(NodeData::DocumentFragment))
+        } else {
+            None
+        };
+        let tag_name = span::tag_name(&name).into_owned();
+        let last_start_tag = self.spans.as_ref().and_then(|spans| spans.last_start_tag);
+        let start_tag_span = self
+            .current_span(|source, end| span::find_start_tag(source, end, &tag_name))
+            .filter(|span| Some(span.start) != last_start_tag);
+
+        let node = self.new_orphan_node(NodeData::Element(ElementData {
             name,
-            attrs,
-            //template_contents: None,
+            attributes: convert_attributes(attrs),
+            template_contents,
         }));
-        self.orphan_nodes.insert(node);
+        if let Some(start_tag_span) = start_tag_span {
+            self.node_tree
+                .get_node_mut(node)
+                .set_start_tag_span(Some(start_tag_span));
+            if let Some(spans) = &mut self.spans {
+                spans.last_start_tag = Some(start_tag_span.start);
+            }
+        }
         node
     }
 
     fn create_comment(&mut self, text: StrTendril) -> Self::Handle {
-        todo!()
+        let span = self.current_span(span::find_comment);
+        let node = self.new_orphan_node(NodeData::Comment(String::from(text)));
+        self.node_tree.get_node_mut(node).set_span(span);
+        node
     }
 
     fn create_pi(&mut self, target: StrTendril, data: StrTendril) -> Self::Handle {
-        todo!()
+        let span = self.current_span(span::find_processing_instruction);
+        let node = self.new_orphan_node(NodeData::ProcessingInstruction((
+            String::from(target),
+            String::from(data),
+        )));
+        self.node_tree.get_node_mut(node).set_span(span);
+        node
     }
 
     fn append(&mut self, parent: &Self::Handle, child: NodeOrText<Self::Handle>) {
         match child {
-            NodeOrText::AppendNode(handle) => self.node_tree.append(*parent, handle),
-            NodeOrText::AppendText(_) => todo!(),
+            NodeOrText::AppendNode(handle) => {
+                self.orphan_nodes.remove(&handle);
+                self.node_tree.append(*parent, handle)
+            }
+            NodeOrText::AppendText(text) => {
+                if let Some(last_child) = self.node_tree.get_node(*parent).last_child() {
+                    if let Some(existing) = self.node_tree.get_node_mut(last_child).as_text_mut() {
+                        existing.push_str(&text);
+                        self.extend_text_span(last_child, *parent);
+                        return;
+                    }
+                }
+                let node = self.node_tree.new_node(NodeData::Text(String::from(text)));
+                self.extend_text_span(node, *parent);
+                self.node_tree.append(*parent, node)
+            }
         }
     }
 
@@ -85,11 +526,24 @@ impl TreeSink for Sink {
         public_id: StrTendril,
         system_id: StrTendril,
     ) {
-        todo!()
+        let doctype = self.node_tree.new_node(NodeData::Doctype(Doctype {
+            name: String::from(name),
+            public_id: String::from(public_id),
+            system_id: String::from(system_id),
+        }));
+        let span = self.current_span(span::find_doctype);
+        self.node_tree.get_node_mut(doctype).set_span(span);
+        let root = self.node_tree.root();
+        self.node_tree.append(root, doctype)
     }
 
     fn get_template_contents(&mut self, target: &Self::Handle) -> Self::Handle {
-        todo!()
+        self.node_tree
+            .get_node(*target)
+            .as_element()
+            .unwrap()
+            .template_contents
+            .unwrap()
     }
 
     fn same_node(&self, x: &Self::Handle, y: &Self::Handle) -> bool {
@@ -97,7 +551,12 @@ impl TreeSink for Sink {
     }
 
     fn set_quirks_mode(&mut self, mode: QuirksMode) {
-        todo!()
+        let root = self.node_tree.root();
+        self.node_tree
+            .get_node_mut(root)
+            .as_document_mut()
+            .unwrap()
+            ._quirks_mode = mode;
     }
 
     fn append_before_sibling(
@@ -105,16 +564,48 @@ impl TreeSink for Sink {
         sibling: &Self::Handle,
         new_node: NodeOrText<Self::Handle>,
     ) {
-        todo!()
+        match new_node {
+            NodeOrText::AppendNode(handle) => {
+                self.orphan_nodes.remove(&handle);
+                self.node_tree.append_before_sibling(*sibling, handle)
+            }
+            NodeOrText::AppendText(text) => {
+                let parent_ref = self.node_tree.parent(*sibling).unwrap_or(*sibling);
+                if let Some(previous_sibling) = self.node_tree.get_node(*sibling).previous_sibling()
+                {
+                    if let Some(existing) =
+                        self.node_tree.get_node_mut(previous_sibling).as_text_mut()
+                    {
+                        existing.push_str(&text);
+                        self.extend_text_span(previous_sibling, parent_ref);
+                        return;
+                    }
+                }
+                let node = self.node_tree.new_node(NodeData::Text(String::from(text)));
+                self.extend_text_span(node, parent_ref);
+                self.node_tree.append_before_sibling(*sibling, node)
+            }
+        }
     }
 
     fn add_attrs_if_missing(&mut self, target: &Self::Handle, attrs: Vec<Attribute>) {
-        todo!()
+        let element = self
+            .node_tree
+            .get_node_mut(*target)
+            .as_element_mut()
+            .unwrap();
+
+        for (name, attribute) in convert_attributes(attrs).map {
+            element.attributes.map.entry(name).or_insert(attribute);
+        }
     }
 
-    fn remove_from_parent(&mut self, target: &Self::Handle) {}
+    fn remove_from_parent(&mut self, target: &Self::Handle) {
+        self.node_tree.remove_from_parent(*target);
+        self.orphan_nodes.insert(*target);
+    }
 
     fn reparent_children(&mut self, node: &Self::Handle, new_parent: &Self::Handle) {
-        todo!()
+        self.node_tree.reparent_children(*node, *new_parent)
     }
 }
@@ -0,0 +1,141 @@
+use html5ever::{namespace_url, ns};
+
+use crate::serializer::html_local_name;
+use crate::tree::*;
+
+/// The content passed for one slot: its name (empty for the default slot) and the children
+/// of the caller that go into it, in order.
+struct Provided {
+    name: String,
+    nodes: Vec<NodeRef>,
+}
+
+/// Whether a node passed as slot content shows anything, unlike whitespace and comments.
+fn is_content(node: &Node) -> bool {
+    match node.data() {
+        NodeData::Text(text) => !text.trim().is_empty(),
+        NodeData::Comment(_) => false,
+        _ => true,
+    }
+}
+
+fn is_slot(node: &Node) -> bool {
+    node.as_element()
+        .is_some_and(|element| element.name.ns == ns!(html))
+        && html_local_name(node).is_some_and(|local| &**local == "slot")
+}
+
+impl NodeTree {
+    /// Fills the `<slot>` elements under `node_ref` with the children of `caller_ref`, the
+    /// element of `caller` a component was used as.
+    ///
+    /// A child with a `slot="name"` attribute goes into the `<slot name="name">`, without the
+    /// attribute (and for a `<template>`, only its contents); all the other children go into
+    /// the default `<slot>`, the one without a name. A slot nothing is passed for is replaced
+    /// with its own children, the fallback content.
+    ///
+    /// Returns the children of `caller_ref` that the component has no slot for.
+    pub fn fill_slots(
+        &mut self,
+        node_ref: NodeRef,
+        caller: &NodeTree,
+        caller_ref: NodeRef,
+    ) -> Vec<NodeRef> {
+        let mut provided: Vec<Provided> = Vec::new();
+        for child_ref in caller.children(caller_ref) {
+            let name = caller
+                .get_node(child_ref)
+                .as_element()
+                .and_then(|element| element.attributes.get("slot"))
+                .unwrap_or("");
+            match provided.iter_mut().find(|slot| slot.name == name) {
+                Some(slot) => slot.nodes.push(child_ref),
+                None => provided.push(Provided {
+                    name: String::from(name),
+                    nodes: vec![child_ref],
+                }),
+            }
+        }
+        // the default slot only counts as passed when there is more than whitespace for it
+        provided.retain(|slot| {
+            !slot.name.is_empty()
+                || slot
+                    .nodes
+                    .iter()
+                    .any(|&node_ref| is_content(caller.get_node(node_ref)))
+        });
+
+        let mut slots = Vec::new();
+        let mut stack = vec![node_ref];
+        while let Some(node_ref) = stack.pop() {
+            let node = self.get_node(node_ref);
+            if is_slot(node) {
+                slots.push(node_ref);
+            }
+            if let Some(template_contents) = node.as_element().and_then(|e| e.template_contents) {
+                stack.push(template_contents);
+            }
+            // reversed, so the stack pops them in document order
+            let children: Vec<NodeRef> = self.children(node_ref).collect();
+            stack.extend(children.into_iter().rev());
+        }
+
+        let mut used = vec![false; provided.len()];
+        for slot_ref in slots {
+            // a slot in the fallback content of a filled slot is gone
+            if !self.contains(slot_ref) {
+                continue;
+            }
+            let element = self.get_node(slot_ref).as_element().unwrap();
+            let name = element.attributes.get("name").unwrap_or("");
+            match provided.iter().position(|slot| slot.name == name) {
+                Some(index) => {
+                    used[index] = true;
+                    for &content_ref in &provided[index].nodes {
+                        self.project(caller, content_ref, slot_ref);
+                    }
+                }
+                None => {
+                    let fallback: Vec<NodeRef> = self.children(slot_ref).collect();
+                    for child_ref in fallback {
+                        self.remove_from_parent(child_ref);
+                        self.append_before_sibling(slot_ref, child_ref);
+                    }
+                }
+            }
+            self.remove_subtree(slot_ref);
+        }
+
+        provided
+            .into_iter()
+            .zip(used)
+            .filter(|(_, used)| !used)
+            .flat_map(|(slot, _)| slot.nodes)
+            .filter(|&node_ref| is_content(caller.get_node(node_ref)))
+            .collect()
+    }
+
+    /// Copies a node passed as slot content from `caller` in front of `slot_ref`.
+    fn project(&mut self, caller: &NodeTree, content_ref: NodeRef, slot_ref: NodeRef) {
+        let content = caller.get_node(content_ref);
+        let element = match content.as_element() {
+            Some(element) if element.attributes.contains("slot") => element,
+            _ => {
+                let copy_ref = self.import_subtree(caller, content_ref);
+                self.append_before_sibling(slot_ref, copy_ref);
+                return;
+            }
+        };
+        if let Some(template_contents) = element.template_contents {
+            for child_ref in caller.children(template_contents) {
+                let copy_ref = self.import_subtree(caller, child_ref);
+                self.append_before_sibling(slot_ref, copy_ref);
+            }
+            return;
+        }
+        let copy_ref = self.import_subtree(caller, content_ref);
+        let copy = self.get_node_mut(copy_ref).as_element_mut().unwrap();
+        copy.attributes.remove("slot");
+        self.append_before_sibling(slot_ref, copy_ref);
+    }
+}
@@ -0,0 +1,326 @@
+use html5ever::serialize::{HtmlSerializer, Serialize, SerializeOpts, Serializer, TraversalScope};
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::ast::write_json_string;
+use crate::serializer::{html_local_name, is_void, SerializableNode};
+use crate::span::SourceFile;
+use crate::tree::*;
+
+/// Where each part of some serialized HTML came from, in the templates the nodes were parsed
+/// from.
+///
+/// Nodes keep their source file and span through cloning and moving between trees, so output
+/// produced by loops and components maps back to the loop body or component that wrote it.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    sources: Vec<Rc<SourceFile>>,
+    /// Line starts of each source, by byte offset.
+    line_starts: Vec<Vec<usize>>,
+    mappings: Vec<Mapping>,
+}
+
+/// A point in the output, and the point in a source it came from (unless it was generated).
+/// Lines and columns are 0-based, and columns count UTF-16 code units, as in source maps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Mapping {
+    generated_line: u32,
+    generated_column: u32,
+    original: Option<Original>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Original {
+    source: usize,
+    line: u32,
+    column: u32,
+}
+
+impl SourceMap {
+    /// The files the output maps into, in the order of the `"sources"` field.
+    pub fn sources(&self) -> &[Rc<SourceFile>] {
+        &self.sources
+    }
+
+    /// The file, line and column (0-based, in UTF-16 code units) that the output at `line`
+    /// and `column` came from, or `None` when it was generated.
+    pub fn lookup(&self, line: u32, column: u32) -> Option<(&SourceFile, u32, u32)> {
+        self.mappings
+            .iter()
+            .take_while(|mapping| {
+                (mapping.generated_line, mapping.generated_column) <= (line, column)
+            })
+            .last()
+            .filter(|mapping| mapping.generated_line == line)
+            .and_then(|mapping| mapping.original)
+            .map(|original| {
+                (
+                    &*self.sources[original.source],
+                    original.line,
+                    original.column,
+                )
+            })
+    }
+
+    /// The source map as version 3 JSON. `file` is the name of the generated file.
+    ///
+    /// Sources without a name are written as empty strings; the content of every source is
+    /// included, so the map is usable on its own.
+    pub fn to_json(&self, file: Option<&str>) -> String {
+        let mut json = String::from("{\"version\":3,");
+        if let Some(file) = file {
+            json.push_str("\"file\":");
+            write_json_string(&mut json, file);
+            json.push(',');
+        }
+        json.push_str("\"sources\":[");
+        for (i, source) in self.sources.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            write_json_string(&mut json, source.name.as_deref().unwrap_or(""));
+        }
+        json.push_str("],\"sourcesContent\":[");
+        for (i, source) in self.sources.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            write_json_string(&mut json, &source.text);
+        }
+        json.push_str("],\"names\":[],\"mappings\":\"");
+        json.push_str(&self.encode_mappings());
+        json.push_str("\"}");
+        json
+    }
+
+    fn encode_mappings(&self) -> String {
+        let mut encoded = String::new();
+        let mut line = 0;
+        let mut previous_generated_column = 0;
+        let mut previous_source = 0;
+        let mut previous_line = 0;
+        let mut previous_column = 0;
+        for (i, mapping) in self.mappings.iter().enumerate() {
+            if mapping.generated_line > line {
+                for _ in line..mapping.generated_line {
+                    encoded.push(';');
+                }
+                line = mapping.generated_line;
+                previous_generated_column = 0;
+            } else if i > 0 {
+                encoded.push(',');
+            }
+            encode_vlq(
+                &mut encoded,
+                i64::from(mapping.generated_column) - i64::from(previous_generated_column),
+            );
+            previous_generated_column = mapping.generated_column;
+            if let Some(original) = mapping.original {
+                encode_vlq(
+                    &mut encoded,
+                    original.source as i64 - previous_source as i64,
+                );
+                encode_vlq(
+                    &mut encoded,
+                    i64::from(original.line) - i64::from(previous_line),
+                );
+                encode_vlq(
+                    &mut encoded,
+                    i64::from(original.column) - i64::from(previous_column),
+                );
+                previous_source = original.source;
+                previous_line = original.line;
+                previous_column = original.column;
+            }
+        }
+        encoded
+    }
+
+    /// Where `offset` in `source` is, adding the source to the map if it is new.
+    fn original(&mut self, source: &Rc<SourceFile>, offset: usize) -> Original {
+        let index = match self.sources.iter().position(|s| Rc::ptr_eq(s, source)) {
+            Some(index) => index,
+            None => {
+                let line_starts = std::iter::once(0)
+                    .chain(source.text.match_indices('\n').map(|(i, _)| i + 1))
+                    .collect();
+                self.sources.push(source.clone());
+                self.line_starts.push(line_starts);
+                self.sources.len() - 1
+            }
+        };
+        let line_starts = &self.line_starts[index];
+        let line = line_starts.partition_point(|&start| start <= offset) - 1;
+        let column = source.text[line_starts[line]..offset]
+            .chars()
+            .map(char::len_utf16)
+            .sum::<usize>();
+        Original {
+            source: index,
+            line: line as u32,
+            column: column as u32,
+        }
+    }
+}
+
+fn encode_vlq(encoded: &mut String, value: i64) {
+    const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut vlq = if value < 0 {
+        ((-value) << 1) | 1
+    } else {
+        value << 1
+    };
+    loop {
+        let mut digit = vlq & 0b11111;
+        vlq >>= 5;
+        if vlq > 0 {
+            digit |= 0b100000;
+        }
+        encoded.push(BASE64[digit as usize] as char);
+        if vlq == 0 {
+            break;
+        }
+    }
+}
+
+/// Passes output through while keeping track of the line and column it is at.
+struct Tracking<W: Write> {
+    writer: W,
+    line: u32,
+    column: u32,
+}
+
+impl<W: Write> Write for Tracking<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write_all(buf)?;
+        for &byte in buf {
+            if byte == b'\n' {
+                self.line += 1;
+                self.column = 0;
+            } else if byte & 0b1100_0000 != 0b1000_0000 {
+                // a char outside the BMP (four UTF-8 bytes) is two UTF-16 code units
+                self.column += if byte >= 0b1111_0000 { 2 } else { 1 };
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+struct SourceMapper<'a, W: Write> {
+    node_tree: &'a NodeTree,
+    serializer: HtmlSerializer<Tracking<W>>,
+    source_map: SourceMap,
+}
+
+impl<'a, W: Write> SourceMapper<'a, W> {
+    /// Maps the output from here on to `offset` in the node's source, or to nothing.
+    fn mark(&mut self, node: &Node, offset: Option<usize>) {
+        let original = match (node.source(), offset) {
+            (Some(source), Some(offset)) => Some(self.source_map.original(source, offset)),
+            _ => None,
+        };
+        let writer = &self.serializer.writer;
+        let mapping = Mapping {
+            generated_line: writer.line,
+            generated_column: writer.column,
+            original,
+        };
+        match self.source_map.mappings.last_mut() {
+            // nothing was written since the last mapping, so it would never be used
+            Some(last)
+                if (last.generated_line, last.generated_column)
+                    == (mapping.generated_line, mapping.generated_column) =>
+            {
+                *last = mapping
+            }
+            _ => self.source_map.mappings.push(mapping),
+        }
+    }
+
+    fn node(&mut self, node_ref: NodeRef) -> io::Result<()> {
+        let node = self.node_tree.get_node(node_ref);
+        let start = node.span().map(|span| span.start);
+        match node.data() {
+            NodeData::Document(_) | NodeData::DocumentFragment => self.children(node_ref),
+            NodeData::Element(element) => {
+                self.mark(node, start);
+                SerializableNode::new(self.node_tree, node_ref)
+                    .serialize_start_tag(&mut self.serializer, element)?;
+                self.children(element.template_contents.unwrap_or(node_ref))?;
+                if !html_local_name(node).is_some_and(is_void) {
+                    self.mark(node, node.end_tag_span().map(|span| span.start));
+                }
+                self.serializer.end_elem(element.name.clone())
+            }
+            NodeData::Text(text) => {
+                // every line gets its own mapping, as long as the source has the same lines
+                let source_text = match (node.source(), node.span()) {
+                    (Some(source), Some(span)) => &source.text[span.range()],
+                    _ => "",
+                };
+                let mut source_lines = source_text.match_indices('\n');
+                let mut line_start = start;
+                for (i, line) in text.split_inclusive('\n').enumerate() {
+                    if i > 0 {
+                        line_start = source_lines
+                            .next()
+                            .and_then(|(newline, _)| start.map(|start| start + newline + 1));
+                    }
+                    self.mark(node, line_start);
+                    self.serializer.write_text(line)?;
+                }
+                Ok(())
+            }
+            _ => {
+                self.mark(node, start);
+                SerializableNode::new(self.node_tree, node_ref)
+                    .serialize(&mut self.serializer, TraversalScope::IncludeNode)
+            }
+        }
+    }
+
+    fn children(&mut self, parent_ref: NodeRef) -> io::Result<()> {
+        for child_ref in self.node_tree.children(parent_ref) {
+            self.node(child_ref)?;
+        }
+        Ok(())
+    }
+}
+
+impl NodeTree {
+    /// Serialize a node and its descendants as HTML into `writer`, returning a source map for
+    /// what was written.
+    pub fn serialize_with_source_map<W: Write>(
+        &self,
+        node_ref: NodeRef,
+        writer: W,
+    ) -> io::Result<SourceMap> {
+        let mut mapper = SourceMapper {
+            node_tree: self,
+            serializer: HtmlSerializer::new(
+                Tracking {
+                    writer,
+                    line: 0,
+                    column: 0,
+                },
+                SerializeOpts::default(),
+            ),
+            source_map: SourceMap::default(),
+        };
+        mapper.node(node_ref)?;
+        Ok(mapper.source_map)
+    }
+
+    /// Serialize the whole tree as an HTML string, together with its source map.
+    pub fn to_html_string_with_source_map(&self) -> (String, SourceMap) {
+        let mut html = Vec::new();
+        let source_map = self
+            .serialize_with_source_map(self.root(), &mut html)
+            .expect("writing to a Vec<u8> can not fail");
+        (String::from_utf8(html).unwrap(), source_map)
+    }
+}
@@ -0,0 +1,293 @@
+use html5ever::QualName;
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::ops::Range;
+
+/// A file the parser read, which [`Span`]s point into.
+#[derive(Debug)]
+pub struct SourceFile {
+    /// What to call the file in diagnostics and source maps, such as its path.
+    pub name: Option<String>,
+
+    /// The decoded text of the file.
+    pub text: String,
+
+    /// Where each line of `text` starts, and whether it is all ASCII, built the first time a
+    /// [`SourceFile::location`] is asked for.
+    lines: OnceCell<Vec<(usize, bool)>>,
+}
+
+impl SourceFile {
+    pub fn new(name: Option<String>, text: String) -> Self {
+        SourceFile {
+            name,
+            text,
+            lines: OnceCell::new(),
+        }
+    }
+
+    /// The line and column of a byte offset in the file, like [`location`] but without going
+    /// through the text before it every time.
+    pub fn location(&self, offset: usize) -> Location {
+        let lines = self.lines.get_or_init(|| {
+            let mut lines = Vec::new();
+            let mut start = 0;
+            for line in self.text.split_inclusive('\n') {
+                lines.push((start, line.is_ascii()));
+                start += line.len();
+            }
+            // the line after a last `\n`, or the only line of an empty file
+            if self.text.is_empty() || self.text.ends_with('\n') {
+                lines.push((start, true));
+            }
+            lines
+        });
+        let offset = offset.min(self.text.len());
+        let line = lines.partition_point(|&(start, _)| start <= offset);
+        let (start, is_ascii) = lines[line - 1];
+        let column = match is_ascii {
+            true => offset - start,
+            false => self.text[start..offset].chars().count(),
+        };
+        Location {
+            line,
+            column: column + 1,
+        }
+    }
+}
+
+impl PartialEq for SourceFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.text == other.text
+    }
+}
+
+impl Eq for SourceFile {}
+
+/// A range of bytes in the (decoded) source a tree was parsed from.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A position in the source, both 1-based. Columns count chars, not bytes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// Where the span starts in `source`.
+    pub fn start_location(&self, source: &str) -> Location {
+        location(source, self.start)
+    }
+
+    /// Where the span ends in `source`.
+    pub fn end_location(&self, source: &str) -> Location {
+        location(source, self.end)
+    }
+
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(&self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+/// The line and column of a byte offset in `source`.
+pub fn location(source: &str, offset: usize) -> Location {
+    let before = &source[..offset.min(source.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Location {
+        line: before.matches('\n').count() + 1,
+        column: before[line_start..].chars().count() + 1,
+    }
+}
+
+/// Where an attribute is in a start tag.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct AttributeSpan {
+    pub name: Span,
+    /// Where the value is, without its quotes; `None` for an attribute without `=`.
+    pub value: Option<Span>,
+}
+
+/// Finds the attributes in the source of a start tag, with `start` being where the tag is.
+pub(crate) fn attribute_spans(tag: &str, start: usize) -> Vec<(&str, AttributeSpan)> {
+    let bytes = tag.as_bytes();
+    let mut attributes = Vec::new();
+    // skip `<` and the tag name
+    let mut i = 1;
+    while i < bytes.len() && !is_tag_delimiter(bytes[i]) {
+        i += 1;
+    }
+    loop {
+        while i < bytes.len() && (bytes[i].is_ascii_whitespace() || bytes[i] == b'/') {
+            i += 1;
+        }
+        if i >= bytes.len() || bytes[i] == b'>' {
+            return attributes;
+        }
+        let name_start = i;
+        i += 1;
+        while i < bytes.len() && !is_tag_delimiter(bytes[i]) && bytes[i] != b'=' {
+            i += 1;
+        }
+        let name_end = i;
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        let mut value = None;
+        if bytes.get(i) == Some(&b'=') {
+            i += 1;
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            let (value_start, value_end) = match bytes.get(i) {
+                Some(&quote) if quote == b'"' || quote == b'\'' => {
+                    let value_start = i + 1;
+                    let value_end = tag[value_start..]
+                        .find(quote as char)
+                        .map_or(bytes.len(), |end| value_start + end);
+                    i = (value_end + 1).min(bytes.len());
+                    (value_start, value_end)
+                }
+                _ => {
+                    let value_start = i;
+                    while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' {
+                        i += 1;
+                    }
+                    (value_start, i)
+                }
+            };
+            value = Some(Span::new(start + value_start, start + value_end));
+        } else {
+            // not followed by `=`, so the whitespace belongs before the next attribute
+            i = name_end;
+        }
+        attributes.push((
+            &tag[name_start..name_end],
+            AttributeSpan {
+                name: Span::new(start + name_start, start + name_end),
+                value,
+            },
+        ));
+    }
+}
+
+/// The name of an element as written in its tags, such as `svg:rect`.
+pub(crate) fn tag_name(name: &QualName) -> Cow<'_, str> {
+    match &name.prefix {
+        Some(prefix) => Cow::Owned(format!("{}:{}", prefix, name.local)),
+        None => Cow::Borrowed(&*name.local),
+    }
+}
+
+/// If `source[..end]` finishes with a start tag named `name`, where that tag starts.
+pub(crate) fn find_start_tag(source: &str, end: usize, name: &str) -> Option<usize> {
+    if !source[..end].ends_with('>') {
+        return None;
+    }
+    // attribute values can hold `<`, so a few earlier candidates are tried as well
+    source[..end]
+        .rmatch_indices('<')
+        .take(16)
+        .map(|(start, _)| start)
+        .find(|&start| {
+            start_tag_end(source, start).is_some_and(|(tag_name, tag_end)| {
+                tag_end == end && tag_name.eq_ignore_ascii_case(name)
+            })
+        })
+}
+
+/// If an end tag named `name` starts at `start`, where it ends.
+pub(crate) fn end_tag_end(source: &str, start: usize, name: &str) -> Option<usize> {
+    let rest = source[start..].strip_prefix("</")?;
+    let tag_name = rest.get(..name.len())?;
+    if !tag_name.eq_ignore_ascii_case(name) {
+        return None;
+    }
+    let after_name = &rest[name.len()..];
+    if !after_name.starts_with(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/') {
+        return None;
+    }
+    after_name.find('>').map(|i| start + 2 + name.len() + i + 1)
+}
+
+/// If `source[..end]` finishes with a comment, where it starts.
+pub(crate) fn find_comment(source: &str, end: usize) -> Option<usize> {
+    let before = &source[..end];
+    if before.ends_with("-->") || before.ends_with("--!>") {
+        before.rfind("<!--")
+    } else {
+        None
+    }
+}
+
+/// If `source[..end]` finishes with a doctype, where it starts.
+pub(crate) fn find_doctype(source: &str, end: usize) -> Option<usize> {
+    let before = &source[..end];
+    if !before.ends_with('>') {
+        return None;
+    }
+    before.rfind("<!").filter(|&start| {
+        before[start + 2..]
+            .get(..7)
+            .is_some_and(|keyword| keyword.eq_ignore_ascii_case("doctype"))
+    })
+}
+
+/// If `source[..end]` finishes with a processing instruction, where it starts.
+pub(crate) fn find_processing_instruction(source: &str, end: usize) -> Option<usize> {
+    let before = &source[..end];
+    if before.ends_with("?>") {
+        before.rfind("<?")
+    } else {
+        None
+    }
+}
+
+/// Reads a start tag at `start`, returning its name and where it ends (after the `>`).
+fn start_tag_end(source: &str, start: usize) -> Option<(&str, usize)> {
+    let bytes = source.as_bytes();
+    let name_start = start + 1;
+    if !bytes.get(name_start)?.is_ascii_alphabetic() {
+        return None;
+    }
+    let mut i = name_start;
+    while i < bytes.len() && !is_tag_delimiter(bytes[i]) {
+        i += 1;
+    }
+    let name = &source[name_start..i];
+
+    let mut quote = None;
+    while i < bytes.len() {
+        match (quote, bytes[i]) {
+            (None, b'>') => return Some((name, i + 1)),
+            (None, b'"') | (None, b'\'') => {
+                // a quote only opens a value right after `=`
+                let previous = source[..i].trim_end_matches(|c: char| c.is_ascii_whitespace());
+                if previous.ends_with('=') {
+                    quote = Some(bytes[i]);
+                }
+            }
+            (Some(q), c) if q == c => quote = None,
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+fn is_tag_delimiter(byte: u8) -> bool {
+    byte.is_ascii_whitespace() || byte == b'/' || byte == b'>'
+}
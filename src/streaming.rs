@@ -0,0 +1,127 @@
+use html5ever::serialize::{HtmlSerializer, Serialize, SerializeOpts, Serializer, TraversalScope};
+use std::io::{self, BufWriter, Write};
+
+use crate::serializer::SerializableNode;
+use crate::tree::*;
+
+/// How much serialized output is held before it is written out, by default.
+const DEFAULT_BUFFER_CAPACITY: usize = 8 * 1024;
+
+/// Produces finished nodes one at a time, see [`Streamed::Expanded`].
+pub type Expansion<'a> = Box<dyn FnMut(&mut NodeTree) -> Option<NodeRef> + 'a>;
+
+/// What [`StreamingSerializer::stream`] should do with a node.
+pub enum Streamed<'a> {
+    /// Write the node, streaming its children the same way.
+    Node,
+
+    /// Write the nodes produced by this function in place of the node, until it returns
+    /// `None`. Each one is written and freed before the next one is asked for, so only one
+    /// of them has to exist at a time. This is how
+    /// [`Renderer::render_to`](crate::render::Renderer::render_to) writes the items of a
+    /// loop, rendering each one when it is asked for.
+    Expanded(Expansion<'a>),
+}
+
+/// Writes HTML to an `io::Write` as it goes, holding at most a fixed amount of output.
+pub struct StreamingSerializer<W: Write> {
+    serializer: HtmlSerializer<BufWriter<W>>,
+}
+
+impl<W: Write> StreamingSerializer<W> {
+    pub fn new(writer: W) -> Self {
+        Self::with_capacity(DEFAULT_BUFFER_CAPACITY, writer)
+    }
+
+    /// Buffers up to `capacity` bytes of output before writing to `writer`.
+    pub fn with_capacity(capacity: usize, writer: W) -> Self {
+        StreamingSerializer {
+            serializer: HtmlSerializer::new(
+                BufWriter::with_capacity(capacity, writer),
+                SerializeOpts::default(),
+            ),
+        }
+    }
+
+    /// Writes a node and its descendants, leaving the tree as it is.
+    pub fn write_node(&mut self, node_tree: &NodeTree, node_ref: NodeRef) -> io::Result<()> {
+        SerializableNode::new(node_tree, node_ref)
+            .serialize(&mut self.serializer, TraversalScope::IncludeNode)
+    }
+
+    /// Writes a node and its descendants, freeing each node as soon as it has been written.
+    ///
+    /// `expand` is asked about every node before it is written, which lets a render pass
+    /// finish a subtree right before it goes out instead of finishing the whole tree first.
+    pub fn stream<'a, F>(
+        &mut self,
+        node_tree: &mut NodeTree,
+        node_ref: NodeRef,
+        expand: &mut F,
+    ) -> io::Result<()>
+    where
+        F: FnMut(&mut NodeTree, NodeRef) -> Streamed<'a>,
+    {
+        match expand(node_tree, node_ref) {
+            Streamed::Expanded(mut next) => {
+                while let Some(produced_ref) = next(node_tree) {
+                    self.write_node(node_tree, produced_ref)?;
+                    node_tree.remove_subtree(produced_ref);
+                }
+                // producing the nodes may have taken the node itself out already
+                if node_tree.contains(node_ref) {
+                    node_tree.remove_subtree(node_ref);
+                }
+                Ok(())
+            }
+            Streamed::Node => {
+                let children_parent_ref = match node_tree.get_node(node_ref).data() {
+                    NodeData::Element(element) => {
+                        SerializableNode::new(node_tree, node_ref)
+                            .serialize_start_tag(&mut self.serializer, element)?;
+                        element.template_contents.unwrap_or(node_ref)
+                    }
+                    NodeData::Document(_) | NodeData::DocumentFragment => node_ref,
+                    _ => {
+                        self.write_node(node_tree, node_ref)?;
+                        node_tree.remove_subtree(node_ref);
+                        return Ok(());
+                    }
+                };
+
+                // streaming a child removes it, so the first child is always the next one
+                while let Some(child_ref) = node_tree.get_node(children_parent_ref).first_child() {
+                    self.stream(node_tree, child_ref, expand)?;
+                }
+
+                if let Some(element) = node_tree.get_node(node_ref).as_element() {
+                    self.serializer.end_elem(element.name.clone())?;
+                }
+                if node_ref != node_tree.root() {
+                    node_tree.remove_subtree(node_ref);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Flushes everything that is still buffered and returns the writer.
+    pub fn finish(self) -> io::Result<W> {
+        self.serializer
+            .writer
+            .into_inner()
+            .map_err(|error| error.into_error())
+    }
+}
+
+impl NodeTree {
+    /// Serialize the whole tree into `writer` without building the output in memory first.
+    ///
+    /// The tree is emptied in the process: every node is freed once it has been written.
+    pub fn stream_to<W: Write>(&mut self, writer: W) -> io::Result<W> {
+        let mut serializer = StreamingSerializer::new(writer);
+        let root = self.root();
+        serializer.stream(self, root, &mut |_, _| Streamed::Node)?;
+        serializer.finish()
+    }
+}
@@ -1,10 +1,10 @@
-//use crate::attributes::{Attribute, Attributes, ExpandedName};
-use html5ever::tendril::StrTendril;
-use html5ever::tree_builder::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
-use html5ever::{Attribute, QualName};
-use std::borrow::Cow;
-use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
+use crate::attributes::Attributes;
+use crate::span::{self, AttributeSpan, SourceFile, Span};
+use encoding_rs::Encoding;
+use html5ever::tree_builder::QuirksMode;
+use html5ever::QualName;
+use std::collections::HashMap;
+use std::rc::Rc;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum NodeData {
@@ -37,17 +37,36 @@ pub struct NodeTree {
     root: NodeRef,
     current_ref: NodeRef,
     nodes: HashMap<NodeRef, Node>,
+    /// The file the tree was parsed from.
+    source: Option<Rc<SourceFile>>,
 }
 
 impl NodeTree {
-    pub fn new(root: NodeRef) -> Self {
-        let current_ref = NodeRef(0);
-        let nodes = HashMap::new();
-        NodeTree {
-            root,
-            current_ref,
-            nodes,
-        }
+    /// Creates a tree whose root node holds `root_data`.
+    pub fn new(root_data: NodeData) -> Self {
+        let mut node_tree = NodeTree {
+            root: NodeRef(0),
+            current_ref: NodeRef(0),
+            nodes: HashMap::new(),
+            source: None,
+        };
+        node_tree.root = node_tree.new_node(root_data);
+        node_tree
+    }
+
+    pub fn root(&self) -> NodeRef {
+        self.root
+    }
+
+    /// The file the tree was parsed from, when the parser was given all of it at once.
+    ///
+    /// Nodes can also come from other files (such as components), see [`Node::source`].
+    pub fn source(&self) -> Option<&Rc<SourceFile>> {
+        self.source.as_ref()
+    }
+
+    pub fn set_source(&mut self, source: Rc<SourceFile>) {
+        self.source = Some(source);
     }
 
     // could return error if "node" or "child" don't exist
@@ -61,11 +80,13 @@ impl NodeTree {
 
         //TODO:  will need to match against error type or option and return some_error
         let parent = self.get_node_mut(parent_ref);
+        parent.children_modified = true;
 
-        let first_child_ref = match parent.first_child_ref {
-            Some(first_child_ref) => first_child_ref,
+        let last_child_ref = match parent.last_child_ref {
+            Some(last_child_ref) => last_child_ref,
             None => {
                 parent.first_child_ref = Some(new_child_ref);
+                parent.last_child_ref = Some(new_child_ref);
                 let first_child = self.get_node_mut(new_child_ref);
 
                 // only the first child of each node shall know who their parent is
@@ -75,23 +96,7 @@ impl NodeTree {
             }
         };
 
-        // last_child == Some(_) && first_child == None should never happen.    the opposite is OK
-
-        let last_child_ref = match parent.first_child_ref {
-            Some(last_child_ref) => last_child_ref,
-            None => {
-                //? can assume first_child is Some(_). maybe add an assertion?
-                parent.last_child_ref = Some(new_child_ref);
-
-                let first_child = self.get_node_mut(first_child_ref);
-                first_child.next_sibling_ref = Some(new_child_ref);
-
-                let new_child = self.get_node_mut(new_child_ref);
-                new_child.previous_sibling_ref = Some(first_child_ref);
-
-                return;
-            }
-        };
+        // last_child == Some(_) && first_child == None should never happen, and neither should the opposite
 
         parent.last_child_ref = Some(new_child_ref);
 
@@ -115,6 +120,11 @@ impl NodeTree {
         node_ref
     }
 
+    /// Whether `node_ref` is still in the tree, see [`NodeTree::remove_subtree`].
+    pub fn contains(&self, node_ref: NodeRef) -> bool {
+        self.nodes.contains_key(&node_ref)
+    }
+
     pub fn get_node(&self, node_ref: NodeRef) -> &Node {
         // should unwrap? every NodeRef in the wild will have been valid at some point.
         // if this struct exposes a way to remove nodes this has to handle the possible error.
@@ -128,61 +138,255 @@ impl NodeTree {
         // if a Node has been removed there can still be NodeRefs to it in the wild
         self.nodes.get_mut(&node_ref).unwrap()
     }
+
+    /// Finds the parent of a node.
+    ///
+    /// Only the first child of a node knows its parent, so this walks back through the
+    /// previous siblings first.
+    pub fn parent(&self, node_ref: NodeRef) -> Option<NodeRef> {
+        let mut first_child_ref = node_ref;
+        while let Some(previous_sibling_ref) = self.get_node(first_child_ref).previous_sibling_ref {
+            first_child_ref = previous_sibling_ref;
+        }
+        self.get_node(first_child_ref).parent_ref
+    }
+
     pub fn append_before_sibling(&mut self, sibling_ref: NodeRef, new_node_ref: NodeRef) {
-        todo!()
+        if let Some(parent_ref) = self.parent(sibling_ref) {
+            self.get_node_mut(parent_ref).children_modified = true;
+        }
+
+        let sibling = self.get_node_mut(sibling_ref);
+        let previous_sibling_ref = sibling.previous_sibling_ref.replace(new_node_ref);
+
+        match previous_sibling_ref {
+            Some(previous_sibling_ref) => {
+                let previous_sibling = self.get_node_mut(previous_sibling_ref);
+                previous_sibling.next_sibling_ref = Some(new_node_ref);
+            }
+            None => {
+                // "sibling" was the first child, so the new node inherits the parent
+                let parent_ref = sibling.parent_ref.take();
+                if let Some(parent_ref) = parent_ref {
+                    self.get_node_mut(parent_ref).first_child_ref = Some(new_node_ref);
+                }
+                self.get_node_mut(new_node_ref).parent_ref = parent_ref;
+            }
+        }
+
+        let new_node = self.get_node_mut(new_node_ref);
+        new_node.previous_sibling_ref = previous_sibling_ref;
+        new_node.next_sibling_ref = Some(sibling_ref);
     }
+
+    /// Detaches a node (and its subtree) from its parent and siblings.
+    ///
+    /// The node stays in the tree's storage and can be appended somewhere else.
     pub fn remove_from_parent(&mut self, node_ref: NodeRef) {
-        todo!()
-    }
-    fn reparent_children(&mut self, old_parent_ref: NodeRef, new_parent_ref: NodeRef) {
-        //TODO: needs revision
-        let old_parent = self.get_node(old_parent_ref);
-        let first_child_ref = old_parent.first_child_ref;
-        let last_child_ref = old_parent.last_child_ref;
+        let parent_ref = self.parent(node_ref);
+        if let Some(parent_ref) = parent_ref {
+            self.get_node_mut(parent_ref).children_modified = true;
+        }
 
-        let new_parent = self.get_node_mut(new_parent_ref);
-        new_parent.first_child_ref = first_child_ref;
-        new_parent.last_child_ref = last_child_ref;
+        let node = self.get_node_mut(node_ref);
+        let previous_sibling_ref = node.previous_sibling_ref.take();
+        let next_sibling_ref = node.next_sibling_ref.take();
+        node.parent_ref = None;
 
+        match previous_sibling_ref {
+            Some(previous_sibling_ref) => {
+                self.get_node_mut(previous_sibling_ref).next_sibling_ref = next_sibling_ref;
+            }
+            None => {
+                if let Some(parent_ref) = parent_ref {
+                    self.get_node_mut(parent_ref).first_child_ref = next_sibling_ref;
+                }
+                if let Some(next_sibling_ref) = next_sibling_ref {
+                    self.get_node_mut(next_sibling_ref).parent_ref = parent_ref;
+                }
+            }
+        }
+
+        match next_sibling_ref {
+            Some(next_sibling_ref) => {
+                self.get_node_mut(next_sibling_ref).previous_sibling_ref = previous_sibling_ref;
+            }
+            None => {
+                if let Some(parent_ref) = parent_ref {
+                    self.get_node_mut(parent_ref).last_child_ref = previous_sibling_ref;
+                }
+            }
+        }
+    }
+
+    /// Moves every child of `old_parent_ref` to the end of `new_parent_ref`'s children.
+    pub fn reparent_children(&mut self, old_parent_ref: NodeRef, new_parent_ref: NodeRef) {
         let old_parent = self.get_node_mut(old_parent_ref);
-        old_parent.first_child_ref = None;
-        old_parent.last_child_ref = None;
+        old_parent.children_modified = true;
+        let first_child_ref = old_parent.first_child_ref.take();
+        let last_child_ref = old_parent.last_child_ref.take();
+
+        let (first_child_ref, last_child_ref) = match (first_child_ref, last_child_ref) {
+            (None, None) => return,
+            (Some(first_child_ref), Some(last_child_ref)) => (first_child_ref, last_child_ref),
+            _ => unreachable!(), // should never happen,
+        };
 
-        match (first_child_ref, last_child_ref) {
-            (None, None) => {}
-            (Some(first_child_ref), _) => {
+        let new_parent = self.get_node_mut(new_parent_ref);
+        new_parent.children_modified = true;
+        let previous_last_child_ref = new_parent.last_child_ref.replace(last_child_ref);
+
+        match previous_last_child_ref {
+            Some(previous_last_child_ref) => {
+                self.get_node_mut(previous_last_child_ref).next_sibling_ref = Some(first_child_ref);
                 let first_child = self.get_node_mut(first_child_ref);
-                first_child.parent_ref = Some(new_parent_ref);
+                first_child.previous_sibling_ref = Some(previous_last_child_ref);
+                first_child.parent_ref = None;
+            }
+            None => {
+                new_parent.first_child_ref = Some(first_child_ref);
+                self.get_node_mut(first_child_ref).parent_ref = Some(new_parent_ref);
             }
-            _ => unreachable!(), // should never happen,
         }
     }
+
+    /// Inserts `new_node_ref` right after `sibling_ref`.
+    ///
+    /// This is linear in the number of previous siblings when `sibling_ref` is the last child,
+    /// since the parent has to be found to update its last child.
     pub fn append_after_sibling(&mut self, sibling_ref: NodeRef, new_node_ref: NodeRef) {
-        unimplemented!()
-        // this isn't necessary for my use case but it would make this struct more general purpose.
-        // If I ever want to publish it is a crate this would be a worthy addition. This method's implementation
-        // shouldn't change the time complexity of any other method and it should be constant itself.
-        // it will increase memory usage though, since the last child of any node will have to know its parent
-        // (currently, only the first child needs to)
-        // it could, maybe, be enabled as a cargo feature. maybe more methods could use this treatment
+        match self.get_node(sibling_ref).next_sibling_ref {
+            Some(next_sibling_ref) => self.append_before_sibling(next_sibling_ref, new_node_ref),
+            None => match self.parent(sibling_ref) {
+                Some(parent_ref) => self.append(parent_ref, new_node_ref),
+                None => {
+                    self.get_node_mut(sibling_ref).next_sibling_ref = Some(new_node_ref);
+                    self.get_node_mut(new_node_ref).previous_sibling_ref = Some(sibling_ref);
+                }
+            },
+        }
     }
 
-    /*
-    pub fn get_children(&self, node: &NodeRef) -> &Option<Vec<NodeRef>> {
-        let node = self.get_node(node);
-        &node.children
+    /// Makes a detached copy of a node and its descendants.
+    ///
+    /// The copies keep the source and spans of the originals, and count as modified only
+    /// where the originals do.
+    pub fn clone_subtree(&mut self, node_ref: NodeRef) -> NodeRef {
+        let original = self.get_node(node_ref);
+        let data = original.data.clone();
+        let source = original.source.clone();
+        let (span, start_tag_span) = (original.span, original.start_tag_span);
+        let (modified, children_modified) = (original.modified, original.children_modified);
+
+        let clone_ref = self.new_node(data);
+        let clone = self.get_node_mut(clone_ref);
+        clone.source = source;
+        clone.span = span;
+        clone.start_tag_span = start_tag_span;
+
+        if let Some(template_contents) = self
+            .get_node(clone_ref)
+            .as_element()
+            .and_then(|element| element.template_contents)
+        {
+            let template_contents = self.clone_subtree(template_contents);
+            if let NodeData::Element(element) = &mut self.get_node_mut(clone_ref).data {
+                element.template_contents = Some(template_contents);
+            }
+        }
+        let children: Vec<NodeRef> = self.children(node_ref).collect();
+        for child_ref in children {
+            let child_clone_ref = self.clone_subtree(child_ref);
+            self.append(clone_ref, child_clone_ref);
+        }
+
+        let clone = self.get_node_mut(clone_ref);
+        clone.modified = modified;
+        clone.children_modified = children_modified;
+        clone_ref
+    }
+
+    /// Makes a detached copy of a node and its descendants from another tree, such as a
+    /// parsed fragment, in this one.
+    ///
+    /// The copies keep the source and spans of the originals, and count as modified.
+    pub fn import_subtree(&mut self, other: &NodeTree, node_ref: NodeRef) -> NodeRef {
+        let original = other.get_node(node_ref);
+        let mut data = original.data.clone();
+        if let NodeData::Element(element) = &mut data {
+            element.template_contents = element
+                .template_contents
+                .map(|template_contents| self.import_subtree(other, template_contents));
+        }
+
+        let copy_ref = self.new_node(data);
+        let copy = self.get_node_mut(copy_ref);
+        copy.source = original.source.clone();
+        copy.span = original.span;
+        copy.start_tag_span = original.start_tag_span;
+        for child_ref in other.children(node_ref) {
+            let child_copy_ref = self.import_subtree(other, child_ref);
+            self.append(copy_ref, child_copy_ref);
+        }
+        copy_ref
+    }
+
+    /// Forgets every change made so far, so that only later changes count as modifications.
+    ///
+    /// Parsers call this when they finish, see [`Node::is_modified`].
+    pub fn mark_unmodified(&mut self) {
+        for node in self.nodes.values_mut() {
+            node.modified = false;
+            node.children_modified = false;
+        }
+    }
+
+    /// Detaches a node and frees it and all of its descendants.
+    ///
+    /// Any `NodeRef` to one of the removed nodes becomes dangling.
+    pub fn remove_subtree(&mut self, node_ref: NodeRef) {
+        self.remove_from_parent(node_ref);
+        let mut stack = vec![node_ref];
+        while let Some(node_ref) = stack.pop() {
+            let node = self.nodes.remove(&node_ref).unwrap();
+            if let NodeData::Element(ElementData {
+                template_contents: Some(template_contents),
+                ..
+            }) = node.data
+            {
+                stack.push(template_contents);
+            }
+            let mut child_ref = node.first_child_ref;
+            while let Some(current_ref) = child_ref {
+                child_ref = self.get_node(current_ref).next_sibling_ref;
+                stack.push(current_ref);
+            }
+        }
     }
 
-    pub fn set_children(&mut self, parent: &NodeRef, children: Vec<NodeRef>) {
-        let parent = self.get_node_mut(parent);
-        parent.children = Some(children);
+    /// Iterates over the children of a node, in order.
+    pub fn children(&self, node_ref: NodeRef) -> Children<'_> {
+        Children {
+            node_tree: self,
+            next: self.get_node(node_ref).first_child_ref,
+        }
     }
+}
+
+/// Iterator over the children of a node. See [`NodeTree::children`].
+pub struct Children<'a> {
+    node_tree: &'a NodeTree,
+    next: Option<NodeRef>,
+}
 
-    pub fn clear_children(&mut self, node: &NodeRef) {
-        let node = self.get_node_mut(node);
-        node.children = None;
+impl<'a> Iterator for Children<'a> {
+    type Item = NodeRef;
+
+    fn next(&mut self) -> Option<NodeRef> {
+        let current = self.next?;
+        self.next = self.node_tree.get_node(current).next_sibling_ref;
+        Some(current)
     }
-    */
 }
 
 #[derive(Debug, PartialEq, Clone, Copy, Eq, Hash, PartialOrd, Ord)]
@@ -190,7 +394,6 @@ pub struct NodeRef(u64);
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Node {
-    //TODO: figure out parent.
     //TODO: maybe Node should have no exposed methods and everything should be routed through NodeFactory (NodeTree renaming?)
     // only the "first_child" of a given node will know its parent
     // if parent = None this node could still have a parent, it just means it is not the "first_child"
@@ -203,6 +406,11 @@ pub struct Node {
     data: NodeData,
     first_child_ref: Option<NodeRef>,
     last_child_ref: Option<NodeRef>,
+    source: Option<Rc<SourceFile>>,
+    span: Option<Span>,
+    start_tag_span: Option<Span>,
+    modified: bool,
+    children_modified: bool,
 }
 
 impl Node {
@@ -219,6 +427,193 @@ impl Node {
             data,
             first_child_ref,
             last_child_ref,
+            source: None,
+            span: None,
+            start_tag_span: None,
+            modified: true,
+            children_modified: true,
+        }
+    }
+
+    /// Whether the node's data was changed (or the node was created) since the tree was
+    /// parsed. Changes through [`Node::data_mut`] and the `as_*_mut` accessors count.
+    #[inline]
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    /// Whether children were added, removed or moved since the tree was parsed.
+    #[inline]
+    pub fn children_modified(&self) -> bool {
+        self.children_modified
+    }
+
+    /// The file this node's spans point into.
+    #[inline]
+    pub fn source(&self) -> Option<&Rc<SourceFile>> {
+        self.source.as_ref()
+    }
+
+    #[inline]
+    pub fn set_source(&mut self, source: Option<Rc<SourceFile>>) {
+        self.source = source;
+    }
+
+    /// Where the whole node (for elements, up to and including the end tag) is in the source.
+    #[inline]
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    #[inline]
+    pub fn set_span(&mut self, span: Option<Span>) {
+        self.span = span;
+    }
+
+    /// Where an element's start tag is in the source.
+    #[inline]
+    pub fn start_tag_span(&self) -> Option<Span> {
+        self.start_tag_span
+    }
+
+    #[inline]
+    pub fn set_start_tag_span(&mut self, span: Option<Span>) {
+        self.start_tag_span = span;
+    }
+
+    /// Where an attribute of an element is in the source (the first one, if it was written
+    /// more than once, which is the one the parser keeps).
+    pub fn attribute_span(&self, local_name: &str) -> Option<AttributeSpan> {
+        let source = self.source.as_ref()?;
+        let start_tag_span = self.start_tag_span?;
+        span::attribute_spans(&source.text[start_tag_span.range()], start_tag_span.start)
+            .into_iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(local_name))
+            .map(|(_, attribute_span)| attribute_span)
+    }
+
+    /// Where an element's end tag is in the source, if it was written there.
+    pub fn end_tag_span(&self) -> Option<Span> {
+        let source = self.source.as_ref()?;
+        let span = self.span?;
+        let start_tag_span = self.start_tag_span?;
+        let tag_name = span::tag_name(&self.as_element()?.name);
+        source.text[start_tag_span.end..span.end]
+            .rfind("</")
+            .map(|i| start_tag_span.end + i)
+            .filter(|&start| span::end_tag_end(&source.text, start, &tag_name) == Some(span.end))
+            .map(|start| Span::new(start, span.end))
+    }
+
+    #[inline]
+    pub fn data(&self) -> &NodeData {
+        &self.data
+    }
+
+    #[inline]
+    pub fn data_mut(&mut self) -> &mut NodeData {
+        self.modified = true;
+        &mut self.data
+    }
+
+    #[inline]
+    pub fn first_child(&self) -> Option<NodeRef> {
+        self.first_child_ref
+    }
+
+    #[inline]
+    pub fn last_child(&self) -> Option<NodeRef> {
+        self.last_child_ref
+    }
+
+    #[inline]
+    pub fn previous_sibling(&self) -> Option<NodeRef> {
+        self.previous_sibling_ref
+    }
+
+    #[inline]
+    pub fn next_sibling(&self) -> Option<NodeRef> {
+        self.next_sibling_ref
+    }
+
+    /// If this node is an element, return a reference to element-specific data.
+    #[inline]
+    pub fn as_element(&self) -> Option<&ElementData> {
+        match self.data {
+            NodeData::Element(ref value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// If this node is an element, return a mutable reference to element-specific data.
+    #[inline]
+    pub fn as_element_mut(&mut self) -> Option<&mut ElementData> {
+        match self.data {
+            NodeData::Element(ref mut value) => {
+                self.modified = true;
+                Some(value)
+            }
+            _ => None,
+        }
+    }
+
+    /// If this node is a text node, return a reference to its contents.
+    #[inline]
+    pub fn as_text(&self) -> Option<&String> {
+        match self.data {
+            NodeData::Text(ref value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// If this node is a text node, return a mutable reference to its contents.
+    #[inline]
+    pub fn as_text_mut(&mut self) -> Option<&mut String> {
+        match self.data {
+            NodeData::Text(ref mut value) => {
+                self.modified = true;
+                Some(value)
+            }
+            _ => None,
+        }
+    }
+
+    /// If this node is a comment, return a reference to its contents.
+    #[inline]
+    pub fn as_comment(&self) -> Option<&String> {
+        match self.data {
+            NodeData::Comment(ref value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// If this node is a doctype, return a reference to doctype-specific data.
+    #[inline]
+    pub fn as_doctype(&self) -> Option<&Doctype> {
+        match self.data {
+            NodeData::Doctype(ref value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// If this node is a document, return a reference to document-specific data.
+    #[inline]
+    pub fn as_document(&self) -> Option<&DocumentData> {
+        match self.data {
+            NodeData::Document(ref value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// If this node is a document, return a mutable reference to document-specific data.
+    #[inline]
+    pub fn as_document_mut(&mut self) -> Option<&mut DocumentData> {
+        match self.data {
+            NodeData::Document(ref mut value) => {
+                self.modified = true;
+                Some(value)
+            }
+            _ => None,
         }
     }
 }
@@ -229,10 +624,11 @@ pub struct ElementData {
     pub name: QualName,
 
     /// The attributes of the elements.
-    pub attrs: Vec<Attribute>,
-    // If the element is an HTML `<template>` element,
-    // the document fragment node that is the root of template contents.
-    //pub template_contents: Option<Box<Node>>,
+    pub attributes: Attributes,
+
+    /// If the element is an HTML `<template>` element,
+    /// the document fragment node that is the root of template contents.
+    pub template_contents: Option<NodeRef>,
 }
 
 /// Data specific to document nodes.
@@ -240,8 +636,13 @@ pub struct ElementData {
 pub struct DocumentData {
     #[doc(hidden)]
     pub _quirks_mode: QuirksMode,
+
+    /// The character encoding the document was decoded from.
+    ///
+    /// Documents parsed from a `&str` are always `UTF_8`.
+    pub encoding: &'static Encoding,
 }
-/*
+
 impl DocumentData {
     /// The quirks mode of the document, as determined by the HTML parser.
     #[inline]
@@ -249,7 +650,6 @@ impl DocumentData {
         self._quirks_mode
     }
 }
-*/
 
 /// Data specific to doctype nodes.
 #[derive(Debug, PartialEq, Clone)]
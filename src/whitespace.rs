@@ -0,0 +1,213 @@
+use html5ever::{namespace_url, ns};
+
+use crate::serializer::{collapse, html_local_name, preserves_whitespace};
+use crate::span::Span;
+use crate::tree::*;
+
+/// What to do with whitespace in text next to `kolo-*` directive elements.
+///
+/// Whatever the policy, `-` trim markers in a directive's tags remove all whitespace on their
+/// side of the tag, like `{%-` and `-%}` in Jinja:
+///
+/// - a `-` written right before the `>` trims the text after the tag, as in
+///   `<kolo-list for="x" in="xs" ->` or `</kolo-list ->`;
+/// - any other `-`, usually right after the tag name, trims the text before the tag, as in
+///   `<kolo-list - for="x" in="xs">` or `</kolo-list - >`.
+///
+/// Interpolations take the same markers inside their braces, as in `{{- x -}}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WhitespacePolicy {
+    /// Leave the text as written.
+    Preserve,
+
+    /// Remove the indentation before a directive tag and the line break after it, when the
+    /// tag is alone on its line, like Jinja's `trim_blocks` and `lstrip_blocks`. Every
+    /// repetition of a `<kolo-list>` body then starts on a line of its own.
+    #[default]
+    Trim,
+
+    /// Collapse runs of whitespace in the text next to a directive tag to a single space.
+    Collapse,
+}
+
+/// Which side of a tag a piece of text is on.
+#[derive(Clone, Copy, PartialEq)]
+enum Side {
+    Before,
+    After,
+}
+
+/// The trim markers written in a start or end tag.
+#[derive(Default, Clone, Copy)]
+struct TrimMarkers {
+    before: bool,
+    after: bool,
+}
+
+impl TrimMarkers {
+    /// Finds the markers in the source of a tag, such as `<kolo-if - condition="x" ->`.
+    fn parse(tag: &str) -> Self {
+        let inside = tag.trim_end_matches('>').trim_end_matches('/');
+        let mut markers = TrimMarkers::default();
+        let mut tokens = tokens(inside).skip(1).peekable();
+        while let Some(token) = tokens.next() {
+            if token == "-" {
+                if tokens.peek().is_none() && inside.ends_with('-') {
+                    markers.after = true;
+                } else {
+                    markers.before = true;
+                }
+            }
+        }
+        markers
+    }
+}
+
+/// Splits the inside of a tag on whitespace that is not in a quoted attribute value.
+fn tokens(tag: &str) -> impl Iterator<Item = &str> {
+    let mut quote = None;
+    tag.split(move |c: char| match quote {
+        Some(q) if c == q => {
+            quote = None;
+            false
+        }
+        Some(_) => false,
+        None if c == '"' || c == '\'' => {
+            quote = Some(c);
+            false
+        }
+        None => c.is_ascii_whitespace(),
+    })
+    .filter(|token| !token.is_empty())
+}
+
+fn is_directive(node: &Node) -> bool {
+    node.as_element()
+        .is_some_and(|element| element.name.ns == ns!(html))
+        && html_local_name(node).is_some_and(|local| local.starts_with("kolo-"))
+}
+
+impl NodeTree {
+    /// Apply `policy` (and the trim markers) to the text around and inside every `kolo-*`
+    /// element under `node_ref`, removing text that ends up empty and the `-` marker
+    /// attributes. Text in `<pre>`, `<textarea>` and the like is only touched by markers.
+    pub fn apply_whitespace_policy(&mut self, node_ref: NodeRef, policy: WhitespacePolicy) {
+        let mut directives = Vec::new();
+        let mut stack = vec![node_ref];
+        while let Some(node_ref) = stack.pop() {
+            let node = self.get_node(node_ref);
+            if is_directive(node) {
+                directives.push(node_ref);
+            }
+            if let Some(template_contents) = node.as_element().and_then(|e| e.template_contents) {
+                stack.push(template_contents);
+            }
+            stack.extend(self.children(node_ref));
+        }
+
+        for directive_ref in directives {
+            let (start_tag, end_tag) = self.trim_markers(directive_ref);
+            let boundaries = [
+                (Side::Before, start_tag.before),
+                (Side::After, start_tag.after),
+                (Side::Before, end_tag.before),
+                (Side::After, end_tag.after),
+            ];
+            for (i, (side, marker)) in boundaries.iter().copied().enumerate() {
+                // looked up one at a time, since trimming can remove a text node
+                let node = self.get_node(directive_ref);
+                let text_ref = match i {
+                    0 => node.previous_sibling(),
+                    1 => node.first_child(),
+                    2 => node.last_child(),
+                    _ => node.next_sibling(),
+                };
+                if let Some(text_ref) = text_ref {
+                    self.trim_text(text_ref, side, marker, policy);
+                }
+            }
+        }
+    }
+
+    /// The markers in a directive's start and end tags, taking the `-` attributes out.
+    fn trim_markers(&mut self, directive_ref: NodeRef) -> (TrimMarkers, TrimMarkers) {
+        let node = self.get_node(directive_ref);
+        let has_marker = node.as_element().unwrap().attributes.contains("-");
+        let tag_source = |span: Option<Span>| {
+            node.source()
+                .zip(span)
+                .map(|(source, span)| TrimMarkers::parse(&source.text[span.range()]))
+        };
+        let start_tag = tag_source(node.start_tag_span()).unwrap_or(TrimMarkers {
+            // without the source there is no telling where the marker was written
+            before: has_marker,
+            after: has_marker,
+        });
+        let end_tag = tag_source(node.end_tag_span()).unwrap_or_default();
+        if has_marker {
+            let element = self.get_node_mut(directive_ref).as_element_mut().unwrap();
+            element.attributes.remove("-");
+        }
+        (start_tag, end_tag)
+    }
+
+    /// Trims the end of a text node that is before a tag, or the start of one after a tag.
+    fn trim_text(&mut self, text_ref: NodeRef, side: Side, marker: bool, policy: WhitespacePolicy) {
+        let text = match self.get_node(text_ref).as_text() {
+            Some(text) => text,
+            None => return,
+        };
+        let preserved = {
+            let mut ancestor_ref = self.parent(text_ref);
+            let mut preserved = false;
+            while let Some(node_ref) = ancestor_ref {
+                let node = self.get_node(node_ref);
+                preserved |= html_local_name(node).is_some_and(preserves_whitespace);
+                ancestor_ref = self.parent(node_ref);
+            }
+            preserved
+        };
+
+        let trimmed = match (marker, side) {
+            (true, Side::Before) => text.trim_end_matches(|c: char| c.is_ascii_whitespace()),
+            (true, Side::After) => text.trim_start_matches(|c: char| c.is_ascii_whitespace()),
+            (false, _) if preserved => return,
+            (false, side) => match policy {
+                WhitespacePolicy::Preserve => return,
+                WhitespacePolicy::Collapse => {
+                    let collapsed = collapse(text);
+                    if collapsed == *text {
+                        return;
+                    }
+                    *self.get_node_mut(text_ref).as_text_mut().unwrap() = collapsed;
+                    return;
+                }
+                WhitespacePolicy::Trim => match side {
+                    // the indentation of the tag's line
+                    Side::Before => match text.rfind('\n') {
+                        Some(newline) if is_indentation(&text[newline + 1..]) => {
+                            &text[..newline + 1]
+                        }
+                        _ => return,
+                    },
+                    // the rest of the tag's line, up to and including the line break
+                    Side::After => match text.find('\n') {
+                        Some(newline) if is_indentation(&text[..newline]) => &text[newline + 1..],
+                        _ => return,
+                    },
+                },
+            },
+        };
+
+        if trimmed.is_empty() {
+            self.remove_subtree(text_ref);
+        } else if trimmed.len() != text.len() {
+            let trimmed = String::from(trimmed);
+            *self.get_node_mut(text_ref).as_text_mut().unwrap() = trimmed;
+        }
+    }
+}
+
+fn is_indentation(text: &str) -> bool {
+    text.chars().all(|c| c == ' ' || c == '\t' || c == '\r')
+}
@@ -0,0 +1,82 @@
+use kolo::ast::SCHEMA_VERSION;
+use kolo::sink::{parse_html_bytes, parse_xml_bytes};
+use kolo::tree::{NodeData, NodeTree};
+use std::fs;
+
+#[test]
+fn test_ast() {
+    let source = fs::read("test_data/test_ast/source.html").unwrap();
+    let expected = fs::read_to_string("test_data/test_ast/result.json").unwrap();
+
+    let node_tree = parse_html_bytes(&source);
+    assert_eq!(SCHEMA_VERSION, 1);
+    assert_eq!(node_tree.to_json_ast() + "\n", expected);
+}
+
+#[test]
+fn test_ast_fragment() {
+    let mut node_tree = NodeTree::new(NodeData::DocumentFragment);
+    let text_ref = node_tree.new_node(NodeData::Text(String::from("a\"\n")));
+    let root = node_tree.root();
+    node_tree.append(root, text_ref);
+    assert_eq!(
+        node_tree.to_json_ast(),
+        r#"{"schema_version":1,"root":{"kind":"document_fragment","span":null,"children":[{"kind":"text","span":null,"text":"a\"\n"}]}}"#
+    );
+}
+
+#[test]
+fn test_ast_xml() {
+    // what `kolo ast` does for `.svg`, `.xml` and `.xhtml` files
+    let source = br##"<?xml version="1.0"?>
+<?xml-stylesheet href="a.css"?>
+<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink"
+     width="1" height="2"><use xlink:href="#a"/></svg>"##;
+    let json = parse_xml_bytes(source).to_json_ast();
+
+    assert!(json.contains(
+        r#"{"kind":"processing_instruction","span":{"start":22,"end":53,"start_line":2,"start_column":1,"end_line":2,"end_column":32},"target":"xml-stylesheet","data":"href=\"a.css\""}"#
+    ), "{}", json);
+    // in source order, not the tree's
+    assert!(json.contains(
+        r#""name":"svg","namespace":"http://www.w3.org/2000/svg","prefix":null,"attributes":[{"name":"width","namespace":"","prefix":null,"value":"1"},{"name":"height","namespace":"","prefix":null,"value":"2"}]"#
+    ), "{}", json);
+    assert!(json.contains(
+        r##""name":"use","namespace":"http://www.w3.org/2000/svg","prefix":null,"attributes":[{"name":"href","namespace":"http://www.w3.org/1999/xlink","prefix":"xlink","value":"#a"}]"##
+    ), "{}", json);
+}
+
+#[test]
+fn test_text_spans() {
+    // a character reference at the end of text is only flushed with the next tag
+    let source = "<p>Caf&eacute;</p><p>&#233;<b>x&amp</b>&lt;</p>\
+                  <script>&amp;< b</script><textarea>a &amp;</textarea>";
+    let node_tree = parse_html_bytes(source.as_bytes());
+    let mut texts = Vec::new();
+    let mut stack = vec![node_tree.root()];
+    while let Some(node_ref) = stack.pop() {
+        let node = node_tree.get_node(node_ref);
+        if let Some(text) = node.as_text() {
+            let span = node.span().unwrap();
+            texts.push((text.clone(), span.start, &source[span.range()]));
+        }
+        stack.extend(
+            node_tree
+                .children(node_ref)
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev(),
+        );
+    }
+    assert_eq!(
+        texts,
+        [
+            (String::from("Caf\u{e9}"), 3, "Caf&eacute;"),
+            (String::from("\u{e9}"), 21, "&#233;"),
+            (String::from("x&"), 30, "x&amp"),
+            (String::from("<"), 39, "&lt;"),
+            (String::from("&amp;< b"), 55, "&amp;< b"),
+            (String::from("a &"), 82, "a &amp;"),
+        ]
+    );
+}
@@ -0,0 +1,136 @@
+use encoding_rs::{
+    Encoding, ISO_8859_2, KOI8_R, SHIFT_JIS, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252,
+};
+use kolo::encoding::{decode, sniff, Confidence};
+use kolo::sink::parse_html_bytes;
+
+fn sniffed(bytes: &[u8]) -> (&'static Encoding, Confidence) {
+    sniff(bytes, WINDOWS_1252)
+}
+
+#[test]
+fn test_bom() {
+    let utf8 = b"\xEF\xBB\xBF<meta charset=koi8-r><p>caf\xC3\xA9";
+    assert_eq!(sniffed(utf8), (UTF_8, Confidence::Certain));
+    assert_eq!(
+        decode(utf8, WINDOWS_1252).0,
+        "<meta charset=koi8-r><p>caf\u{e9}"
+    );
+
+    let mut utf16le = vec![0xFF, 0xFE];
+    let mut utf16be = vec![0xFE, 0xFF];
+    for unit in "<p>caf\u{e9}".encode_utf16() {
+        utf16le.extend_from_slice(&unit.to_le_bytes());
+        utf16be.extend_from_slice(&unit.to_be_bytes());
+    }
+    assert_eq!(sniffed(&utf16le), (UTF_16LE, Confidence::Certain));
+    assert_eq!(sniffed(&utf16be), (UTF_16BE, Confidence::Certain));
+    assert_eq!(
+        decode(&utf16le, WINDOWS_1252),
+        ("<p>caf\u{e9}".into(), UTF_16LE)
+    );
+    assert_eq!(
+        decode(&utf16be, WINDOWS_1252),
+        ("<p>caf\u{e9}".into(), UTF_16BE)
+    );
+}
+
+#[test]
+fn test_meta_charset() {
+    let bytes = b"<!doctype html><meta charset=\"ISO-8859-2\"><p>\xB1";
+    assert_eq!(sniffed(bytes), (ISO_8859_2, Confidence::Tentative));
+    assert!(decode(bytes, WINDOWS_1252).0.ends_with("<p>\u{105}"));
+
+    // declarations in comments and in other attributes do not count
+    let bytes = b"<!-- <meta charset=koi8-r> --><p title='<meta charset=koi8-r>'>\
+                  <META CHARSET=iso-8859-2>";
+    assert_eq!(sniffed(bytes), (ISO_8859_2, Confidence::Tentative));
+
+    // only the first 1024 bytes are looked at
+    let mut bytes = vec![b' '; 1024];
+    bytes.extend_from_slice(b"<meta charset=koi8-r>");
+    assert_eq!(sniffed(&bytes), (UTF_8, Confidence::Tentative));
+}
+
+#[test]
+fn test_meta_http_equiv() {
+    let bytes =
+        b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset='koi8-r'\"><p>\xC1";
+    assert_eq!(sniffed(bytes), (KOI8_R, Confidence::Tentative));
+    assert!(decode(bytes, WINDOWS_1252).0.ends_with("<p>\u{430}"));
+
+    let bytes = b"<meta content=\"text/html; charset=koi8-r\" http-equiv=content-type>";
+    assert_eq!(sniffed(bytes).0, KOI8_R);
+
+    // a `content` without the `http-equiv` is not a declaration
+    let bytes = b"<meta content=\"text/html; charset=koi8-r\">";
+    assert_eq!(sniffed(bytes).0, UTF_8);
+}
+
+#[test]
+fn test_meta_utf16() {
+    // a document that could be read far enough to find the declaration is not UTF-16
+    for label in ["utf-16", "UTF-16LE", "utf-16be"] {
+        let bytes = format!("<meta charset=\"{}\"><p>\u{e9}", label);
+        assert_eq!(sniffed(bytes.as_bytes()), (UTF_8, Confidence::Tentative));
+    }
+    let bytes = b"<meta charset=x-user-defined><p>\x80";
+    assert_eq!(sniffed(bytes).0, WINDOWS_1252);
+}
+
+#[test]
+fn test_fallback() {
+    let bytes = b"<p>caf\xE9</p>";
+    assert_eq!(sniffed(bytes), (WINDOWS_1252, Confidence::Tentative));
+    assert_eq!(decode(bytes, WINDOWS_1252).0, "<p>caf\u{e9}</p>");
+    assert_eq!(sniff(bytes, KOI8_R).0, KOI8_R);
+
+    // valid UTF-8 needs no declaration
+    assert_eq!(
+        sniffed("<p>caf\u{e9}".as_bytes()),
+        (UTF_8, Confidence::Tentative)
+    );
+}
+
+#[test]
+fn test_shift_jis() {
+    let mut bytes = b"<html><head><meta charset=\"Shift_JIS\"></head><body><p>".to_vec();
+    let (japanese, _, _) = SHIFT_JIS.encode("\u{65e5}\u{672c}\u{8a9e}");
+    bytes.extend_from_slice(&japanese);
+    assert_eq!(sniffed(&bytes), (SHIFT_JIS, Confidence::Tentative));
+
+    let node_tree = parse_html_bytes(&bytes);
+    let html = node_tree.to_html_string();
+    assert!(html.contains("<p>\u{65e5}\u{672c}\u{8a9e}</p>"), "{}", html);
+    assert_eq!(node_tree.to_html_bytes(SHIFT_JIS), {
+        let mut expected = b"<html><head><meta charset=\"Shift_JIS\"></head><body><p>".to_vec();
+        expected.extend_from_slice(&japanese);
+        expected.extend_from_slice(b"</p></body></html>");
+        expected
+    });
+}
+
+#[test]
+fn test_to_html_bytes() {
+    let source = "<meta charset=\"utf-8\"><meta http-equiv=\"Content-Type\" \
+                  content=\"text/html; charset=utf-8\"><p title=\"\u{e9}\u{65e5}\">\u{e9}\u{65e5}";
+    let node_tree = parse_html_bytes(source.as_bytes());
+
+    let bytes = node_tree.to_html_bytes(WINDOWS_1252);
+    let expected = b"<html><head><meta charset=\"windows-1252\"><meta \
+                     content=\"text/html; charset=windows-1252\" http-equiv=\"Content-Type\">\
+                     </head><body>\
+                     <p title=\"\xE9&#26085;\">\xE9&#26085;</p></body></html>";
+    assert_eq!(
+        String::from_utf8_lossy(&bytes),
+        String::from_utf8_lossy(expected)
+    );
+    assert_eq!(bytes, expected.to_vec());
+
+    // UTF-16 output is written as UTF-8, and declared so
+    let bytes = node_tree.to_html_bytes(UTF_16LE);
+    assert_eq!(bytes, node_tree.to_html_bytes(UTF_8));
+    assert!(String::from_utf8(bytes)
+        .unwrap()
+        .starts_with("<html><head><meta charset=\"UTF-8\">"));
+}
@@ -0,0 +1,99 @@
+use kolo::render::Renderer;
+use kolo::sink::parse_html_bytes;
+use kolo::span::Span;
+use std::fs;
+
+#[test]
+fn test_lossless() {
+    let source = fs::read_to_string("test_data/test_lossless/source.html").unwrap();
+    let expected = fs::read_to_string("test_data/test_lossless/result.html").unwrap();
+
+    // untouched documents come back byte for byte
+    for case in ["test_lossless", "test_for", "test_minify", "test_pretty"] {
+        let source = fs::read_to_string(format!("test_data/{}/source.html", case)).unwrap();
+        let node_tree = parse_html_bytes(source.as_bytes());
+        assert_eq!(node_tree.to_lossless_html_string(), source, "{}", case);
+    }
+
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    let mut stack = vec![node_tree.root()];
+    let mut status = None;
+    let mut item = None;
+    let mut removed = Vec::new();
+    while let Some(node_ref) = stack.pop() {
+        if let Some(element) = node_tree.get_node(node_ref).as_element() {
+            if element.attributes.get("id") == Some("status") {
+                status = Some(node_ref);
+            }
+            if element.attributes.get("class") == Some("item") {
+                item = Some(node_ref);
+            }
+            if element.attributes.contains("data-remove") {
+                removed.push(node_ref);
+            }
+        }
+        stack.extend(node_tree.children(node_ref));
+    }
+    let status = status.unwrap();
+    let text_ref = node_tree.get_node(status).first_child().unwrap();
+    *node_tree.get_node_mut(text_ref).as_text_mut().unwrap() = String::from("published");
+    let item = node_tree.get_node_mut(item.unwrap());
+    let attributes = &mut item.as_element_mut().unwrap().attributes;
+    attributes.insert("class", String::from("item active"));
+    for node_ref in removed {
+        node_tree.remove_subtree(node_ref);
+    }
+
+    assert_eq!(node_tree.to_lossless_html_string(), expected);
+}
+
+#[test]
+fn test_render_lossless() {
+    // rendering leaves what it does not change as it was written
+    let source = "<p title=unquoted data-b='2' data-a=1>Caf&eacute;</p>\n\
+                  <ul class=list><kolo-list for=\"i\" in=\"[1, 2]\"><li>{{ i }} &lt;</li></kolo-list></ul>";
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    Renderer::new().render(&mut node_tree).unwrap();
+    assert_eq!(
+        node_tree.to_lossless_html_string(),
+        "<p title=unquoted data-b='2' data-a=1>Caf&eacute;</p>\n\
+         <ul class=list><li>1 &lt;</li><li>2 &lt;</li></ul>"
+    );
+}
+
+#[test]
+fn test_lossless_span_past_node() {
+    let source = "<p>a</p><p>b</p>";
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    let mut stack = vec![node_tree.root()];
+    let mut paragraph = None;
+    while let Some(node_ref) = stack.pop() {
+        if node_tree
+            .get_node(node_ref)
+            .as_element()
+            .map(|e| &*e.name.local)
+            == Some("p")
+        {
+            paragraph = Some(node_ref);
+        }
+        stack.extend(node_tree.children(node_ref));
+    }
+    let paragraph = paragraph.unwrap();
+    let text_ref = node_tree.get_node(paragraph).first_child().unwrap();
+    // a text span wrongly taking in the end tag of its parent
+    node_tree
+        .get_node_mut(text_ref)
+        .set_span(Some(Span::new(3, 8)));
+    node_tree.mark_unmodified();
+    let attributes = &mut node_tree
+        .get_node_mut(paragraph)
+        .as_element_mut()
+        .unwrap()
+        .attributes;
+    attributes.insert("class", String::from("x"));
+
+    assert_eq!(
+        node_tree.to_lossless_html_string(),
+        "<p class=\"x\">a</p><p>b</p>"
+    );
+}
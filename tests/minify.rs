@@ -0,0 +1,87 @@
+use kolo::minify::MinifyOpts;
+use kolo::sink::parse_html_bytes;
+use kolo::tree::{NodeData, NodeRef, NodeTree};
+use std::fs;
+
+/// A textual dump of a tree, with boolean attribute values normalized away.
+fn dump(node_tree: &NodeTree, node_ref: NodeRef, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    match node_tree.get_node(node_ref).data() {
+        NodeData::Element(element) => {
+            out.push_str(&format!("{}<{}", indent, element.name.local));
+            for (name, attribute) in &element.attributes.map {
+                let value = if attribute.value.eq_ignore_ascii_case(&name.local) {
+                    ""
+                } else {
+                    &attribute.value
+                };
+                out.push_str(&format!(" {}={:?}", name.local, value));
+            }
+            out.push_str(">\n");
+        }
+        data => out.push_str(&format!("{}{:?}\n", indent, data)),
+    }
+    for child_ref in node_tree.children(node_ref) {
+        dump(node_tree, child_ref, depth + 1, out);
+    }
+}
+
+fn dump_tree(node_tree: &NodeTree) -> String {
+    let mut out = String::new();
+    dump(node_tree, node_tree.root(), 0, &mut out);
+    out
+}
+
+#[test]
+fn test_minify() {
+    let source = fs::read("test_data/test_minify/source.html").unwrap();
+    let expected = fs::read_to_string("test_data/test_minify/result.html").unwrap();
+
+    let mut node_tree = parse_html_bytes(&source);
+    let opts = MinifyOpts::default();
+    node_tree.minify(node_tree.root(), &opts);
+    let minified = node_tree.to_minified_html_string(&opts);
+    assert_eq!(minified, expected.trim_end_matches('\n'));
+
+    let reparsed = parse_html_bytes(minified.as_bytes());
+    assert_eq!(dump_tree(&reparsed), dump_tree(&node_tree));
+}
+
+#[test]
+fn test_minify_body_start_tag() {
+    let opts = MinifyOpts::default();
+    let minified = |source: &str| {
+        let mut node_tree = parse_html_bytes(source.as_bytes());
+        node_tree.minify(node_tree.root(), &opts);
+        node_tree.to_minified_html_string(&opts)
+    };
+    // a `<noscript>` first in the body would be parsed into the head without the tag
+    assert_eq!(
+        minified("<title>t</title><body><noscript>n</noscript><p>a"),
+        "<title>t</title><body><noscript>n</noscript><p>a"
+    );
+    assert_eq!(
+        minified("<title>t</title><body><p>a"),
+        "<title>t</title><p>a"
+    );
+}
+
+#[test]
+fn test_minify_frees_removed_nodes() {
+    let mut node_tree = parse_html_bytes(b"<p>a<!-- b -->c</p>");
+    let mut comments = Vec::new();
+    let mut stack = vec![node_tree.root()];
+    while let Some(node_ref) = stack.pop() {
+        if let NodeData::Comment(_) = node_tree.get_node(node_ref).data() {
+            comments.push(node_ref);
+        }
+        stack.extend(node_tree.children(node_ref));
+    }
+    assert_eq!(comments.len(), 1);
+    node_tree.minify(node_tree.root(), &MinifyOpts::default());
+    assert!(!node_tree.contains(comments[0]));
+    assert_eq!(
+        node_tree.to_html_string(),
+        "<html><head></head><body><p>ac</p></body></html>"
+    );
+}
@@ -0,0 +1,34 @@
+use kolo::pretty::PrettyOpts;
+use kolo::sink::parse_html_bytes;
+use std::fs;
+
+#[test]
+fn test_pretty() {
+    let source = fs::read("test_data/test_pretty/source.html").unwrap();
+    let expected = fs::read_to_string("test_data/test_pretty/result.html").unwrap();
+
+    let opts = PrettyOpts {
+        max_line_length: 80,
+        ..Default::default()
+    };
+    let pretty = parse_html_bytes(&source).to_pretty_html_string(&opts);
+    assert_eq!(pretty, expected);
+
+    // formatting the output again is a no-op
+    let reformatted = parse_html_bytes(pretty.as_bytes()).to_pretty_html_string(&opts);
+    assert_eq!(reformatted, pretty);
+}
+
+#[test]
+fn test_pretty_custom_elements() {
+    let source = "<div><p>New: <my-badge>hot</my-badge><x-foo>!</x-foo> today</p>\
+                  <my-card><p>a</p></my-card></div>";
+    let pretty = parse_html_bytes(source.as_bytes()).to_pretty_html_string(&PrettyOpts::default());
+    // custom elements without blocks inside flow with the text, so no whitespace is added
+    assert_eq!(
+        pretty,
+        "<html>\n  <head></head>\n  <body>\n    <div>\n      \
+         <p>New: <my-badge>hot</my-badge><x-foo>!</x-foo> today</p>\n      \
+         <my-card>\n        <p>a</p>\n      </my-card>\n    </div>\n  </body>\n</html>\n"
+    );
+}
@@ -0,0 +1,789 @@
+use html5ever::local_name;
+use kolo::render::{RenderOpts, Renderer};
+use kolo::sink::{parse_html_bytes, parse_html_bytes_with_options, ParseOpts};
+use kolo::tree::{NodeRef, NodeTree};
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+
+#[test]
+fn test_for() {
+    let source = fs::read_to_string("test_data/test_for/source.html").unwrap();
+    let expected = fs::read_to_string("test_data/test_for/result.html").unwrap();
+
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    Renderer::new().render(&mut node_tree).unwrap();
+    assert_eq!(node_tree.inner_html(body(&node_tree)), expected);
+}
+
+fn body(node_tree: &NodeTree) -> NodeRef {
+    let mut stack = vec![node_tree.root()];
+    while let Some(node_ref) = stack.pop() {
+        let node = node_tree.get_node(node_ref);
+        if node.as_element().map(|element| &element.name.local) == Some(&local_name!("body")) {
+            return node_ref;
+        }
+        stack.extend(node_tree.children(node_ref));
+    }
+    panic!("no <body>")
+}
+
+#[test]
+fn test_component() {
+    let path = "test_data/test_component/source.html";
+    let source = fs::read_to_string(path).unwrap();
+    let expected = fs::read_to_string("test_data/test_component/result.html").unwrap();
+
+    let opts = ParseOpts {
+        source_name: Some(String::from(path)),
+        ..Default::default()
+    };
+    let mut node_tree = parse_html_bytes_with_options(opts, source.as_bytes());
+    Renderer::new().render(&mut node_tree).unwrap();
+    assert_eq!(node_tree.inner_html(body(&node_tree)), expected);
+}
+
+#[test]
+fn test_component_cycle() {
+    let source = r#"<kolo-import src="a.html" as="a-b"></kolo-import><a-b></a-b>"#;
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    let error = Renderer::with_options(RenderOpts {
+        load_template: Some(Box::new(|path| {
+            Ok(match path {
+                "a.html" => r#"<kolo-import src="b.html" as="b-a"></kolo-import><b-a></b-a>"#,
+                _ => "<kolo-import src=\"a.html\" as=\"a-b\"></kolo-import>\n<a-b></a-b>",
+            }
+            .into())
+        })),
+        ..Default::default()
+    })
+    .render(&mut node_tree)
+    .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "b.html:2:1: `<a-b>` is used inside itself: a.html → b.html → a.html"
+    );
+
+    let source = r#"<kolo-import src="self.html" as="x-self"></kolo-import><x-self></x-self>"#;
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    let error = Renderer::with_options(RenderOpts {
+        load_template: Some(Box::new(move |_| Ok(source.into()))),
+        ..Default::default()
+    })
+    .render(&mut node_tree)
+    .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "self.html:1:56: `<x-self>` is used inside itself: self.html → self.html"
+    );
+
+    // a component used twice down different paths is not a cycle
+    let source = concat!(
+        r#"<kolo-import src="a.html" as="x-a"></kolo-import>"#,
+        r#"<kolo-import src="b.html" as="x-b"></kolo-import><x-a></x-a><x-b></x-b>"#
+    );
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    Renderer::with_options(RenderOpts {
+        load_template: Some(Box::new(|path| {
+            Ok(match path {
+                "leaf.html" => "<i>leaf</i>",
+                _ => r#"<kolo-import src="leaf.html" as="x-leaf"></kolo-import><x-leaf></x-leaf>"#,
+            }
+            .into())
+        })),
+        ..Default::default()
+    })
+    .render(&mut node_tree)
+    .unwrap();
+    assert_eq!(
+        node_tree.to_html_string(),
+        "<html><head></head><body><i>leaf</i><i>leaf</i></body></html>"
+    );
+}
+
+#[test]
+fn test_loop() {
+    let source = r##"
+<kolo-list for="(key, value)" in="#{a: 1, b: 2}">
+  <kolo-list for="item" in="[value]">
+    <kolo-list for="_" in="record([key, item, loop.index, loop.parent.index0,
+                                   loop.parent.first, loop.parent.last, loop.parent.length])">
+    </kolo-list>
+  </kolo-list>
+</kolo-list>
+<kolo-list for="_" in="record(loop)"></kolo-list>
+"##;
+    let recorded = Rc::new(RefCell::new(Vec::new()));
+    let mut renderer = Renderer::new();
+    let record = recorded.clone();
+    renderer
+        .engine_mut()
+        .register_fn("record", move |value: rhai::Dynamic| {
+            record.borrow_mut().push(value.to_string());
+            rhai::Array::new()
+        });
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    let error = renderer.render(&mut node_tree).unwrap_err();
+    assert!(error
+        .message
+        .contains("`loop` is only defined inside a loop"));
+    assert_eq!(
+        *recorded.borrow(),
+        [
+            r#"["a", 1, 1, 0, true, false, 2]"#,
+            r#"["b", 2, 1, 1, false, true, 2]"#,
+        ]
+    );
+}
+
+/// The message of the error rendering `source` gives.
+fn render_error(source: &str) -> String {
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    Renderer::new()
+        .render(&mut node_tree)
+        .unwrap_err()
+        .to_string()
+}
+
+#[test]
+fn test_empty_list() {
+    let source = r##"<ul><kolo-list for="x" in="[]"><li>{{ x }}</li></kolo-list></ul>
+<p k-for="x in 0..0">{{ x }}</p><kolo-list for="(k, v)" in="#{}">{{ k }}</kolo-list>
+<kolo-list for="c" in="&quot;&quot;">{{ c }}<kolo-empty>none</kolo-empty></kolo-list>"##;
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    Renderer::new().render(&mut node_tree).unwrap();
+    assert_eq!(
+        node_tree.to_html_string(),
+        "<html><head></head><body><ul></ul>\nnone</body></html>"
+    );
+}
+
+#[test]
+fn test_loop_errors() {
+    assert_eq!(
+        render_error("<ul>\n  <kolo-list for=\"x\" in=\"5\">{{ x }}</kolo-list>\n</ul>"),
+        "2:26: `in` must be an array, map, range or string, not i64"
+    );
+    assert_eq!(
+        render_error(r#"<kolo-list for="(a, " in="[1]">x</kolo-list>"#),
+        "1:17: `for` must be a variable name or `(key, value)`, not `(a, `"
+    );
+    assert_eq!(
+        render_error(r#"<kolo-list for="1x" in="[1]">x</kolo-list>"#),
+        "1:17: `for` must be a variable name or `(key, value)`, not `1x`"
+    );
+    assert_eq!(
+        render_error(r#"<kolo-list for="x">x</kolo-list>"#),
+        "1:1: `<kolo-list>` needs an `in` attribute"
+    );
+    assert_eq!(
+        render_error(r#"<p k-for="x of [1]">x</p>"#),
+        "1:11: `k-for` must be `item in items`, `(item, index) in items` or `(key, value) in map`, \
+         not `x of [1]`"
+    );
+    assert_eq!(
+        render_error(r#"<p k-for="x in 5">x</p>"#),
+        "1:16: `k-for` must go through an array, map, range or string, not i64"
+    );
+}
+
+#[test]
+fn test_script_build() {
+    // all the scripts run before anything else, wherever they are
+    let source = r#"<p>{{ b }}</p><div><script build>let a = [1]; a.push(2);</script></div>
+<script build>fn twice(x) { x * 2 } let b = twice(a.len());</script><script>let c;</script>"#;
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    Renderer::new().render(&mut node_tree).unwrap();
+    assert_eq!(
+        node_tree.to_html_string(),
+        "<html><head></head><body><p>4</p><div></div>\n<script>let c;</script></body></html>"
+    );
+
+    assert_eq!(
+        render_error("<p>\n<script build>\n  let a = ;\n</script></p>"),
+        "3:11: Syntax error: Unexpected ';'"
+    );
+    assert_eq!(
+        render_error(r#"<script build>throw "no";</script>"#),
+        "1:15: Runtime error: no"
+    );
+    assert_eq!(
+        render_error("<script build>let a = 3;</script><p>{{ b }}</p>"),
+        "1:40: Variable not found: b"
+    );
+
+    // each component has a scope of its own
+    let render = |source: &str| {
+        let mut node_tree = parse_html_bytes(source.as_bytes());
+        Renderer::with_options(RenderOpts {
+            load_template: Some(Box::new(|_| {
+                Ok(b"<script build>let inner = 1;</script><b>{{ inner }}{{ outer }}</b>".to_vec())
+            })),
+            ..Default::default()
+        })
+        .render(&mut node_tree)
+        .unwrap_err()
+        .to_string()
+    };
+    assert_eq!(
+        render(
+            r#"<kolo-import src="c.html" as="my-c"></kolo-import>
+<script build>let outer = 2;</script><my-c></my-c>"#
+        ),
+        "c.html:1:55: Variable not found: outer"
+    );
+    assert_eq!(
+        render(
+            r#"<kolo-import src="c.html" as="my-c"></kolo-import>
+<script build>let outer = 2;</script>{{ inner }}"#
+        ),
+        "2:41: Variable not found: inner"
+    );
+}
+
+#[test]
+fn test_if_chain() {
+    let source = r#"<kolo-if condition="1 == 2">a</kolo-if>
+<kolo-else-if condition="false">b</kolo-else-if>|<kolo-if condition="false">c</kolo-if>
+<kolo-else>d</kolo-else>|<kolo-if condition="true">e</kolo-if><kolo-else>f</kolo-else>"#;
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    Renderer::new().render(&mut node_tree).unwrap();
+    assert_eq!(
+        node_tree.to_html_string(),
+        "<html><head></head><body>|d|e</body></html>"
+    );
+
+    assert_eq!(
+        render_error("<kolo-if>a</kolo-if>"),
+        "1:1: `<kolo-if>` needs a `condition` attribute"
+    );
+    assert_eq!(
+        render_error(r#"<kolo-if condition="1">a</kolo-if>"#),
+        "1:21: `condition` must be a boolean, not i64"
+    );
+    assert_eq!(
+        render_error("<p>x</p><kolo-else>b</kolo-else>"),
+        "1:9: `<kolo-else>` must follow a `<kolo-if>` or `<kolo-else-if>`"
+    );
+    assert_eq!(
+        render_error(r#"<kolo-else-if condition="true">b</kolo-else-if>"#),
+        "1:1: `<kolo-else-if>` must follow a `<kolo-if>` or `<kolo-else-if>`"
+    );
+    assert_eq!(
+        render_error(r#"<kolo-if condition="false">a</kolo-if><p></p><kolo-else>b</kolo-else>"#),
+        "1:46: `<kolo-else>` must follow a `<kolo-if>` or `<kolo-else-if>`"
+    );
+    assert_eq!(
+        render_error(
+            r#"<kolo-if condition="true">a</kolo-if><kolo-else>b</kolo-else><kolo-else>c</kolo-else>"#
+        ),
+        "1:62: `<kolo-else>` must follow a `<kolo-if>` or `<kolo-else-if>`"
+    );
+}
+
+#[test]
+fn test_interpolation() {
+    let source = r#"<p title="a {{ &quot;&lt;&amp;&gt;&quot; }}">{{ "&lt;b&gt;" }} {{{ "&lt;i&gt;x" }}}
+{{ () }}|{{ [1, 2] }}|{{ 1.5 }}|{{ true }}</p><p k-html="&quot;<b>x</b> &amp;&quot;">old</p>"#;
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    Renderer::new().render(&mut node_tree).unwrap();
+    assert_eq!(
+        node_tree.to_html_string(),
+        "<html><head></head><body><p title=\"a <&amp;>\">&lt;b&gt; <i>x</i>\n\
+         |[1, 2]|1.5|true</p><p><b>x</b> &amp;</p></body></html>"
+    );
+
+    assert_eq!(
+        render_error("<p>{{ 1 }}{{ 2</p>"),
+        "1:11: `{{` is never closed"
+    );
+    assert_eq!(
+        render_error(r#"<p title="{{ x">a</p>"#),
+        "1:11: `{{` is never closed"
+    );
+    assert_eq!(
+        render_error("<p>\n  {{ 1 + }}</p>"),
+        "2:9: Syntax error: Script is incomplete"
+    );
+    assert_eq!(
+        render_error("<p>{{ }}</p>"),
+        "1:7: Syntax error: Script is incomplete"
+    );
+    assert_eq!(
+        render_error(r#"<p title="{{ undefined_thing }}">a</p>"#),
+        "1:14: Variable not found: undefined_thing"
+    );
+    assert_eq!(
+        render_error(r#"<p title="{{{ 1 }}}">a</p>"#),
+        "1:15: `{{{ }}}` can only be used in text, attribute values are always escaped"
+    );
+    assert_eq!(
+        render_error(r#"<p k-html="1 +">old</p>"#),
+        "1:15: Syntax error: Script is incomplete"
+    );
+    assert_eq!(
+        render_error("<p k-html>old</p>"),
+        "1:4: Syntax error: Script is incomplete"
+    );
+}
+
+#[test]
+fn test_bindings() {
+    let source = r##"<input :disabled="true" :hidden="false" :title="()" :value="3">
+<p class="a b" :class="[&quot;b c&quot;, (), #{d: true, e: false}]" style="x: y"
+   :style="#{color: &quot;red&quot;, margin: ()}" title="a" :title="&quot;b&quot;">p</p>
+<p :class="#{on: 1 > 0}" :style="[&quot;a: b&quot;]">q</p><svg><a :xlink:href="&quot;#x&quot;"></a></svg>"##;
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    Renderer::new().render(&mut node_tree).unwrap();
+    assert_eq!(
+        node_tree.to_html_string(),
+        "<html><head></head><body><input disabled=\"\" value=\"3\">\n\
+         <p class=\"a b c d\" style=\"x: y; color: red\" title=\"b\">p</p>\n\
+         <p class=\"on\" style=\"a: b\">q</p><svg><a xlink:href=\"#x\"></a></svg></body></html>"
+    );
+
+    assert_eq!(
+        render_error(r#"<a :href="1 +">a</a>"#),
+        "1:14: Syntax error: Script is incomplete"
+    );
+    assert_eq!(
+        render_error(r#"<p :title="[1]">a</p>"#),
+        "1:12: `:title` can not be set to array"
+    );
+    assert_eq!(
+        render_error(r#"<p :class="[1]">a</p>"#),
+        "1:12: `:class` can not be set to i64"
+    );
+    assert_eq!(
+        render_error(r##"<p :class="#{a: 1}">a</p>"##),
+        "1:12: `:class` can not be set to i64"
+    );
+    assert_eq!(
+        render_error(r#"<p :style="5 + []">a</p>"#),
+        "1:14: Function not found: + (i64, array)"
+    );
+    assert_eq!(
+        render_error(r#"<p :="1">a</p>"#),
+        "1:7: `:` needs the name of the attribute to set after it"
+    );
+}
+
+#[test]
+fn test_directive_precedence() {
+    // `k-for`, then `k-let`, then `k-if`, then `k-show`, each seeing the ones before
+    let source = r#"<li k-for="n in 0..5" k-if="n % 2 == 0">{{ n }}</li>
+<p k-let="x = 3" k-if="x > 2" k-show="x > 5">{{ x }}</p><p k-let="y = 1" k-if="y > 2">no</p>
+<b k-for="n in 0..3" k-let="d = n * 2" k-if="d > 0" k-show="d > 2">{{ d }}</b>"#;
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    Renderer::new().render(&mut node_tree).unwrap();
+    assert_eq!(
+        node_tree.to_html_string(),
+        "<html><head></head><body><li>0</li><li>2</li><li>4</li>\n\
+         <p hidden=\"\">3</p>\n<b hidden=\"\">2</b><b>4</b></body></html>"
+    );
+}
+
+#[test]
+fn test_for_map() {
+    // rhai maps go through their keys in order, whatever order they were written in
+    let source = r##"<p k-for="(k, v) in #{b: 2, a: 1, c: 3}">{{ k }}={{ v }}</p>
+<i k-for="(item, i) in [&quot;x&quot;, &quot;y&quot;]">{{ i }}:{{ item }}</i>
+<kolo-list for="(k, v)" in="#{z: (), y: [1]}">{{ k }}={{ v }};</kolo-list>"##;
+    for _ in 0..2 {
+        let mut node_tree = parse_html_bytes(source.as_bytes());
+        Renderer::new().render(&mut node_tree).unwrap();
+        assert_eq!(
+            node_tree.to_html_string(),
+            "<html><head></head><body><p>a=1</p><p>b=2</p><p>c=3</p>\n\
+             <i>0:x</i><i>1:y</i>\ny=[1];z=;</body></html>"
+        );
+    }
+}
+
+#[test]
+fn test_show() {
+    let source = r#"<p k-show="false" style="color: red">a</p><p k-show="true">b</p>"#;
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    Renderer::with_options(RenderOpts {
+        hide_style: Some(String::from("display: none")),
+        ..Default::default()
+    })
+    .render(&mut node_tree)
+    .unwrap();
+    assert_eq!(
+        node_tree.to_html_string(),
+        "<html><head></head><body><p style=\"color: red; display: none\">a</p><p>b</p></body></html>"
+    );
+}
+
+#[test]
+fn test_switch_lint() {
+    let source = r#"<kolo-switch on="2">
+  <kolo-case value="1 | 2">a</kolo-case>
+  <kolo-case value="2 | 3">b</kolo-case>
+  <kolo-case value="1">c</kolo-case>
+  <kolo-default>d</kolo-default>
+  <kolo-case value="4">e</kolo-case>
+</kolo-switch>"#;
+    let warnings = Rc::new(RefCell::new(Vec::new()));
+    let on_warning = warnings.clone();
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    Renderer::with_options(RenderOpts {
+        on_warning: Some(Box::new(move |warning| {
+            on_warning.borrow_mut().push(warning.to_string())
+        })),
+        ..Default::default()
+    })
+    .render(&mut node_tree)
+    .unwrap();
+    assert_eq!(
+        *warnings.borrow(),
+        [
+            "3:21: `2` is already handled earlier in the `<kolo-switch>`",
+            "4:21: `<kolo-case>` is never used, its values are all handled by earlier cases",
+            "6:3: `<kolo-case>` after the `<kolo-default>` is never used",
+        ]
+    );
+    assert_eq!(
+        node_tree.to_html_string(),
+        "<html><head></head><body>a</body></html>"
+    );
+}
+
+#[test]
+fn test_switch_guards() {
+    let render = |source: &str| {
+        let warnings = Rc::new(RefCell::new(Vec::new()));
+        let on_warning = warnings.clone();
+        let mut node_tree = parse_html_bytes(source.as_bytes());
+        Renderer::with_options(RenderOpts {
+            on_warning: Some(Box::new(move |warning| {
+                on_warning.borrow_mut().push(warning.to_string())
+            })),
+            ..Default::default()
+        })
+        .render(&mut node_tree)
+        .unwrap();
+        let warnings = warnings.borrow().clone();
+        (node_tree.to_html_string(), warnings)
+    };
+
+    // ` if ` in a string is not a guard, so the same value again is never used
+    let (html, warnings) = render(
+        r#"<kolo-switch on="&quot;go if ready&quot;">
+  <kolo-case value="&quot;go if ready&quot;">a</kolo-case>
+  <kolo-case value="&quot;go if ready&quot;">b</kolo-case>
+</kolo-switch>"#,
+    );
+    assert_eq!(html, "<html><head></head><body>a</body></html>");
+    assert_eq!(
+        warnings,
+        ["3:21: `<kolo-case>` is never used, its values are all handled by earlier cases"]
+    );
+
+    // a guard can be on a line of its own, and makes the value usable again
+    let (html, warnings) = render(
+        "<kolo-switch on=\"2\">\n  <kolo-case value=\"2\n    if false\">a</kolo-case>\n  \
+         <kolo-case value=\"1 | 2\tif true\">b</kolo-case>\n  <kolo-case value=\"2\">c</kolo-case>\n\
+         </kolo-switch>",
+    );
+    assert_eq!(html, "<html><head></head><body>b</body></html>");
+    assert!(warnings.is_empty(), "{:?}", warnings);
+}
+
+#[test]
+fn test_let_unused() {
+    let source = r#"<ul>
+  <li k-for="n in 0..3" k-let="half = n / 2">
+    <kolo-let name="twice" value="n * 2"><kolo-if condition="n == 1">{{ twice }}</kolo-if></kolo-let>
+  </li>
+</ul>"#;
+    let warnings = Rc::new(RefCell::new(Vec::new()));
+    let on_warning = warnings.clone();
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    Renderer::with_options(RenderOpts {
+        on_warning: Some(Box::new(move |warning| {
+            on_warning.borrow_mut().push(warning.to_string())
+        })),
+        ..Default::default()
+    })
+    .render(&mut node_tree)
+    .unwrap();
+    // `twice` is only used in one of the copies, which is enough
+    assert_eq!(*warnings.borrow(), ["2:32: `half` is never used"]);
+}
+
+#[test]
+fn test_escape() {
+    let source = r##"<script build>let u = "javascript:alert(1)"; let q = "a b&c"; let s = "\"</" + "b>";</script>
+<a href="{{ u }}"></a><a href="/s?q={{ q }}"></a><a :href="safe(u)"></a>
+<p onclick="f('{{ s }}')" style="color: {{ q }}">{{ safe(s) }}</p>
+<p :onclick="s" :style="q"></p><p :onclick="safe(s)" :style="#{color: s, &quot;a;b&quot;: safe(&quot;url(x)&quot;)}"></p>
+<p :style="[&quot;top: 1px; left: 2px&quot;, safe(&quot;content: ';'&quot;)]"></p>"##;
+    let warnings = Rc::new(RefCell::new(Vec::new()));
+    let on_warning = warnings.clone();
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    Renderer::with_options(RenderOpts {
+        on_warning: Some(Box::new(move |warning| {
+            on_warning.borrow_mut().push(warning.to_string())
+        })),
+        ..Default::default()
+    })
+    .render(&mut node_tree)
+    .unwrap();
+    assert_eq!(
+        *warnings.borrow(),
+        ["2:13: `javascript:alert(1)` is not a safe URL, so it was replaced with `about:invalid#kolo-unsafe-url`"]
+    );
+    assert_eq!(
+        node_tree.to_html_string(),
+        "<html><head>\n</head><body><a href=\"about:invalid#kolo-unsafe-url\"></a><a href=\"/s?q=a%20b%26c\"></a><a href=\"javascript:alert(1)\"></a>\n\
+         <p onclick=\"f('\\&quot;\\u003c\\u002fb\\u003e')\" style=\"color: a b\\26 c\">\"</p>\n\
+         <p onclick=\"\\&quot;\\u003c\\u002fb\\u003e\" style=\"a b\\26 c\"></p>\
+         <p onclick=\"&quot;</b>\" style=\"a\\3b b: url(x); color: \\22 \\3c \\2f b\\3e\"></p>\n\
+         <p style=\"top: 1px\\3b  left\\3a  2px; content: ';'\"></p></body></html>"
+    );
+}
+
+#[test]
+fn test_escape_srcset() {
+    let source = r#"<script build>let bad = "javascript:alert(1)"; let ok = "b.png 2x";</script>
+<img srcset="{{ bad }}"><img :srcset="&quot;a.png 1x, &quot; + bad + &quot; 2x&quot;">
+<img srcset="a.png 1x, {{ ok }}"><link :imagesrcset="&quot;data:x 1x&quot;">
+<object archive="{{ bad }}" classid="{{ bad }}"></object>"#;
+    let warnings = Rc::new(RefCell::new(Vec::new()));
+    let on_warning = warnings.clone();
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    Renderer::with_options(RenderOpts {
+        on_warning: Some(Box::new(move |warning| {
+            on_warning.borrow_mut().push(warning.to_string())
+        })),
+        ..Default::default()
+    })
+    .render(&mut node_tree)
+    .unwrap();
+    assert_eq!(warnings.borrow().len(), 5, "{:?}", warnings.borrow());
+    assert_eq!(
+        node_tree.to_html_string(),
+        "<html><head>\n</head><body><img srcset=\"about:invalid#kolo-unsafe-url\">\
+         <img srcset=\"about:invalid#kolo-unsafe-url\">\n<img srcset=\"a.png 1x, b.png 2x\">\
+         <link imagesrcset=\"about:invalid#kolo-unsafe-url\">\n<object \
+         archive=\"about:invalid#kolo-unsafe-url\" classid=\"about:invalid#kolo-unsafe-url\">\
+         </object></body></html>"
+    );
+}
+
+#[test]
+fn test_slots() {
+    let component = r#"<header><slot name="title">Untitled</slot></header>
+<main><slot>Nothing here</slot></main>
+<footer><slot name="footer">No footer</slot></footer>"#;
+    let caller = r#"<my-card>
+  <template slot="title"><b>{{ title }}</b></template>
+  <p>{{ title }} is shown</p>
+  <p slot="aside">I am not</p>
+</my-card>"#;
+    let warnings = Rc::new(RefCell::new(Vec::new()));
+    let on_warning = warnings.clone();
+    let mut renderer = Renderer::with_options(RenderOpts {
+        on_warning: Some(Box::new(move |warning| {
+            on_warning.borrow_mut().push(warning.to_string())
+        })),
+        ..Default::default()
+    });
+    let mut caller_tree = parse_html_bytes(caller.as_bytes());
+    let mut scope = rhai::Scope::new();
+    scope.push("title", "Kolo");
+    renderer
+        .render_with_scope(&mut caller_tree, &mut scope)
+        .unwrap();
+    let mut component_tree = parse_html_bytes(component.as_bytes());
+    renderer.render(&mut component_tree).unwrap();
+
+    let mut stack = vec![caller_tree.root()];
+    let mut card = None;
+    while let Some(node_ref) = stack.pop() {
+        let node = caller_tree.get_node(node_ref);
+        if node.as_element().map(|element| &*element.name.local) == Some("my-card") {
+            card = Some(node_ref);
+        }
+        stack.extend(caller_tree.children(node_ref));
+    }
+    renderer.fill_slots(&mut component_tree, &caller_tree, card.unwrap());
+    assert_eq!(
+        *warnings.borrow(),
+        ["4:12: there is no `<slot name=\"aside\">` for this content, so it is never shown"]
+    );
+    assert_eq!(
+        component_tree.to_html_string(),
+        "<html><head></head><body><header><b>Kolo</b></header>\n\
+         <main>\n  \n  <p>Kolo is shown</p>\n  \n</main>\n\
+         <footer>No footer</footer></body></html>"
+    );
+}
+
+#[test]
+fn test_slot_fallback() {
+    let render = |content: &str| {
+        let source = format!(
+            r#"<kolo-import src="card.html" as="my-card"></kolo-import><my-card>{}</my-card>"#,
+            content
+        );
+        let warnings = Rc::new(RefCell::new(Vec::new()));
+        let on_warning = warnings.clone();
+        let mut node_tree = parse_html_bytes(source.as_bytes());
+        Renderer::with_options(RenderOpts {
+            load_template: Some(Box::new(|_| {
+                Ok(b"<header><slot name=\"title\">Untitled</slot></header>\
+                     <main><slot>Nothing here</slot></main>"
+                    .to_vec())
+            })),
+            on_warning: Some(Box::new(move |warning| {
+                on_warning.borrow_mut().push(warning.to_string())
+            })),
+            ..Default::default()
+        })
+        .render(&mut node_tree)
+        .unwrap();
+        let warnings = warnings.borrow().clone();
+        (node_tree.to_html_string(), warnings)
+    };
+    let page = |header: &str, main: &str| {
+        format!(
+            "<html><head></head><body><header>{}</header><main>{}</main></body></html>",
+            header, main
+        )
+    };
+
+    // nothing, or only whitespace, given for any slot
+    assert_eq!(render(""), (page("Untitled", "Nothing here"), vec![]));
+    assert_eq!(render("\n  "), (page("Untitled", "Nothing here"), vec![]));
+    // the named slot has no provider, but the default one does, and the other way around
+    assert_eq!(render("x"), (page("Untitled", "x"), vec![]));
+    assert_eq!(
+        render(r#"<b slot="title">T</b>"#),
+        (page("<b>T</b>", "Nothing here"), vec![])
+    );
+    // content for a slot the component does not have is not used for the others
+    assert_eq!(
+        render(r#"<b slot="other">T</b>"#),
+        (
+            page("Untitled", "Nothing here"),
+            vec![String::from(
+                "1:75: there is no `<slot name=\"other\">` for this content, so it is never shown"
+            )]
+        )
+    );
+}
+
+#[test]
+fn test_props() {
+    let render = |source: &str| {
+        let mut node_tree = parse_html_bytes(source.as_bytes());
+        Renderer::with_options(RenderOpts {
+            load_template: Some(Box::new(|_| {
+                Ok(r#"<script build>props(#{ title: "string", count: #{ type: "int", "default": 1 } })</script>
+<p>{{ title }}: {{ count + 1 }}</p>"#
+                    .into())
+            })),
+            ..Default::default()
+        })
+        .render(&mut node_tree)
+        .map(|()| node_tree.to_html_string())
+        .map_err(|error| error.to_string())
+    };
+    let import = r#"<kolo-import src="card.html" as="my-card"></kolo-import>"#;
+    assert_eq!(
+        render(&format!(
+            r#"{}<my-card title="a" count=" 2"></my-card>"#,
+            import
+        )),
+        Ok(String::from(
+            "<html><head></head><body>\n<p>a: 3</p></body></html>"
+        ))
+    );
+    assert_eq!(
+        render(&format!("{}\n<my-card tilte=\"a\"></my-card>", import)),
+        Err(String::from("2:17: `<my-card>` has no `tilte` prop"))
+    );
+    assert_eq!(
+        render(&format!("{}\n<my-card></my-card>", import)),
+        Err(String::from("2:1: `<my-card>` needs a `title` prop"))
+    );
+    assert_eq!(
+        render(&format!(
+            "{}\n<my-card title=\"a\" count=\"two\"></my-card>",
+            import
+        )),
+        Err(String::from("2:27: `count` must be an int, not `two`"))
+    );
+    assert_eq!(
+        render(&format!("{}\n<my-card :title=\"1\"></my-card>", import)),
+        Err(String::from("2:18: `:title` must be a string, not an int"))
+    );
+
+    let render = |tag: &str| {
+        let source = format!(
+            "<kolo-import src=\"p.html\" as=\"x-p\"></kolo-import>\n{}",
+            tag
+        );
+        let mut node_tree = parse_html_bytes(source.as_bytes());
+        Renderer::with_options(RenderOpts {
+            load_template: Some(Box::new(|_| {
+                Ok(r#"<script build>props(#{ label: "string", on: #{ type: "bool", "default": false },
+                                         n: #{ type: "int", "default": 7 } })</script>
+<p>{{ label }} {{ on }} {{ n }}</p>"#
+                    .into())
+            })),
+            ..Default::default()
+        })
+        .render(&mut node_tree)
+        .map(|()| node_tree.inner_html(body(&node_tree)))
+        .map_err(|error| error.to_string())
+    };
+    assert_eq!(
+        render(r#"<x-p label="a"></x-p>"#),
+        Ok(String::from("\n<p>a false 7</p>"))
+    );
+    assert_eq!(
+        render(r#"<x-p label="a" on :n="n + 1" k-let="n = 1"></x-p>"#),
+        Ok(String::from("\n<p>a true 2</p>"))
+    );
+    assert_eq!(
+        render(r#"<x-p on></x-p>"#),
+        Err(String::from("2:1: `<x-p>` needs a `label` prop"))
+    );
+    assert_eq!(
+        render(r#"<x-p on="yes" label="a"></x-p>"#),
+        Err(String::from("2:10: `on` must be a bool, not `yes`"))
+    );
+    assert_eq!(
+        render(r#"<x-p label="a" on="true" n="3.5"></x-p>"#),
+        Err(String::from("2:29: `n` must be an int, not `3.5`"))
+    );
+    assert_eq!(
+        render(r#"<x-p label="a" on="true" :n="3.0"></x-p>"#),
+        Err(String::from("2:30: `:n` must be an int, not a float"))
+    );
+}
+
+#[test]
+fn test_scoped_style_once() {
+    let source = r#"<kolo-import src="tag.html" as="my-tag"></kolo-import>
+<my-tag k-for="n in 0..2"></my-tag>"#;
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    Renderer::with_options(RenderOpts {
+        load_template: Some(Box::new(|_| {
+            Ok("<style scoped>b { color: red; }</style><b>tag</b>".into())
+        })),
+        ..Default::default()
+    })
+    .render(&mut node_tree)
+    .unwrap();
+    assert_eq!(
+        node_tree.inner_html(body(&node_tree)),
+        "<style>b[data-k-e1f26cba] { color: red; }</style>\
+         <b data-k-e1f26cba=\"\" data-k-e1f26cba-host=\"\">tag</b>\
+         <b data-k-e1f26cba=\"\" data-k-e1f26cba-host=\"\">tag</b>"
+    );
+}
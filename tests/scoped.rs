@@ -0,0 +1,95 @@
+use kolo::render::{RenderOpts, Renderer};
+use kolo::scoped::{is_scope_attribute, scope_stylesheet};
+use kolo::sink::parse_html_bytes;
+
+const ATTRIBUTE: &str = "data-k-0123abcd";
+
+fn scope(css: &str) -> String {
+    scope_stylesheet(css, ATTRIBUTE)
+}
+
+#[test]
+fn test_selector_lists() {
+    assert_eq!(
+        scope("a, .b > c,\n.d + e ~ f { color: red; }"),
+        "a[data-k-0123abcd], .b > c[data-k-0123abcd],\n\
+         .d + e ~ f[data-k-0123abcd] { color: red; }"
+    );
+    assert_eq!(
+        scope(".x:not(.y, .z) { content: \"}{\"; }"),
+        ".x:not(.y, .z)[data-k-0123abcd] { content: \"}{\"; }"
+    );
+}
+
+#[test]
+fn test_global() {
+    assert_eq!(
+        scope(":global(.dark) .title {}"),
+        ".dark .title[data-k-0123abcd] {}"
+    );
+    assert_eq!(scope(":global(body) {}"), "body {}");
+    assert_eq!(scope(":global(.a .b) {}"), ".a .b {}");
+}
+
+#[test]
+fn test_host() {
+    assert_eq!(scope(":host {}"), "[data-k-0123abcd-host] {}");
+    assert_eq!(scope(":host:hover {}"), "[data-k-0123abcd-host]:hover {}");
+    assert_eq!(
+        scope(":host(.on) span {}"),
+        ".on[data-k-0123abcd-host] span[data-k-0123abcd] {}"
+    );
+}
+
+#[test]
+fn test_at_rules() {
+    assert_eq!(
+        scope("@media (min-width: 10px) { .a { b: c } @supports (d: e) { f {} } }"),
+        "@media (min-width: 10px) { .a[data-k-0123abcd] { b: c } \
+         @supports (d: e) { f[data-k-0123abcd] {} } }"
+    );
+    let untouched = "@import url(\"x.css\");\n@keyframes spin { from { a: b } to { c: d } }\n\
+                     @font-face { font-family: x; }";
+    assert_eq!(scope(untouched), untouched);
+}
+
+#[test]
+fn test_pseudo_elements() {
+    assert_eq!(
+        scope("p::before, a:hover:after, q:first-line {}"),
+        "p[data-k-0123abcd]::before, a:hover[data-k-0123abcd]:after, \
+         q[data-k-0123abcd]:first-line {}"
+    );
+}
+
+#[test]
+fn test_user_data_k_attributes() {
+    assert_eq!(
+        scope("[data-k-id=\"1\"] p, p[data-k-x] {}"),
+        "[data-k-id=\"1\"] p[data-k-0123abcd], p[data-k-x][data-k-0123abcd] {}"
+    );
+    assert!(is_scope_attribute("data-k-0123abcd"));
+    assert!(is_scope_attribute("data-k-0123abcd-2"));
+    assert!(is_scope_attribute("data-k-0123abcd-2-host"));
+    assert!(!is_scope_attribute("data-k-id"));
+    assert!(!is_scope_attribute("data-k-0123abcd-x"));
+
+    // a prop that only starts like a scope attribute is still a prop
+    let source = r#"<kolo-import src="tag.html" as="my-tag"></kolo-import>
+<style scoped>b {}</style><my-tag data-k-id="7"></my-tag>"#;
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    Renderer::with_options(RenderOpts {
+        load_template: Some(Box::new(|_| {
+            Ok(
+                r#"<script build>props(#{ data_k_id: "int" })</script><b>{{ data_k_id + 1 }}</b>"#
+                    .into(),
+            )
+        })),
+        ..Default::default()
+    })
+    .render(&mut node_tree)
+    .unwrap();
+    let html = node_tree.to_html_string();
+    assert!(html.contains(">8</b>"), "{}", html);
+    assert!(!html.contains("data-k-id"), "{}", html);
+}
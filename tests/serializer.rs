@@ -0,0 +1,124 @@
+use encoding_rs::WINDOWS_1252;
+use html5ever::serialize::{serialize, SerializeOpts, TraversalScope};
+use kolo::serializer::SerializableNode;
+use kolo::sink::parse_html_bytes;
+use kolo::tree::{NodeRef, NodeTree};
+
+fn element(node_tree: &NodeTree, local: &str) -> NodeRef {
+    let mut stack = vec![node_tree.root()];
+    while let Some(node_ref) = stack.pop() {
+        let node = node_tree.get_node(node_ref);
+        if node
+            .as_element()
+            .is_some_and(|element| &*element.name.local == local)
+        {
+            return node_ref;
+        }
+        stack.extend(node_tree.children(node_ref));
+    }
+    panic!("no <{}>", local)
+}
+
+/// The serialized `<body>` of `source`, checking that it parses back to the same.
+fn body_html(source: &str) -> String {
+    let node_tree = parse_html_bytes(source.as_bytes());
+    let html = node_tree.inner_html(element(&node_tree, "body"));
+    let reparsed = parse_html_bytes(html.as_bytes());
+    assert_eq!(reparsed.inner_html(element(&reparsed, "body")), html);
+    html
+}
+
+#[test]
+fn test_escaping() {
+    assert_eq!(
+        body_html(
+            "<p title='a \"b\" &amp; <c> &#39;d&#39;'>a &lt; b &gt; c &amp; \"d\" 'e'&nbsp;</p>"
+        ),
+        "<p title=\"a &quot;b&quot; &amp; <c> 'd'\">a &lt; b &gt; c &amp; \"d\" 'e'&nbsp;</p>"
+    );
+}
+
+#[test]
+fn test_void_elements() {
+    assert_eq!(
+        body_html("<br><img src=a.png alt=''><input disabled><hr/><p>x</p>"),
+        "<br><img alt=\"\" src=\"a.png\"><input disabled=\"\"><hr><p>x</p>"
+    );
+}
+
+#[test]
+fn test_raw_text() {
+    let source = "<script>if (a < b && c > d) { s = \"</p>&amp;\"; }</script>\
+                  <style>a > b::after { content: \"&\" }</style>";
+    let node_tree = parse_html_bytes(source.as_bytes());
+    let head = element(&node_tree, "head");
+    assert_eq!(node_tree.inner_html(head), source);
+
+    // also as the children of the element being serialized
+    let script = element(&node_tree, "script");
+    assert_eq!(
+        node_tree.inner_html(script),
+        "if (a < b && c > d) { s = \"</p>&amp;\"; }"
+    );
+}
+
+#[test]
+fn test_leading_newline() {
+    assert_eq!(
+        body_html("<pre>\n\nx</pre><textarea>\n\ny</textarea><pre>\nz</pre><div>\n\nw</div>"),
+        "<pre>\n\nx</pre><textarea>\n\ny</textarea><pre>z</pre><div>\n\nw</div>"
+    );
+}
+
+#[test]
+fn test_inner_and_outer_html() {
+    let node_tree = parse_html_bytes(b"<ul id=list><li>a</li><li>b &amp; c</li></ul>");
+    let list = element(&node_tree, "ul");
+    assert_eq!(
+        node_tree.outer_html(list),
+        "<ul id=\"list\"><li>a</li><li>b &amp; c</li></ul>"
+    );
+    assert_eq!(node_tree.inner_html(list), "<li>a</li><li>b &amp; c</li>");
+    assert_eq!(
+        node_tree.to_html_string(),
+        "<html><head></head><body><ul id=\"list\"><li>a</li><li>b &amp; c</li></ul></body></html>"
+    );
+
+    // the contents of a `<template>` are its children when serialized
+    let node_tree = parse_html_bytes(b"<template><b>t</b></template>");
+    let template = element(&node_tree, "template");
+    assert_eq!(node_tree.inner_html(template), "<b>t</b>");
+    assert_eq!(
+        node_tree.outer_html(template),
+        "<template><b>t</b></template>"
+    );
+}
+
+#[test]
+fn test_with_charset() {
+    let source = "<meta charset=utf-8>\
+                  <meta http-equiv=content-type content='text/html; charset=utf-8'>\
+                  <meta name=charset content=utf-8><p>caf\u{e9}</p>";
+    let node_tree = parse_html_bytes(source.as_bytes());
+    let head = element(&node_tree, "head");
+
+    let mut html = Vec::new();
+    serialize(
+        &mut html,
+        &SerializableNode::new(&node_tree, head).with_charset(WINDOWS_1252),
+        SerializeOpts {
+            traversal_scope: TraversalScope::IncludeNode,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    // only charset declarations change, and the text is not encoded yet
+    assert_eq!(
+        String::from_utf8(html).unwrap(),
+        "<head><meta charset=\"windows-1252\"><meta content=\"text/html; charset=windows-1252\" \
+         http-equiv=\"content-type\"><meta content=\"utf-8\" name=\"charset\"></head>"
+    );
+    assert!(node_tree
+        .to_html_string()
+        .starts_with("<html><head><meta charset=\"utf-8\">"));
+}
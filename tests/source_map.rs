@@ -0,0 +1,109 @@
+use kolo::render::{RenderOpts, Renderer};
+use kolo::sink::{parse_html_bytes_with_options, ParseOpts};
+use std::fs;
+
+#[test]
+fn test_source_map() {
+    let source = fs::read_to_string("test_data/test_for/source.html").unwrap();
+    let node_tree = parse_html_bytes_with_options(
+        ParseOpts {
+            source_name: Some(String::from("source.html")),
+            ..Default::default()
+        },
+        source.as_bytes(),
+    );
+    let (html, source_map) = node_tree.to_html_string_with_source_map();
+
+    let generated_position = |needle: &str| {
+        let offset = html.find(needle).unwrap();
+        let line = html[..offset].matches('\n').count();
+        let column = offset - html[..offset].rfind('\n').map_or(0, |i| i + 1);
+        (line as u32, column as u32)
+    };
+    let original_position = |needle: &str| {
+        let offset = source.find(needle).unwrap();
+        let line = source[..offset].matches('\n').count();
+        let column = offset - source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        (line as u32, column as u32)
+    };
+
+    for needle in [
+        "<kolo-list",
+        "<div>I am repeated",
+        "I am conditional",
+        "</kolo-if>",
+    ] {
+        let (line, column) = generated_position(needle);
+        let (file, original_line, original_column) = source_map.lookup(line, column).unwrap();
+        assert_eq!(file.name.as_deref(), Some("source.html"));
+        assert_eq!((original_line, original_column), original_position(needle));
+    }
+    // the implied `<html>` was not in the source
+    assert!(source_map.lookup(0, 0).is_none());
+
+    let json = source_map.to_json(Some("index.html"));
+    assert!(json.starts_with(
+        "{\"version\":3,\"file\":\"index.html\",\"sources\":[\"source.html\"],\"sourcesContent\":["
+    ));
+}
+
+#[test]
+fn test_rendered_source_map() {
+    let source = r#"<kolo-import src="card.html" as="my-card"></kolo-import>
+<ul><kolo-list for="x" in="[1, 2]">
+  <li class="item">{{ x }}</li>
+</kolo-list></ul>
+<my-card></my-card>"#;
+    let card = "<section class=\"card\">\n  <h2>Card</h2>\n</section>";
+    let mut node_tree = parse_html_bytes_with_options(
+        ParseOpts {
+            source_name: Some(String::from("index.html")),
+            ..Default::default()
+        },
+        source.as_bytes(),
+    );
+    Renderer::with_options(RenderOpts {
+        load_template: Some(Box::new(move |path| {
+            assert_eq!(path, "card.html");
+            Ok(card.into())
+        })),
+        ..Default::default()
+    })
+    .render(&mut node_tree)
+    .unwrap();
+    let (html, source_map) = node_tree.to_html_string_with_source_map();
+
+    let lookup = |offset: usize| {
+        let line = html[..offset].matches('\n').count();
+        let column = offset - html[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let (file, line, column) = source_map.lookup(line as u32, column as u32).unwrap();
+        (file.name.clone().unwrap(), line, column)
+    };
+
+    // every item points back to the loop body
+    let items: Vec<_> = html.match_indices("<li").map(|(i, _)| i).collect();
+    assert_eq!(items.len(), 2, "{}", html);
+    for offset in items {
+        assert_eq!(lookup(offset), (String::from("index.html"), 2, 2));
+    }
+    assert_eq!(
+        lookup(html.find("<ul>").unwrap()),
+        (String::from("index.html"), 1, 0)
+    );
+
+    // and the component's nodes to the component file
+    assert_eq!(
+        lookup(html.find("<section").unwrap()),
+        (String::from("card.html"), 0, 0)
+    );
+    assert_eq!(
+        lookup(html.find("<h2>").unwrap()),
+        (String::from("card.html"), 1, 2)
+    );
+    let names: Vec<_> = source_map
+        .sources()
+        .iter()
+        .map(|file| file.name.as_deref().unwrap())
+        .collect();
+    assert_eq!(names, ["index.html", "card.html"]);
+}
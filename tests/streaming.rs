@@ -0,0 +1,118 @@
+use kolo::render::Renderer;
+use kolo::sink::{parse_html_bytes, parse_html_bytes_with_options, ParseOpts};
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// Remembers everything written to it, and how much had been when each write happened.
+#[derive(Clone, Default)]
+struct Recorder {
+    output: Rc<RefCell<Vec<u8>>>,
+    writes: Rc<RefCell<Vec<usize>>>,
+}
+
+impl Write for Recorder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut output = self.output.borrow_mut();
+        output.extend_from_slice(buf);
+        self.writes.borrow_mut().push(output.len());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn assert_streams_the_same(path: &str, source: &str) {
+    let parse = || {
+        let opts = ParseOpts {
+            source_name: Some(String::from(path)),
+            ..Default::default()
+        };
+        parse_html_bytes_with_options(opts, source.as_bytes())
+    };
+    let mut node_tree = parse();
+    Renderer::new().render(&mut node_tree).unwrap();
+    let expected = node_tree.to_html_string();
+
+    let mut node_tree = parse();
+    let streamed = Renderer::new()
+        .render_to(&mut node_tree, Vec::new())
+        .unwrap();
+    assert_eq!(String::from_utf8(streamed).unwrap(), expected, "{}", path);
+}
+
+#[test]
+fn test_render_to() {
+    for path in [
+        "test_data/test_for/source.html",
+        "test_data/test_component/source.html",
+    ] {
+        assert_streams_the_same(path, &fs::read_to_string(path).unwrap());
+    }
+    assert_streams_the_same(
+        "chain.html",
+        r#"<style scoped>p { color: red }</style>
+<kolo-let name="n" value="2"><kolo-if condition="n == 1">one</kolo-if>
+<kolo-else-if condition="n == 2"><p k-for="i in 0..n">{{ i }}</p></kolo-else-if>
+<kolo-else>many</kolo-else> <b :title="n">{{ n }}</b></kolo-let>
+<kolo-list for="x" in="[]"><kolo-empty>none</kolo-empty></kolo-list>"#,
+    );
+}
+
+/// Renders `source` into a [`Recorder`], where the items of its loop over `0..1000` each
+/// show `{{ written() }}`, and returns how much had been written when the last one was
+/// rendered.
+fn written_before_last(source: &str) -> usize {
+    let recorder = Recorder::default();
+    let mut renderer = Renderer::new();
+    let output = recorder.output.clone();
+    renderer
+        .engine_mut()
+        .register_fn("written", move || output.borrow().len() as i64);
+
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    renderer
+        .render_to(&mut node_tree, recorder.clone())
+        .unwrap();
+    assert!(node_tree.children(node_tree.root()).next().is_none());
+
+    let output = String::from_utf8(recorder.output.borrow().clone()).unwrap();
+    let (_, written) = output.rsplit_once("999: ").unwrap();
+    written.split(' ').next().unwrap().parse().unwrap()
+}
+
+#[test]
+fn test_render_to_flushes() {
+    let source = r#"<ul><kolo-list for="i" in="0..1000">
+  <li>{{ i }}: {{ written() }} bytes written so far</li>
+</kolo-list></ul>"#;
+    // the first items were written out while the last ones were still being rendered
+    assert!(written_before_last(source) > 0);
+}
+
+#[test]
+fn test_render_to_what_streams() {
+    let item = r#"<li k-for="i in 0..1000">{{ i }}: {{ written() }} bytes written so far</li>"#;
+    // loops stream inside plain elements
+    for wrapper in [
+        "<div><ul>{}</ul></div>",
+        r#"<ul k-show="true" :title="1">{}</ul>"#,
+    ] {
+        let source = wrapper.replace("{}", item);
+        assert!(written_before_last(&source) > 0, "{}", wrapper);
+    }
+    // but are rendered whole, before anything of them is written, inside everything else
+    for wrapper in [
+        r#"<kolo-list for="_" in="[1]"><ul>{}</ul></kolo-list>"#,
+        r#"<ul k-if="true">{}</ul>"#,
+        r#"<ul k-let="n = 1">{}</ul>"#,
+        r#"<kolo-let name="n" value="1"><ul>{}</ul></kolo-let>"#,
+        r#"<kolo-if condition="true"><ul>{}</ul></kolo-if>"#,
+    ] {
+        let source = wrapper.replace("{}", item);
+        assert_eq!(written_before_last(&source), 0, "{}", wrapper);
+    }
+}
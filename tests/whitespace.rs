@@ -0,0 +1,90 @@
+use kolo::render::{RenderOpts, Renderer};
+use kolo::sink::parse_html_bytes;
+use kolo::whitespace::WhitespacePolicy;
+
+const LIST: &str = r#"<ul>
+  <kolo-list for="x" in="[1, 2]">
+    <li>{{ x }}</li>
+  </kolo-list>
+</ul>
+<pre>
+  <kolo-if condition="true">
+    a   b
+  </kolo-if>
+</pre>"#;
+
+/// The rendered `<body>` of `source`.
+fn render(source: &str, whitespace: WhitespacePolicy) -> String {
+    let mut node_tree = parse_html_bytes(source.as_bytes());
+    Renderer::with_options(RenderOpts {
+        whitespace,
+        ..Default::default()
+    })
+    .render(&mut node_tree)
+    .unwrap();
+    let html = node_tree.to_html_string();
+    html.strip_prefix("<html><head></head><body>")
+        .and_then(|html| html.strip_suffix("</body></html>"))
+        .unwrap_or_else(|| panic!("{}", html))
+        .to_owned()
+}
+
+/// The `<pre>` in `LIST` keeps its text whatever the policy.
+const PRE: &str = "<pre>  \n    a   b\n  \n</pre>";
+
+#[test]
+fn test_preserve() {
+    assert_eq!(
+        render(LIST, WhitespacePolicy::Preserve),
+        format!(
+            "<ul>\n  \n    <li>1</li>\n  \n    <li>2</li>\n  \n</ul>\n{}",
+            PRE
+        )
+    );
+}
+
+#[test]
+fn test_trim() {
+    assert_eq!(
+        render(LIST, WhitespacePolicy::Trim),
+        format!("<ul>\n    <li>1</li>\n    <li>2</li>\n</ul>\n{}", PRE)
+    );
+    assert_eq!(WhitespacePolicy::default(), WhitespacePolicy::Trim);
+}
+
+#[test]
+fn test_collapse() {
+    assert_eq!(
+        render(LIST, WhitespacePolicy::Collapse),
+        format!("<ul>  <li>1</li>  <li>2</li>  </ul>\n{}", PRE)
+    );
+}
+
+#[test]
+fn test_tag_markers() {
+    let source = r#"<p>a
+  <kolo-if - condition="true" ->
+  x  </kolo-if - >
+ b<kolo-if condition="false">y</kolo-if ->
+ c</p><pre>  <kolo-if - condition="true">
+ z</kolo-if></pre>"#;
+    // markers apply whatever the policy, and inside `<pre>` too
+    assert_eq!(
+        render(source, WhitespacePolicy::Preserve),
+        "<p>ax\n bc</p><pre>\n\n z</pre>"
+    );
+    assert_eq!(
+        render(source, WhitespacePolicy::Collapse),
+        "<p>ax bc</p><pre>\n\n z</pre>"
+    );
+}
+
+#[test]
+fn test_interpolation_markers() {
+    let source = "<p>a   {{- 1 -}}   b {{ -1 }} c {{{- \"&lt;i&gt;x&lt;/i&gt;\" }}}  d\n\
+                  {{ 2 -}}\n e</p><pre>  {{- 3 }}</pre>";
+    assert_eq!(
+        render(source, WhitespacePolicy::Preserve),
+        "<p>a1b -1 c<i>x</i>  d\n2e</p><pre>3</pre>"
+    );
+}
@@ -0,0 +1,125 @@
+use encoding_rs::{UTF_16LE, UTF_8, WINDOWS_1252};
+use html5ever::{namespace_url, ns};
+use kolo::encoding::{sniff_xml, Confidence};
+use kolo::sink::parse_xml_bytes;
+use kolo::tree::{NodeData, NodeRef, NodeTree};
+
+const SVG: &[u8] = br##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink"><use xlink:href="#a"/><text xml:lang="en">x &lt; y</text></svg>"##;
+
+const RSS: &[u8] = br#"<?xml version="1.0" encoding="UTF-8"?>
+<?xml-stylesheet type="text/xsl" href="feed.xsl"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"><channel><atom:link href="https://example.com/feed" rel="self"/><title>News &amp; notes</title></channel></rss>"#;
+
+fn element(node_tree: &NodeTree, local: &str) -> NodeRef {
+    let mut stack = vec![node_tree.root()];
+    while let Some(node_ref) = stack.pop() {
+        let node = node_tree.get_node(node_ref);
+        if node
+            .as_element()
+            .is_some_and(|element| &*element.name.local == local)
+        {
+            return node_ref;
+        }
+        stack.extend(node_tree.children(node_ref));
+    }
+    panic!("no <{}>", local)
+}
+
+#[test]
+fn test_xml_namespaces() {
+    let node_tree = parse_xml_bytes(SVG);
+    let svg = node_tree
+        .get_node(element(&node_tree, "svg"))
+        .as_element()
+        .unwrap();
+    assert_eq!(svg.name.ns, ns!(svg));
+    assert_eq!(svg.name.prefix, None);
+
+    let use_ = node_tree
+        .get_node(element(&node_tree, "use"))
+        .as_element()
+        .unwrap();
+    assert_eq!(use_.name.ns, ns!(svg));
+    let (name, attribute) = use_.attributes.map.iter().next().unwrap();
+    assert_eq!((&name.ns, &*name.local), (&ns!(xlink), "href"));
+    assert_eq!(attribute.prefix.as_deref(), Some("xlink"));
+    assert_eq!(&*attribute.value, "#a");
+
+    let node_tree = parse_xml_bytes(RSS);
+    let rss = node_tree
+        .get_node(element(&node_tree, "rss"))
+        .as_element()
+        .unwrap();
+    assert_eq!(rss.name.ns, ns!());
+    let link = node_tree
+        .get_node(element(&node_tree, "link"))
+        .as_element()
+        .unwrap();
+    assert_eq!(&*link.name.ns, "http://www.w3.org/2005/Atom");
+    assert_eq!(link.name.prefix.as_deref(), Some("atom"));
+}
+
+#[test]
+fn test_xml_processing_instructions() {
+    let node_tree = parse_xml_bytes(RSS);
+    let instructions: Vec<(String, String)> = node_tree
+        .children(node_tree.root())
+        .filter_map(|child_ref| match node_tree.get_node(child_ref).data() {
+            NodeData::ProcessingInstruction(instruction) => Some(instruction.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        instructions,
+        [
+            ("xml".into(), "version=\"1.0\" encoding=\"UTF-8\"".into()),
+            (
+                "xml-stylesheet".into(),
+                "type=\"text/xsl\" href=\"feed.xsl\"".into()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_xml_roundtrip() {
+    let cases: [(&[u8], &str); 2] = [
+        (
+            SVG,
+            r##"<svg xmlns="http://www.w3.org/2000/svg"><use xmlns:xlink="http://www.w3.org/1999/xlink" xlink:href="#a"></use><text xml:lang="en">x &lt; y</text></svg>"##,
+        ),
+        (
+            RSS,
+            r#"<?xml version="1.0" encoding="UTF-8"?><?xml-stylesheet type="text/xsl" href="feed.xsl"?><rss version="2.0"><channel><atom:link xmlns:atom="http://www.w3.org/2005/Atom" href="https://example.com/feed" rel="self"></atom:link><title>News &amp; notes</title></channel></rss>"#,
+        ),
+    ];
+    for (source, expected) in cases {
+        let xml = parse_xml_bytes(source).to_xml_string();
+        assert_eq!(xml, expected);
+        let reparsed = parse_xml_bytes(xml.as_bytes());
+        assert_eq!(reparsed.to_xml_string(), xml);
+    }
+}
+
+#[test]
+fn test_xml_encoding() {
+    let latin1 = b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><p>caf\xE9</p>";
+    assert_eq!(sniff_xml(latin1), (WINDOWS_1252, Confidence::Tentative));
+    let node_tree = parse_xml_bytes(latin1);
+    assert!(node_tree.to_xml_string().ends_with("<p>caf\u{e9}</p>"));
+
+    // without a BOM, a declaration that could be read is not in UTF-16
+    let utf16 = "<?xml version=\"1.0\" encoding=\"UTF-16\"?><p>caf\u{e9}</p>";
+    assert_eq!(sniff_xml(utf16.as_bytes()), (UTF_8, Confidence::Tentative));
+    let node_tree = parse_xml_bytes(utf16.as_bytes());
+    assert!(node_tree.to_xml_string().ends_with("<p>caf\u{e9}</p>"));
+
+    let mut bom = vec![0xFF, 0xFE];
+    for unit in utf16.encode_utf16() {
+        bom.extend_from_slice(&unit.to_le_bytes());
+    }
+    assert_eq!(sniff_xml(&bom), (UTF_16LE, Confidence::Certain));
+    assert!(parse_xml_bytes(&bom)
+        .to_xml_string()
+        .ends_with("<p>caf\u{e9}</p>"));
+}